@@ -1,30 +1,25 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-use std::cell::RefCell;
-use std::rc::Rc;
-
 use exa::vm::exa::Exa;
 use exa::vm::register::Register;
 use exa::vm::{Host, Permissions, VM};
 
 pub fn copy_register_loop(c: &mut Criterion) {
-    let h1 = Host::new_shared(String::from("start"), 4);
+    let mut h1 = Host::new(String::from("start"), 4);
     let r = Register::new(Permissions::ReadWrite, 100);
-    h1.borrow_mut()
-        .add_register(String::from("#REG"), Rc::new(RefCell::new(r)));
+    h1.add_register(String::from("#REG"), r);
 
-    let h2 = Host::new_shared(String::from("end"), 4);
+    let h2 = Host::new(String::from("end"), 4);
 
     let mut vm = VM::new();
 
-    vm.add_host(h1.clone());
-    vm.add_host(h2.clone());
+    let h1 = vm.add_host(h1);
+    let h2 = vm.add_host(h2);
 
-    vm.add_link(800, h1.clone(), h2.clone());
-    vm.add_link(-1, h2.clone(), h1.clone());
+    vm.add_link(800, h1, h2);
+    vm.add_link(-1, h2, h1);
 
-    let name = "start";
-    let host = vm.hosts.get(name).unwrap().clone();
+    let host = h1;
     let name = String::from("x0");
 
     Exa::spawn(&mut vm, host, name, false, "mark a\n copy 1 x\n jump a\n").unwrap();
@@ -35,23 +30,21 @@ pub fn copy_register_loop(c: &mut Criterion) {
 }
 
 pub fn rand_loop(c: &mut Criterion) {
-    let h1 = Host::new_shared(String::from("start"), 4);
+    let mut h1 = Host::new(String::from("start"), 4);
     let r = Register::new(Permissions::ReadWrite, 100);
-    h1.borrow_mut()
-        .add_register(String::from("#REG"), Rc::new(RefCell::new(r)));
+    h1.add_register(String::from("#REG"), r);
 
-    let h2 = Host::new_shared(String::from("end"), 4);
+    let h2 = Host::new(String::from("end"), 4);
 
     let mut vm = VM::new();
 
-    vm.add_host(h1.clone());
-    vm.add_host(h2.clone());
+    let h1 = vm.add_host(h1);
+    let h2 = vm.add_host(h2);
 
-    vm.add_link(800, h1.clone(), h2.clone());
-    vm.add_link(-1, h2.clone(), h1.clone());
+    vm.add_link(800, h1, h2);
+    vm.add_link(-1, h2, h1);
 
-    let name = "start";
-    let host = vm.hosts.get(name).unwrap().clone();
+    let host = h1;
     let name = String::from("x0");
 
     Exa::spawn(
@@ -67,23 +60,21 @@ pub fn rand_loop(c: &mut Criterion) {
 }
 
 pub fn rand_gx_with_sprite_defined(c: &mut Criterion) {
-    let h1 = Host::new_shared(String::from("start"), 4);
+    let mut h1 = Host::new(String::from("start"), 4);
     let r = Register::new(Permissions::ReadWrite, 100);
-    h1.borrow_mut()
-        .add_register(String::from("#REG"), Rc::new(RefCell::new(r)));
+    h1.add_register(String::from("#REG"), r);
 
-    let h2 = Host::new_shared(String::from("end"), 4);
+    let h2 = Host::new(String::from("end"), 4);
 
     let mut vm = VM::new();
 
-    vm.add_host(h1.clone());
-    vm.add_host(h2.clone());
+    let h1 = vm.add_host(h1);
+    let h2 = vm.add_host(h2);
 
-    vm.add_link(800, h1.clone(), h2.clone());
-    vm.add_link(-1, h2.clone(), h1.clone());
+    vm.add_link(800, h1, h2);
+    vm.add_link(-1, h2, h1);
 
-    let name = "start";
-    let host = vm.hosts.get(name).unwrap().clone();
+    let host = h1;
     let name = String::from("x0");
 
     Exa::spawn(
@@ -102,10 +93,45 @@ pub fn rand_gx_with_sprite_defined(c: &mut Criterion) {
     });
 }
 
+// 64 EXAs with builtin sprites jumping around a 60x50 corner of the
+// screen, so most of them overlap someone. Their loops are staggered so
+// that some EXA reads CI on every cycle.
+fn crowded_vm() -> VM {
+    let mut vm = VM::new_redshift();
+    let arena = vm.add_host(Host::new(String::from("arena"), 64));
+
+    for i in 0..64 {
+        let script = format!(
+            "copy {} co\n copy {} gp\n{} mark a\n rand 0 50 gx\n rand 0 40 gy\n addi ci 0 x\n jump a\n",
+            i,
+            301 + (i % 39),
+            " noop\n".repeat(i % 4),
+        );
+        Exa::spawn(&mut vm, arena, format!("x{}", i), true, &script).unwrap();
+    }
+
+    for _ in 0..10 {
+        vm.run_cycle();
+    }
+    vm
+}
+
+pub fn collision_64_exas(c: &mut Criterion) {
+    let mut vm = crowded_vm();
+    c.bench_function("collision 64 exas", |b| b.iter(|| vm.run_cycle()));
+}
+
+pub fn render_64_exas(c: &mut Criterion) {
+    let mut vm = crowded_vm();
+    c.bench_function("render 64 exas", |b| b.iter(|| black_box(vm.render()[0])));
+}
+
 criterion_group!(
     benches,
     copy_register_loop,
     rand_loop,
-    rand_gx_with_sprite_defined
+    rand_gx_with_sprite_defined,
+    collision_64_exas,
+    render_64_exas
 );
 criterion_main!(benches);
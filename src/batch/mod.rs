@@ -0,0 +1,364 @@
+//! Runs many headless VMs at once, spread over every CPU core. Each run
+//! starts from a fresh copy of the same Redshift image or network, with
+//! its own seed and input script, and goes until its stop condition holds.
+//! Results come back in the order runs were given, and a run with the
+//! same seed and inputs always ends the same way, however many threads
+//! are used.
+//!
+//! Input scripts are plain text, one input per line, giving the cycle to
+//! feed the input before and the input itself:
+//!
+//! ```text
+//! # hold right for a while, then press start
+//! 0 right
+//! 300 reset
+//! 300 start
+//! ```
+//!
+//! Inputs are up, down, left, right, start, x, y and z for buttons, reset
+//! to release every button, and unfreeze to wake EXAs blocked on WAIT, as
+//! the frontend does at the end of each frame.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use itertools::Itertools;
+
+use super::image::load_image;
+use super::network::Network;
+use super::vm::redshift::RedshiftButton;
+use super::vm::rewind::Input;
+use super::vm::terminated::Terminated;
+use super::vm::VM;
+
+/// What every run in a batch starts from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    /// Path to a Redshift image.
+    Image(String),
+    Network(Network),
+}
+
+impl Source {
+    /// Pick the kind of source from the file extension: PNGs are Redshift
+    /// images and anything else is a network description.
+    pub fn from_path(path: &str) -> Result<Source, Box<dyn Error>> {
+        if path.to_ascii_lowercase().ends_with(".png") {
+            return Ok(Source::Image(path.to_string()));
+        }
+        let text = fs::read_to_string(path)?;
+        Ok(Source::Network(Network::from_toml(&text)?))
+    }
+
+    fn load(&self) -> Result<VM, Box<dyn Error>> {
+        match self {
+            Source::Image(path) => load_image(path.clone()),
+            Source::Network(network) => network.build(),
+        }
+    }
+}
+
+/// When a run ends. Conditions are checked between cycles, before any
+/// inputs for the next cycle are given.
+#[derive(Clone)]
+pub enum Stop {
+    /// Run for exactly this many cycles.
+    Cycles(u32),
+    /// Run until every EXA has died, for at most max_cycles.
+    AllDead { max_cycles: u32 },
+    /// Run until the predicate holds, for at most max_cycles.
+    Predicate {
+        max_cycles: u32,
+        until: Arc<dyn Fn(&VM) -> bool + Send + Sync>,
+    },
+}
+
+impl Stop {
+    fn max_cycles(&self) -> u32 {
+        match self {
+            Stop::Cycles(cycles) => *cycles,
+            Stop::AllDead { max_cycles } => *max_cycles,
+            Stop::Predicate { max_cycles, .. } => *max_cycles,
+        }
+    }
+
+    fn holds(&self, vm: &VM, cycles: u32) -> bool {
+        match self {
+            Stop::Cycles(max_cycles) => cycles >= *max_cycles,
+            Stop::AllDead { .. } => vm.exas.iter().all(|e| e.is_fatal()),
+            Stop::Predicate { until, .. } => until(vm),
+        }
+    }
+}
+
+impl fmt::Debug for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Cycles(cycles) => write!(f, "Cycles({})", cycles),
+            Stop::AllDead { max_cycles } => write!(f, "AllDead {{ max_cycles: {} }}", max_cycles),
+            Stop::Predicate { max_cycles, .. } => {
+                write!(f, "Predicate {{ max_cycles: {} }}", max_cycles)
+            }
+        }
+    }
+}
+
+/// A single run: the seed the VM's randomness starts from, the inputs to
+/// give it along the way, and when to stop.
+#[derive(Clone, Debug)]
+pub struct Run {
+    pub seed: u64,
+    /// Inputs with the cycle, counted from the start of the run, to give
+    /// them before. Inputs for the same cycle are given in order.
+    pub inputs: Vec<(u32, Input)>,
+    pub stop: Stop,
+}
+
+impl Run {
+    pub fn new(seed: u64, stop: Stop) -> Run {
+        Run {
+            seed,
+            inputs: vec![],
+            stop,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunResult {
+    pub index: usize,
+    pub seed: u64,
+    pub cycles: u32,
+    /// Whether the stop condition held. Unset if the run was cut off by
+    /// its cycle limit first.
+    pub finished: bool,
+    /// Save state of the VM when the run stopped.
+    pub state: Vec<u8>,
+    /// Hash of the rendered screen, for Redshift VMs.
+    pub framebuffer_hash: Option<u64>,
+    /// Every EXA that died during the run, in the order they died.
+    pub terminated: Vec<Terminated>,
+}
+
+impl RunResult {
+    /// Hash of the final save state, for telling runs apart at a glance.
+    pub fn state_hash(&self) -> u64 {
+        fnv1a(self.state.iter().copied())
+    }
+}
+
+impl fmt::Display for RunResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "run {} (seed {}): {} cycles, state {:016x}",
+            self.index,
+            self.seed,
+            self.cycles,
+            self.state_hash()
+        )?;
+        if let Some(hash) = self.framebuffer_hash {
+            write!(f, ", framebuffer {:016x}", hash)?;
+        }
+        if !self.finished {
+            write!(f, " (timed out)")?;
+        }
+        for t in self.terminated.iter() {
+            write!(f, "\n  {} died on cycle {}: {:?}", t.name, t.cycle, t.cause)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs batches of runs from one source, in parallel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+    source: Source,
+    redshift: bool,
+    /// How many runs go at once. Defaults to the number of CPU cores.
+    pub threads: usize,
+}
+
+impl Batch {
+    /// Loads the source once up front, so that a bad image or network is
+    /// reported here rather than by every run.
+    pub fn new(source: Source) -> Result<Batch, Box<dyn Error>> {
+        let vm = source.load()?;
+        Ok(Batch {
+            source,
+            redshift: vm.redshift.is_some(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        })
+    }
+
+    /// Play every run to its end, returning results in the same order.
+    pub fn run(&self, runs: &[Run]) -> Result<Vec<RunResult>, Box<dyn Error>> {
+        if !self.redshift {
+            let mut inputs = runs.iter().flat_map(|r| r.inputs.iter());
+            if inputs.any(|(_, i)| *i != Input::UnfreezeWaiters) {
+                return Err("button inputs need a Redshift image".into());
+            }
+        }
+
+        let next = AtomicUsize::new(0);
+        let threads = self.threads.clamp(1, runs.len().max(1));
+        let results: Vec<(usize, Result<RunResult, String>)> = thread::scope(|s| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut done = vec![];
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            match runs.get(index) {
+                                Some(run) => done.push((index, self.run_one(index, run))),
+                                None => return done,
+                            }
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().expect("batch worker panicked"))
+                .collect()
+        });
+
+        let mut ordered = vec![];
+        for (_, result) in results.into_iter().sorted_by_key(|(i, _)| *i) {
+            ordered.push(result?);
+        }
+        Ok(ordered)
+    }
+
+    // Errors are Strings here, since they have to cross back from the
+    // worker threads.
+    fn run_one(&self, index: usize, run: &Run) -> Result<RunResult, String> {
+        let mut vm = self.source.load().map_err(|e| e.to_string())?;
+        vm.reseed(run.seed);
+        vm.set_terminated_capacity(usize::MAX);
+
+        let start = vm.cycle;
+        let max_cycles = run.stop.max_cycles();
+        let mut inputs = run.inputs.iter().sorted_by_key(|(c, _)| *c).peekable();
+        let finished = loop {
+            let cycles = vm.cycle - start;
+            if run.stop.holds(&vm, cycles) {
+                break true;
+            }
+            if cycles >= max_cycles {
+                break false;
+            }
+            while let Some((_, input)) = inputs.next_if(|(c, _)| *c <= cycles) {
+                vm.apply_input(input);
+            }
+
+            // Only a fixed cycle count can be known to hold ahead of time,
+            // so anything else is checked after every cycle.
+            let until = match run.stop {
+                Stop::Cycles(_) => inputs.peek().map_or(max_cycles, |(c, _)| *c),
+                _ => cycles + 1,
+            };
+            vm.run_cycles(until.min(max_cycles).saturating_sub(cycles).max(1) as usize);
+        };
+
+        // EXAs that died on the last cycle haven't been cleaned up yet
+        let dying = vm
+            .exas
+            .iter()
+            .filter_map(|e| Terminated::from_exa(e, &vm.hosts));
+        let terminated = vm.terminated().cloned().chain(dying).collect();
+        let framebuffer_hash = vm
+            .redshift
+            .is_some()
+            .then(|| fnv1a(vm.render().iter().map(|p| *p as u8)));
+        Ok(RunResult {
+            index,
+            seed: run.seed,
+            cycles: vm.cycle - start,
+            finished,
+            state: vm.save_state(),
+            framebuffer_hash,
+            terminated,
+        })
+    }
+}
+
+/// Parse an input script, as described at the top of this module. Blank
+/// lines and anything after a # are ignored.
+pub fn parse_inputs(text: &str) -> Result<Vec<(u32, Input)>, Box<dyn Error>> {
+    let mut inputs = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (cycle, name) = match line.split_whitespace().collect_tuple() {
+            Some(parts) => parts,
+            None => {
+                return Err(format!("line {}: expected a cycle and an input", number + 1).into())
+            }
+        };
+        let cycle: u32 = cycle
+            .parse()
+            .map_err(|_| format!("line {}: bad cycle {}", number + 1, cycle))?;
+        let input = match name.to_ascii_lowercase().as_str() {
+            "up" => Input::Button(RedshiftButton::Up),
+            "down" => Input::Button(RedshiftButton::Down),
+            "left" => Input::Button(RedshiftButton::Left),
+            "right" => Input::Button(RedshiftButton::Right),
+            "start" => Input::Button(RedshiftButton::Start),
+            "x" => Input::Button(RedshiftButton::X),
+            "y" => Input::Button(RedshiftButton::Y),
+            "z" => Input::Button(RedshiftButton::Z),
+            "reset" => Input::ResetInputs,
+            "unfreeze" => Input::UnfreezeWaiters,
+            _ => return Err(format!("line {}: unknown input {}", number + 1, name).into()),
+        };
+        inputs.push((cycle, input));
+    }
+    Ok(inputs)
+}
+
+// FNV-1a, which unlike the standard library's hashers is the same on
+// every platform and Rust version, so hashes can be kept around and
+// compared between builds.
+fn fnv1a(bytes: impl Iterator<Item = u8>) -> u64 {
+    bytes.fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_inputs() {
+        let inputs = parse_inputs("# comment\n0 right\n\n 30 RESET # let go\n30 unfreeze\n")
+            .expect("failed to parse inputs");
+        assert_eq!(
+            inputs,
+            vec![
+                (0, Input::Button(RedshiftButton::Right)),
+                (30, Input::ResetInputs),
+                (30, Input::UnfreezeWaiters),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_inputs_errors() {
+        for (text, error) in [
+            ("right\n", "line 1: expected a cycle and an input"),
+            ("0 right\n-1 left\n", "line 2: bad cycle -1"),
+            ("5 jump\n", "line 1: unknown input jump"),
+        ] {
+            let got = parse_inputs(text).expect_err("inputs should not parse");
+            assert_eq!(got.to_string(), error);
+        }
+    }
+}
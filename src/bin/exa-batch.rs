@@ -0,0 +1,107 @@
+//! Runs a Redshift image or network many times over in parallel and
+//! prints how each run ended. See src/batch/mod.rs for the input script
+//! format.
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::process;
+
+use exa::batch::{parse_inputs, Batch, Run, Source, Stop};
+
+const USAGE: &str = "usage: exa-batch <image.png | network.toml> [options]
+
+options:
+  --runs N        how many runs to do (default 1)
+  --seed S        seed of the first run; run i is seeded with S + i (default 0)
+  --cycles N      stop each run after N cycles (default 10000)
+  --until-dead    stop each run once every EXA has died, or after --cycles
+  --inputs FILE   input script to give every run
+  --threads N     how many runs go at once (default: one per CPU core)";
+
+struct Args {
+    path: String,
+    runs: usize,
+    seed: u64,
+    cycles: u32,
+    until_dead: bool,
+    inputs: Option<String>,
+    threads: Option<usize>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, Box<dyn Error>> {
+    let mut parsed = Args {
+        path: String::new(),
+        runs: 1,
+        seed: 0,
+        cycles: 10000,
+        until_dead: false,
+        inputs: None,
+        threads: None,
+    };
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--runs" => parsed.runs = value()?.parse()?,
+            "--seed" => parsed.seed = value()?.parse()?,
+            "--cycles" => parsed.cycles = value()?.parse()?,
+            "--until-dead" => parsed.until_dead = true,
+            "--inputs" => parsed.inputs = Some(value()?),
+            "--threads" => parsed.threads = Some(value()?.parse()?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg).into()),
+        }
+    }
+    parsed.path = path.ok_or("no image or network given")?;
+    Ok(parsed)
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let mut batch = Batch::new(Source::from_path(&args.path)?)?;
+    if let Some(threads) = args.threads {
+        batch.threads = threads;
+    }
+
+    let inputs = match &args.inputs {
+        Some(path) => parse_inputs(&fs::read_to_string(path)?)?,
+        None => vec![],
+    };
+    let stop = match args.until_dead {
+        true => Stop::AllDead {
+            max_cycles: args.cycles,
+        },
+        false => Stop::Cycles(args.cycles),
+    };
+    let runs: Vec<Run> = (0..args.runs)
+        .map(|i| Run {
+            seed: args.seed.wrapping_add(i as u64),
+            inputs: inputs.clone(),
+            stop: stop.clone(),
+        })
+        .collect();
+
+    for result in batch.run(&runs)? {
+        println!("{}", result);
+    }
+    Ok(())
+}
+
+fn main() {
+    if env::args().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(args) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
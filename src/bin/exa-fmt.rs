@@ -0,0 +1,70 @@
+//! Formats EXA scripts in the canonical style. See format_text in
+//! src/parse/format.rs for what that means.
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+use exa::parse::format_text;
+
+const USAGE: &str = "usage: exa-fmt [options] [script]...
+
+Formats scripts in place, or standard input to standard output if no
+scripts are given.
+
+options:
+  --check         list scripts that aren't formatted, and fail if there
+                  are any, rather than formatting them";
+
+/// Format every script, returning whether any needed it.
+fn run(paths: &[&String], check: bool) -> Result<bool, Box<dyn Error>> {
+    if paths.is_empty() {
+        let mut script = String::new();
+        io::stdin().read_to_string(&mut script)?;
+        let formatted = format_text(&script)?;
+        if !check {
+            print!("{}", formatted);
+        }
+        return Ok(formatted != script);
+    }
+
+    let mut changed = false;
+    for path in paths {
+        let script = fs::read_to_string(path)?;
+        let formatted = format_text(&script).map_err(|e| format!("{}: {}", path, e))?;
+        if formatted == script {
+            continue;
+        }
+        changed = true;
+        match check {
+            true => println!("{}", path),
+            false => fs::write(path, formatted)?,
+        }
+    }
+    Ok(changed)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Some(arg) = args.iter().find(|a| a.starts_with("--") && *a != "--check") {
+        eprintln!("unknown option {}\n\n{}", arg, USAGE);
+        process::exit(2);
+    }
+
+    let check = args.iter().any(|a| a == "--check");
+    let paths: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    match run(&paths, check) {
+        Ok(true) if check => process::exit(1),
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Lints EXA scripts and prints what it finds, one problem per line. See
+//! src/lint/mod.rs for the checks.
+
+use std::env;
+use std::fs;
+use std::process;
+
+use exa::lint::lint;
+
+const USAGE: &str = "usage: exa-lint [options] <script>...
+
+options:
+  --network       scripts are for a network rather than the Redshift, so
+                  skip the checks on Redshift registers";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    if let Some(arg) = args
+        .iter()
+        .find(|a| a.starts_with("--") && *a != "--network")
+    {
+        eprintln!("unknown option {}\n\n{}", arg, USAGE);
+        process::exit(2);
+    }
+
+    let redshift = !args.iter().any(|a| a == "--network");
+    let mut failed = false;
+    for path in args.iter().filter(|a| !a.starts_with("--")) {
+        let result = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|script| lint(&script, redshift).map_err(|e| e.to_string()));
+        match result {
+            Ok(diagnostics) => {
+                for d in diagnostics.iter() {
+                    println!("{}: {}", path, d);
+                }
+                failed |= !diagnostics.is_empty();
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
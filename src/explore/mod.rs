@@ -0,0 +1,339 @@
+//! Looks for race conditions on M. When several EXAs read the same bus in
+//! the same cycle, which of them gets which message comes down to the
+//! order they run in, and the game shuffles that order every cycle. The
+//! Explorer runs a VM forward under every ordering of those readers, for
+//! a bounded number of cycles, and reports each distinct way the run can
+//! end along with a schedule that leads there.
+//!
+//! Cycles without contended reads always run in spawn order, and on
+//! contended cycles the readers run first, so orderings that only differ
+//! in when non-readers run aren't explored. Branches are taken from save
+//! states, so custom hardware registers become plain Registers and every
+//! branch runs under the default Prioritized kill policy, whatever policy
+//! the VM being explored was given.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use itertools::Itertools;
+
+use super::vm::scheduler::Scripted;
+use super::vm::terminated::{Cause, Terminated};
+use super::vm::trace::Bus;
+use super::vm::value::Value;
+use super::vm::VM;
+
+/// The order the contended readers ran in on one cycle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Choice {
+    pub cycle: u32,
+    pub order: Vec<String>,
+}
+
+/// A list of choices, which can be turned into a Scripted scheduler to
+/// run the same way again.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schedule(pub Vec<Choice>);
+
+impl Schedule {
+    pub fn scheduler(&self) -> Scripted {
+        self.0.iter().fold(Scripted::new(&[]), |s, c| {
+            let order: Vec<&str> = c.order.iter().map(|n| n.as_str()).collect();
+            s.on_cycle(c.cycle, &order)
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "spawn order throughout");
+        }
+        let choices = self
+            .0
+            .iter()
+            .map(|c| format!("cycle {}: {}", c.cycle, c.order.join(" ")));
+        write!(f, "{}", choices.format(", "))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Death {
+    pub exa: String,
+    pub cycle: u32,
+    pub cause: Cause,
+}
+
+/// Everything about the end of a run that explored schedules are
+/// compared on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    /// The rendered screen, for Redshift VMs.
+    pub framebuffer: Option<Vec<bool>>,
+    /// Every hardware register as (host, register, value), sorted.
+    pub host_registers: Vec<(String, String, Value)>,
+    /// Registers of every EXA still alive, in spawn order.
+    pub exa_registers: Vec<(String, Vec<(String, Value)>)>,
+    /// Every EXA that died during the run, in the order they died.
+    pub deaths: Vec<Death>,
+}
+
+impl Outcome {
+    /// Differences from other, one per line, for reports.
+    pub fn diff(&self, other: &Outcome) -> Vec<String> {
+        let mut diffs = vec![];
+        if self.framebuffer != other.framebuffer {
+            diffs.push(String::from("framebuffer differs"));
+        }
+        for (host, name, value) in self.host_registers.iter() {
+            let theirs = other
+                .host_registers
+                .iter()
+                .find(|(h, n, _)| h == host && n == name)
+                .map(|(_, _, v)| v);
+            if theirs != Some(value) {
+                diffs.push(format!("{} {} is {}", host, name, value));
+            }
+        }
+        for (exa, registers) in self.exa_registers.iter() {
+            match other.exa_registers.iter().find(|(e, _)| e == exa) {
+                Some((_, theirs)) if theirs == registers => {}
+                Some(_) => diffs.push(format!("{} registers differ", exa)),
+                None => diffs.push(format!("{} is alive", exa)),
+            }
+        }
+        for death in self.deaths.iter() {
+            if !other.deaths.contains(death) {
+                diffs.push(format!(
+                    "{} died on cycle {}: {:?}",
+                    death.exa, death.cycle, death.cause
+                ));
+            }
+        }
+        diffs
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// Every distinct outcome, each with the first schedule found that
+    /// leads to it.
+    pub outcomes: Vec<(Outcome, Schedule)>,
+    /// Schedules run to the end of the cycle limit.
+    pub schedules: usize,
+    /// Set if exploring stopped at max_schedules with orderings left to
+    /// try.
+    pub truncated: bool,
+}
+
+impl Report {
+    /// Whether the run can end in more than one way.
+    pub fn divergent(&self) -> bool {
+        self.outcomes.len() > 1
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} distinct outcomes from {} schedules",
+            self.outcomes.len(),
+            self.schedules
+        )?;
+        if self.truncated {
+            write!(f, " (truncated)")?;
+        }
+        let (first, _) = match self.outcomes.first() {
+            Some(o) => o,
+            None => return Ok(()),
+        };
+        for (i, (outcome, schedule)) in self.outcomes.iter().enumerate() {
+            write!(f, "\noutcome {}: {}", i, schedule)?;
+            if i > 0 {
+                for diff in outcome.diff(first) {
+                    write!(f, "\n  {}", diff)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Explores orderings of contended M reads, depth first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explorer {
+    /// How many cycles each schedule runs for, unless every EXA is gone
+    /// sooner.
+    pub cycles: u32,
+    pub max_schedules: usize,
+}
+
+struct Search {
+    report: Report,
+    // Hashes of every (state, deaths so far) already explored from
+    seen: HashSet<u64>,
+}
+
+impl Explorer {
+    pub fn new(cycles: u32) -> Explorer {
+        Explorer {
+            cycles,
+            max_schedules: 10000,
+        }
+    }
+
+    /// Explore from vm's current state. vm itself isn't changed, and
+    /// can't be partway through a cycle.
+    pub fn explore(&self, vm: &VM) -> Result<Report, Box<dyn Error>> {
+        if vm.mid_cycle() {
+            return Err("cannot explore partway through a cycle".into());
+        }
+        let mut search = Search {
+            report: Report {
+                outcomes: vec![],
+                schedules: 0,
+                truncated: false,
+            },
+            seen: HashSet::new(),
+        };
+        let end = vm.cycle.saturating_add(self.cycles);
+        self.visit(&mut search, vm.save_state(), end, &mut vec![], &mut vec![])?;
+        Ok(search.report)
+    }
+
+    fn visit(
+        &self,
+        search: &mut Search,
+        state: Vec<u8>,
+        end: u32,
+        choices: &mut Vec<Choice>,
+        deaths: &mut Vec<Death>,
+    ) -> Result<(), Box<dyn Error>> {
+        if search.report.schedules >= self.max_schedules {
+            search.report.truncated = true;
+            return Ok(());
+        }
+
+        let mut vm = VM::load_state(&state)?;
+        if vm.cycle >= end || vm.exas.is_empty() {
+            search.report.schedules += 1;
+            let outcome = outcome(&mut vm, deaths);
+            if !search.report.outcomes.iter().any(|(o, _)| *o == outcome) {
+                let schedule = Schedule(choices.clone());
+                search.report.outcomes.push((outcome, schedule));
+            }
+            return Ok(());
+        }
+
+        for order in contended_orders(&vm) {
+            let mut vm = VM::load_state(&state)?;
+            let names: Vec<&str> = order.iter().map(|n| n.as_str()).collect();
+            vm.scheduler = Box::new(Scripted::new(&names));
+            let cycle = vm.cycle;
+            vm.run_cycle();
+
+            let died = vm.terminated().map(death).collect::<Vec<_>>();
+            let next = vm.save_state();
+            let mut hasher = DefaultHasher::new();
+            next.hash(&mut hasher);
+            format!("{:?}{:?}", deaths, died).hash(&mut hasher);
+            if !search.seen.insert(hasher.finish()) {
+                continue;
+            }
+
+            let chose = !order.is_empty();
+            if chose {
+                choices.push(Choice { cycle, order });
+            }
+            let before = deaths.len();
+            deaths.extend(died);
+            self.visit(search, next, end, choices, deaths)?;
+            deaths.truncate(before);
+            if chose {
+                choices.pop();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every order the next cycle's contended readers could run in. A
+/// single empty order if nothing is contended.
+fn contended_orders(vm: &VM) -> Vec<Vec<String>> {
+    let mut readers: Vec<(Bus, Vec<String>)> = vec![];
+    for exa in vm.exas.iter() {
+        if exa.is_frozen() || exa.is_fatal() {
+            continue;
+        }
+        if let Some(bus) = exa.will_read_m_this_cycle(&vm.hosts) {
+            match readers.iter_mut().find(|(b, _)| *b == bus) {
+                Some((_, names)) => names.push(exa.name.to_string()),
+                None => readers.push((bus, vec![exa.name.to_string()])),
+            }
+        }
+    }
+
+    let orderings: Vec<Vec<Vec<String>>> = readers
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(_, names)| {
+            let n = names.len();
+            names.into_iter().permutations(n).collect()
+        })
+        .collect();
+    if orderings.is_empty() {
+        return vec![vec![]];
+    }
+    orderings
+        .into_iter()
+        .multi_cartesian_product()
+        .map(|per_bus| per_bus.concat())
+        .collect()
+}
+
+fn death(t: &Terminated) -> Death {
+    Death {
+        exa: t.name.clone(),
+        cycle: t.cycle,
+        cause: t.cause.clone(),
+    }
+}
+
+fn outcome(vm: &mut VM, deaths: &[Death]) -> Outcome {
+    let mut deaths = deaths.to_vec();
+    // EXAs that died on the last cycle haven't been cleaned up yet
+    for exa in vm.exas.iter() {
+        if let Some(t) = Terminated::from_exa(exa, &vm.hosts) {
+            deaths.push(death(&t));
+        }
+    }
+
+    let mut host_registers = vec![];
+    for host in vm.hosts.values() {
+        for (name, register) in host.registers.iter() {
+            let value = register.peek();
+            host_registers.push((host.name.clone(), name.clone(), value));
+        }
+    }
+    host_registers.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+    let exa_registers = vm
+        .exas
+        .iter()
+        .filter(|e| !e.is_fatal())
+        .map(|e| (e.name.to_string(), e.register_values()))
+        .collect();
+
+    let framebuffer = vm.redshift.is_some().then(|| vm.render().to_vec());
+
+    Outcome {
+        framebuffer,
+        host_registers,
+        exa_registers,
+        deaths,
+    }
+}
@@ -96,7 +96,7 @@ fn png_to_image_data(path: String) -> Result<ImageData, Box<dyn Error>> {
 
 /// Load a Redshift image from the specified file and return
 /// an initialized Redshift VM implementing the program.
-pub fn load_image<'a>(path: String) -> Result<VM<'a>, Box<dyn Error>> {
+pub fn load_image(path: String) -> Result<VM, Box<dyn Error>> {
     let mut image_data = png_to_image_data(path)?;
 
     let mut vm = VM::new_redshift();
@@ -114,7 +114,7 @@ pub fn load_image<'a>(path: String) -> Result<VM<'a>, Box<dyn Error>> {
 
     let exa_count = image_data.read_int();
 
-    let start_host = vm.hosts.get("core").unwrap().clone();
+    let start_host = vm.hosts.id("core").unwrap();
     let mut seen_names: HashSet<String> = HashSet::new();
 
     for _ in 0..exa_count {
@@ -141,17 +141,11 @@ pub fn load_image<'a>(path: String) -> Result<VM<'a>, Box<dyn Error>> {
         }
         let sprite = Sprite::from_pixels(raw_sprite);
 
-        let exa = Exa::spawn(
-            &mut vm,
-            start_host.clone(),
-            exa_name,
-            true,
-            exa_script.as_str(),
-        )
-        .expect("failed to initialize exa");
-        exa.borrow_mut().sprite = sprite;
+        let exa = Exa::spawn(&mut vm, start_host, exa_name, true, exa_script.as_str())
+            .expect("failed to initialize exa");
+        exa.sprite = sprite;
         if message_bus_mode == 1 {
-            exa.borrow_mut().mode = Mode::Local;
+            exa.mode = Mode::Local;
         }
     }
 
@@ -1,55 +1,101 @@
 mod libretro;
 
+pub mod batch;
+pub mod explore;
 pub mod image;
+pub mod lint;
+pub mod network;
 pub mod parse;
+pub mod puzzle;
 pub mod vm;
 
 use libretro::*;
 
 use crate::image::load_image;
-use vm::redshift::RedshiftButton;
+use vm::redshift::{AnaglyphPixel, RedshiftButton};
+use vm::state::{StateReader, StateWriter};
 use vm::VM;
 
+// Save states vary in size with the number of EXAs and files in play,
+// but frontends expect the size to stay put between calls. We grow it
+// in large steps and never shrink it.
+const STATE_SIZE_STEP: usize = 64 * 1024;
+
+// With runtime controls on, keep about 30 seconds of history to rewind
+// through at 1000 cycles per frame.
+#[cfg(feature = "runtime_controls")]
+const REWIND_INTERVAL: u32 = 1000;
+#[cfg(feature = "runtime_controls")]
+const REWIND_CHECKPOINTS: usize = 1800;
+
 #[allow(dead_code)]
-struct Emulator<'a> {
+struct Emulator {
     #[allow(dead_code)]
     rom_path: Option<String>,
     game_data: Option<GameData>,
 
     pub frame_counter: u32,
-    vm: Option<VM<'a>>,
+    vm: Option<VM>,
     video_frame: [u8; 120 * 100 * 2],
+    state_size: usize,
 
     run: bool,
 }
 
-impl Default for Emulator<'_> {
+impl Default for Emulator {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<'a> Emulator<'_> {
-    fn new() -> Emulator<'a> {
+impl Emulator {
+    fn new() -> Emulator {
         Emulator {
             rom_path: None,
             game_data: None,
             frame_counter: 0,
             vm: None,
             video_frame: [0; 120 * 100 * 2],
+            state_size: 0,
             run: true,
         }
     }
 
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let vm = self.vm.as_ref()?;
+        let mut w = StateWriter::new();
+        w.write_uint(self.frame_counter);
+        let mut data = w.into_inner();
+        data.extend(vm.save_state());
+        Some(data)
+    }
+
     fn update_video_frame(video_frame: &mut [u8; 120 * 100 * 2], framebuffer: &[bool; 120 * 100]) {
         for (idx, pixel) in framebuffer.iter().enumerate() {
             video_frame[idx * 2] = if *pixel { 255 } else { 0 };
             video_frame[(idx * 2) + 1] = if *pixel { 255 } else { 0 };
         }
     }
+
+    fn update_video_frame_anaglyph(
+        video_frame: &mut [u8; 120 * 100 * 2],
+        framebuffer: &[AnaglyphPixel; 120 * 100],
+    ) {
+        for (idx, pixel) in framebuffer.iter().enumerate() {
+            // RGB565: red is the top 5 bits, green and blue make up cyan
+            let color: u16 = match (pixel.red, pixel.cyan) {
+                (true, true) => 0xFFFF,
+                (true, false) => 0xF800,
+                (false, true) => 0x07FF,
+                (false, false) => 0x0000,
+            };
+            video_frame[idx * 2] = color as u8;
+            video_frame[(idx * 2) + 1] = (color >> 8) as u8;
+        }
+    }
 }
 
-impl Core for Emulator<'_> {
+impl Core for Emulator {
     fn info() -> CoreInfo {
         CoreInfo::new("exa-rs", env!("CARGO_PKG_VERSION"))
             .supports_roms_with_extension("png")
@@ -91,6 +137,11 @@ impl Core for Emulator<'_> {
 
         let vm = self.vm.as_mut().unwrap();
 
+        #[cfg(feature = "runtime_controls")]
+        if !vm.rewind_enabled() {
+            vm.enable_rewind(REWIND_INTERVAL, REWIND_CHECKPOINTS);
+        }
+
         if self.frame_counter % 2 == 0 {
             vm.reset_inputs();
             vm.unfreeze_waiters();
@@ -128,9 +179,23 @@ impl Core for Emulator<'_> {
             }
 
             if handle.is_joypad_button_pressed(0, JoypadButton::X) {
+                if let Some(name) = vm.step_instruction() {
+                    let info = vm.exa_info().into_iter().find(|e| e.name == name);
+                    println!("{:#?}", info);
+                }
+            }
+
+            if handle.is_joypad_button_pressed(0, JoypadButton::L1) {
                 vm.run_cycle();
             }
 
+            if handle.is_joypad_button_pressed(0, JoypadButton::L2) {
+                match vm.step_back() {
+                    Ok(()) => println!("rewound to cycle {}", vm.cycle),
+                    Err(e) => println!("can't rewind: {}", e),
+                }
+            }
+
             if handle.is_joypad_button_pressed(0, JoypadButton::R1) {
                 println!("{}", &vm);
             }
@@ -140,7 +205,11 @@ impl Core for Emulator<'_> {
             vm.run_for_frame();
         }
 
-        Emulator::update_video_frame(&mut self.video_frame, vm.render());
+        if vm.anaglyph_enabled() {
+            Emulator::update_video_frame_anaglyph(&mut self.video_frame, vm.render_anaglyph());
+        } else {
+            Emulator::update_video_frame(&mut self.video_frame, vm.render());
+        }
         handle.upload_video_frame(&self.video_frame);
 
         handle.upload_audio_frame(vm.audio_frame());
@@ -152,6 +221,47 @@ impl Core for Emulator<'_> {
             Err(_) => panic!("failed to reset"),
         }
     }
+
+    fn serialize_size(&mut self) -> usize {
+        if let Some(state) = self.save_state() {
+            let size = (state.len() / STATE_SIZE_STEP + 1) * STATE_SIZE_STEP;
+            if size > self.state_size {
+                self.state_size = size;
+            }
+        }
+        self.state_size
+    }
+
+    fn serialize(&mut self, data: &mut [u8]) -> bool {
+        let state = match self.save_state() {
+            Some(state) => state,
+            None => return false,
+        };
+        if state.len() > data.len() {
+            return false;
+        }
+
+        data[..state.len()].copy_from_slice(&state);
+        data[state.len()..].iter_mut().for_each(|b| *b = 0);
+        true
+    }
+
+    fn unserialize(&mut self, data: &[u8]) -> bool {
+        let mut r = StateReader::new(data);
+        let frame_counter = match r.read_uint() {
+            Ok(frame_counter) => frame_counter,
+            Err(_) => return false,
+        };
+
+        match VM::load_state(r.remaining()) {
+            Ok(vm) => {
+                self.frame_counter = frame_counter;
+                self.vm = Some(vm);
+                true
+            }
+            Err(_) => false,
+        }
+    }
 }
 
 libretro_core!(Emulator);
@@ -223,6 +223,15 @@ pub trait Core: Default {
     fn on_unload_game(&mut self) -> GameData;
     fn on_run(&mut self, handle: &mut RuntimeHandle);
     fn on_reset(&mut self);
+    fn serialize_size(&mut self) -> usize {
+        0
+    }
+    fn serialize(&mut self, _data: &mut [u8]) -> bool {
+        false
+    }
+    fn unserialize(&mut self, _data: &[u8]) -> bool {
+        false
+    }
     fn save_memory(&mut self) -> Option<&mut [u8]> {
         None
     }
@@ -463,15 +472,23 @@ impl<B: Core> Retro<B> {
     }
 
     pub fn on_serialize_size(&mut self) -> libc::size_t {
-        0
+        self.core.serialize_size() as libc::size_t
     }
 
-    pub fn on_serialize(&mut self, _data: *mut libc::c_void, _size: libc::size_t) -> bool {
-        false
+    pub fn on_serialize(&mut self, data: *mut libc::c_void, size: libc::size_t) -> bool {
+        if data.is_null() {
+            return false;
+        }
+        let data = unsafe { slice::from_raw_parts_mut(data as *mut u8, size) };
+        self.core.serialize(data)
     }
 
-    pub fn on_unserialize(&mut self, _data: *const libc::c_void, _size: libc::size_t) -> bool {
-        false
+    pub fn on_unserialize(&mut self, data: *const libc::c_void, size: libc::size_t) -> bool {
+        if data.is_null() {
+            return false;
+        }
+        let data = unsafe { slice::from_raw_parts(data as *const u8, size) };
+        self.core.unserialize(data)
     }
 
     pub fn on_cheat_reset(&mut self) {}
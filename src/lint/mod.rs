@@ -0,0 +1,305 @@
+//! Finds mistakes in EXA scripts without running them. Most of these
+//! would otherwise only turn up as an EXA dying partway through a run,
+//! if the code they're in runs at all.
+//!
+//! Checks go by the order instructions are written in rather than the
+//! order they run in, so they can miss problems that depend on which way
+//! a jump goes.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::Serialize;
+
+use super::parse::{parse_text_with_spans, ParseError};
+use super::vm::instruction::{Instruction, Span, Target};
+use super::vm::redshift::REDSHIFT_REGISTERS;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lint {
+    /// JUMP, TJMP, FJMP or REPL to a label no MARK gives.
+    UnknownLabel(String),
+    /// MARK of a label that was already marked.
+    DuplicateMark(String),
+    /// Code after a JUMP or HALT with no MARK in between, which nothing
+    /// can reach.
+    Unreachable,
+    /// Write to a register that can only be read, like CI on the Redshift.
+    ReadOnlyRegister(String),
+    /// Read of a register that can only be written, like GP on the
+    /// Redshift.
+    WriteOnlyRegister(String),
+    /// Hardware register no Redshift host has.
+    UnknownHardwareRegister(String),
+    /// File instruction before any MAKE or GRAB, in an EXA that doesn't
+    /// start with a file from DATA.
+    NoFile,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lint::UnknownLabel(label) => write!(f, "unknown label {}", label.to_uppercase()),
+            Lint::DuplicateMark(label) => {
+                write!(f, "label {} is already marked", label.to_uppercase())
+            }
+            Lint::Unreachable => write!(f, "unreachable code"),
+            Lint::ReadOnlyRegister(name) => write!(f, "{} is read-only", name.to_uppercase()),
+            Lint::WriteOnlyRegister(name) => write!(f, "{} is write-only", name.to_uppercase()),
+            Lint::UnknownHardwareRegister(name) => {
+                write!(f, "no Redshift host has register {}", name.to_uppercase())
+            }
+            Lint::NoFile => write!(f, "file used before any MAKE or GRAB"),
+        }
+    }
+}
+
+/// A problem found in a script, and the instruction it's on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub lint: Lint,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.lint)
+    }
+}
+
+/// Parse and lint a script. The register checks only apply to scripts
+/// for the Redshift, since network hosts can have any registers.
+pub fn lint(script: &str, redshift: bool) -> Result<Vec<Diagnostic>, ParseError> {
+    let (insts, spans) = parse_text_with_spans(script)?;
+    Ok(lint_instructions(&insts, &spans, redshift))
+}
+
+/// Lint instructions as parsed, MARKs and DATA included, with the span
+/// of each. Diagnostics come back in source order, and a problem in an
+/// @REP block is only reported for the first expansion it turns up in.
+pub fn lint_instructions(insts: &[Instruction], spans: &[Span], redshift: bool) -> Vec<Diagnostic> {
+    let mut found = vec![];
+    check_labels(insts, &mut found);
+    check_reachable(insts, &mut found);
+    check_files(insts, &mut found);
+    if redshift {
+        check_registers(insts, &mut found);
+    }
+
+    let mut seen = HashSet::new();
+    let mut diagnostics: Vec<Diagnostic> = found
+        .into_iter()
+        .filter_map(|(pc, lint)| {
+            let span = spans[pc];
+            let first = seen.insert((span.line, span.column, lint.clone()));
+            first.then_some(Diagnostic { span, lint })
+        })
+        .collect();
+    diagnostics.sort_by_key(|d| (d.span.line, d.span.column, d.span.rep));
+    diagnostics
+}
+
+fn check_labels(insts: &[Instruction], found: &mut Vec<(usize, Lint)>) {
+    let mut marks = HashSet::new();
+    for (pc, inst) in insts.iter().enumerate() {
+        if let Instruction::Mark(label) = inst {
+            if !marks.insert(label) {
+                found.push((pc, Lint::DuplicateMark(label.clone())));
+            }
+        }
+    }
+    for (pc, inst) in insts.iter().enumerate() {
+        match inst {
+            Instruction::Jump(label)
+            | Instruction::Tjmp(label)
+            | Instruction::Fjmp(label)
+            | Instruction::Repl(label)
+                if !marks.contains(label) =>
+            {
+                found.push((pc, Lint::UnknownLabel(label.clone())));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn check_reachable(insts: &[Instruction], found: &mut Vec<(usize, Lint)>) {
+    // Only the start of each stretch of dead code is reported
+    let (mut dead, mut reported) = (false, false);
+    for (pc, inst) in insts.iter().enumerate() {
+        match inst {
+            Instruction::Mark(_) => (dead, reported) = (false, false),
+            // DATA isn't run, so it's fine anywhere
+            Instruction::Data(_) => {}
+            _ => {
+                if dead && !reported {
+                    found.push((pc, Lint::Unreachable));
+                    reported = true;
+                }
+                if matches!(inst, Instruction::Jump(_) | Instruction::Halt) {
+                    dead = true;
+                }
+            }
+        }
+    }
+}
+
+fn check_files(insts: &[Instruction], found: &mut Vec<(usize, Lint)>) {
+    if insts.iter().any(|i| matches!(i, Instruction::Data(_))) {
+        return;
+    }
+    for (pc, inst) in insts.iter().enumerate() {
+        match inst {
+            Instruction::Make | Instruction::Grab(_) => return,
+            Instruction::File(_)
+            | Instruction::Seek(_)
+            | Instruction::VoidF
+            | Instruction::Drop
+            | Instruction::Wipe
+            | Instruction::TestEof => found.push((pc, Lint::NoFile)),
+            _ => {
+                let (reads, write) = operands(inst);
+                if reads
+                    .iter()
+                    .chain(write.iter())
+                    .any(|t| is_register(t, "f"))
+                {
+                    found.push((pc, Lint::NoFile));
+                }
+            }
+        }
+    }
+}
+
+fn check_registers(insts: &[Instruction], found: &mut Vec<(usize, Lint)>) {
+    for (pc, inst) in insts.iter().enumerate() {
+        let (reads, write) = operands(inst);
+        for target in reads.iter().chain(write.iter()) {
+            if let Target::Register(name) = target {
+                let known = REDSHIFT_REGISTERS.iter().any(|(_, r)| r == name);
+                if name.starts_with('#') && !known {
+                    found.push((pc, Lint::UnknownHardwareRegister(name.clone())));
+                }
+            }
+        }
+        if write.is_some_and(|t| is_register(t, "ci")) {
+            found.push((pc, Lint::ReadOnlyRegister("ci".into())));
+        }
+        if reads.iter().any(|t| is_register(t, "gp")) {
+            found.push((pc, Lint::WriteOnlyRegister("gp".into())));
+        }
+    }
+}
+
+/// The targets an instruction reads, and the one it writes to, if any.
+fn operands(inst: &Instruction) -> (Vec<&Target>, Option<&Target>) {
+    match inst {
+        Instruction::Copy(src, dest) => (vec![src], Some(dest)),
+        Instruction::Addi(l, r, d)
+        | Instruction::Subi(l, r, d)
+        | Instruction::Muli(l, r, d)
+        | Instruction::Divi(l, r, d)
+        | Instruction::Modi(l, r, d)
+        | Instruction::Swiz(l, r, d)
+        | Instruction::Rand(l, r, d) => (vec![l, r], Some(d)),
+        Instruction::Test(l, _, r) => (vec![l, r], None),
+        Instruction::Link(t) | Instruction::Grab(t) | Instruction::Seek(t) => (vec![t], None),
+        Instruction::Host(dest) | Instruction::File(dest) => (vec![], Some(dest)),
+        _ => (vec![], None),
+    }
+}
+
+fn is_register(target: &Target, name: &str) -> bool {
+    matches!(target, Target::Register(r) if r == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lints(script: &str) -> Vec<String> {
+        lint(script, true)
+            .expect("failed to lint")
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_clean() {
+        let script = "mark loop\ncopy #padx gx\ncopy 300 gp\ntest ci > 0\ntjmp hit\njump loop\nmark hit\nmake\ncopy 1 f\nhalt\n";
+        assert!(lints(script).is_empty());
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(
+            lints("mark a\nnoop\n mark a\ntjmp a\nfjmp b\nrepl Nope\n"),
+            vec![
+                "line 3, column 2: label A is already marked",
+                "line 5, column 1: unknown label B",
+                "line 6, column 1: unknown label NOPE",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable() {
+        assert_eq!(
+            lints("jump a\nnoop\njump a\nnoop\nmark a\nhalt\ndata 1 2\ncopy f x\n"),
+            vec![
+                "line 2, column 1: unreachable code",
+                "line 8, column 1: unreachable code",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_registers() {
+        assert_eq!(
+            lints("copy 1 ci\naddi gp 1 x\ncopy 5 #sqr0\ncopy #PADX #nope\nrand gp gp ci\n"),
+            vec![
+                "line 1, column 1: CI is read-only",
+                "line 2, column 1: GP is write-only",
+                "line 4, column 1: no Redshift host has register #NOPE",
+                "line 5, column 1: CI is read-only",
+                "line 5, column 1: GP is write-only",
+            ]
+        );
+        let diagnostics = lint("copy 1 ci\ncopy #nope x\n", false).unwrap();
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_files() {
+        assert_eq!(
+            lints("copy f x\nseek -9999\ngrab 200\ncopy f x\n"),
+            vec![
+                "line 1, column 1: file used before any MAKE or GRAB",
+                "line 2, column 1: file used before any MAKE or GRAB",
+            ]
+        );
+        assert!(lints("copy f x\ntest eof\ndata 1\n").is_empty());
+    }
+
+    #[test]
+    fn test_rep_reported_once() {
+        let diagnostics = lint("@rep 3\ncopy @{1,1} ci\n@end\n", true).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "line 2, column 1 (@REP expansion 0): CI is read-only"
+        );
+        assert_eq!(diagnostics[0].lint, Lint::ReadOnlyRegister("ci".into()));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = lint("copy 1\n", true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 1, column 7: expected COPY R/N R, found end of line"
+        );
+    }
+}
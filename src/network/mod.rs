@@ -0,0 +1,389 @@
+//! Networks described as data instead of Rust code. A network is a TOML
+//! document listing hosts, the links between them, and what starts out
+//! inside each host:
+//!
+//! ```toml
+//! seed = 1234
+//! scheduler = "spawn_order"
+//!
+//! [[host]]
+//! name = "inbox"
+//! capacity = 9
+//!
+//! [[host.register]]
+//! name = "#NERV"
+//! permissions = "read_only"
+//! value = 0
+//!
+//! [[host.file]]
+//! id = 200
+//! contents = [1, 2, "SECRET"]
+//!
+//! [[host.exa]]
+//! name = "XA"
+//! script = """
+//! GRAB 200
+//! LINK 800
+//! """
+//!
+//! [[host]]
+//! name = "outbox"
+//! capacity = 4
+//!
+//! [[link]]
+//! from = "inbox"
+//! to = "outbox"
+//! id = 800
+//! return_id = -1
+//! ```
+//!
+//! Strings in file contents and register values are keywords.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+
+use serde::Deserialize;
+
+use super::parse::parse_text;
+use super::vm::exa::{Exa, Mode};
+use super::vm::file::File;
+use super::vm::register::Register;
+use super::vm::scheduler::{Reverse, Scheduler, Shuffle, SpawnOrder};
+use super::vm::value::Value;
+use super::vm::{Host, HostId, Permissions, VM};
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Network {
+    /// Seed for the VM's randomness. A random seed is picked if unset.
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub scheduler: SchedulerSpec,
+    #[serde(default, rename = "host")]
+    pub hosts: Vec<HostSpec>,
+    #[serde(default, rename = "link")]
+    pub links: Vec<LinkSpec>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HostSpec {
+    pub name: String,
+    /// Squares available to EXAs, files and hardware registers.
+    pub capacity: usize,
+    #[serde(default, rename = "register")]
+    pub registers: Vec<RegisterSpec>,
+    #[serde(default, rename = "file")]
+    pub files: Vec<FileSpec>,
+    #[serde(default, rename = "exa")]
+    pub exas: Vec<ExaSpec>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterSpec {
+    pub name: String,
+    #[serde(default = "default_permissions")]
+    pub permissions: Permissions,
+    #[serde(default = "default_value")]
+    pub value: Value,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FileSpec {
+    /// Files without an id are numbered like files made by EXAs. Those,
+    /// and files EXAs make later, are numbered past the largest id given
+    /// here so they never clash.
+    pub id: Option<i32>,
+    #[serde(default)]
+    pub contents: Vec<Value>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ExaSpec {
+    pub name: String,
+    pub script: String,
+    /// Start out communicating over the host's local bus.
+    #[serde(default)]
+    pub local: bool,
+}
+
+/// A link from one host to another. If return_id is set, a second link
+/// with that id leads back.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LinkSpec {
+    pub from: String,
+    pub to: String,
+    pub id: i32,
+    pub return_id: Option<i32>,
+}
+
+/// Which of the VM's built in schedulers to run EXAs with.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedulerSpec {
+    #[default]
+    Shuffle,
+    SpawnOrder,
+    Reverse,
+}
+
+impl SchedulerSpec {
+    pub fn build(self) -> Box<dyn Scheduler> {
+        match self {
+            SchedulerSpec::Shuffle => Box::new(Shuffle),
+            SchedulerSpec::SpawnOrder => Box::new(SpawnOrder),
+            SchedulerSpec::Reverse => Box::new(Reverse),
+        }
+    }
+}
+
+fn default_permissions() -> Permissions {
+    Permissions::ReadWrite
+}
+
+fn default_value() -> Value {
+    Value::Number(0)
+}
+
+fn validate_value(value: &Value) -> Result<(), Box<dyn Error>> {
+    match value {
+        Value::Number(n) if *n < -9999 || *n > 9999 => {
+            Err(format!("value {} out of range", n).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Network {
+    pub fn from_toml(text: &str) -> Result<Network, Box<dyn Error>> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Build a ready to run VM from the description. Hosts are created
+    /// first, then links, and EXAs are spawned last in the order listed.
+    pub fn build(&self) -> Result<VM, Box<dyn Error>> {
+        let mut vm = match self.seed {
+            Some(seed) => VM::with_seed(seed),
+            None => VM::new(),
+        };
+        vm.scheduler = self.scheduler.build();
+
+        let explicit_ids = self.hosts.iter().flat_map(|h| h.files.iter());
+        if let Some(max) = explicit_ids.filter_map(|f| f.id).max() {
+            vm.file_counter = vm.file_counter.max(max.saturating_add(1));
+        }
+
+        let mut file_ids = HashSet::new();
+        for spec in self.hosts.iter() {
+            if vm.hosts.contains_key(&spec.name) {
+                return Err(format!("duplicate host {}", spec.name).into());
+            }
+            let mut host = Host::new(spec.name.clone(), spec.capacity);
+            self.build_host(&mut vm, &mut host, spec, &mut file_ids)?;
+            vm.add_host(host);
+        }
+
+        for link in self.links.iter() {
+            let from = self.host(&vm, &link.from)?;
+            let to = self.host(&vm, &link.to)?;
+            self.add_link(&mut vm, link.id, from, to)?;
+            if let Some(return_id) = link.return_id {
+                self.add_link(&mut vm, return_id, to, from)?;
+            }
+        }
+
+        let mut exa_names = HashSet::new();
+        for spec in self.hosts.iter() {
+            let host = self.host(&vm, &spec.name)?;
+            for exa in spec.exas.iter() {
+                if !exa_names.insert(exa.name.clone()) {
+                    return Err(format!("duplicate exa {}", exa.name).into());
+                }
+
+                let mut script = exa.script.clone();
+                if !script.ends_with('\n') {
+                    script.push('\n');
+                }
+                parse_text(&script).map_err(|e| format!("exa {}: {}", exa.name, e))?;
+
+                let e = Exa::spawn(&mut vm, host, exa.name.clone(), false, &script)
+                    .map_err(|_| format!("no room for exa {} in host {}", exa.name, spec.name))?;
+                if exa.local {
+                    e.mode = Mode::Local;
+                }
+            }
+        }
+
+        Ok(vm)
+    }
+
+    fn build_host(
+        &self,
+        vm: &mut VM,
+        h: &mut Host,
+        spec: &HostSpec,
+        file_ids: &mut HashSet<i32>,
+    ) -> Result<(), Box<dyn Error>> {
+        let full = || format!("host {} is over capacity", spec.name);
+
+        for register in spec.registers.iter() {
+            if !register.name.starts_with('#') {
+                return Err(format!("register {} must start with #", register.name).into());
+            }
+            if h.registers
+                .contains_key(&register.name.to_ascii_lowercase())
+            {
+                return Err(format!("duplicate register {}", register.name).into());
+            }
+            validate_value(&register.value)?;
+            if h.occupied >= h.capacity {
+                return Err(full().into());
+            }
+
+            let r = Register::new(register.permissions.clone(), register.value.clone());
+            h.add_register(register.name.clone(), r);
+        }
+
+        for file in spec.files.iter() {
+            let id = match file.id {
+                Some(id) => id,
+                None => vm.next_file_id(),
+            };
+            if !file_ids.insert(id) {
+                return Err(format!("duplicate file {}", id).into());
+            }
+            for value in file.contents.iter() {
+                validate_value(value)?;
+            }
+
+            h.reserve_slot().map_err(|_| full())?;
+            h.files.push(File::new(id, file.contents.clone()));
+        }
+
+        Ok(())
+    }
+
+    fn host(&self, vm: &VM, name: &str) -> Result<HostId, Box<dyn Error>> {
+        match vm.hosts.id(name) {
+            Some(h) => Ok(h),
+            None => Err(format!("unknown host {}", name).into()),
+        }
+    }
+
+    fn add_link(
+        &self,
+        vm: &mut VM,
+        id: i32,
+        from: HostId,
+        to: HostId,
+    ) -> Result<(), Box<dyn Error>> {
+        let from_host = &vm.hosts[from];
+        if from_host.links.contains_key(&id) {
+            return Err(format!("duplicate link {} in host {}", id, from_host.name).into());
+        }
+        vm.add_link(id, from, to);
+        Ok(())
+    }
+}
+
+/// Load a network description from the specified TOML file and
+/// return a VM ready to run it.
+pub fn load_network(path: String) -> Result<VM, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    Network::from_toml(&text)?.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_error(text: &str) -> String {
+        let network = Network::from_toml(text).expect("failed to parse network");
+        network
+            .build()
+            .expect_err("network should not build")
+            .to_string()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let network = Network::from_toml(
+            "[[host]]\nname = \"a\"\ncapacity = 1\n[[host.register]]\nname = \"#R\"\n",
+        )
+        .unwrap();
+        assert_eq!(network.scheduler, SchedulerSpec::Shuffle);
+        assert_eq!(network.seed, None);
+        assert_eq!(
+            network.hosts[0].registers[0],
+            RegisterSpec {
+                name: "#R".into(),
+                permissions: Permissions::ReadWrite,
+                value: Value::Number(0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        assert!(Network::from_toml("[[host]]\nname = \"a\"\ncapacity = 1\nsize = 2\n").is_err());
+    }
+
+    #[test]
+    fn test_unknown_host() {
+        assert_eq!(
+            build_error("[[link]]\nfrom = \"a\"\nto = \"b\"\nid = 800\n"),
+            "unknown host a"
+        );
+    }
+
+    #[test]
+    fn test_over_capacity() {
+        assert_eq!(
+            build_error("[[host]]\nname = \"a\"\ncapacity = 1\n[[host.file]]\n[[host.file]]\n"),
+            "host a is over capacity"
+        );
+        assert_eq!(
+            build_error(
+                "[[host]]\nname = \"a\"\ncapacity = 0\n[[host.exa]]\nname = \"XA\"\nscript = \"noop\"\n"
+            ),
+            "no room for exa XA in host a"
+        );
+    }
+
+    #[test]
+    fn test_bad_script() {
+        assert!(build_error(
+            "[[host]]\nname = \"a\"\ncapacity = 1\n[[host.exa]]\nname = \"XA\"\nscript = \"copy m m\"\n"
+        )
+        .starts_with("exa XA:"));
+    }
+
+    #[test]
+    fn test_file_ids() {
+        let network = Network::from_toml(
+            "[[host]]\nname = \"a\"\ncapacity = 4\n[[host.file]]\nid = 400\n[[host.file]]\n[[host.exa]]\nname = \"XA\"\nscript = \"make\"\n",
+        )
+        .unwrap();
+        let mut vm = network.build().unwrap();
+        let ids = |vm: &VM| vm.hosts["a"].files.iter().map(|f| f.id).collect::<Vec<_>>();
+        assert_eq!(ids(&vm), vec![400, 401]);
+
+        vm.run_cycle();
+        assert_eq!(vm.exas[0].file.as_ref().map(|f| f.id), Some(402));
+    }
+
+    #[test]
+    fn test_value_range() {
+        assert_eq!(
+            build_error(
+                "[[host]]\nname = \"a\"\ncapacity = 1\n[[host.file]]\ncontents = [10000]\n"
+            ),
+            "value 10000 out of range"
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use std::error;
+use std::fmt;
+
+use super::super::vm::instruction::Span;
+
+/// Why a script couldn't be parsed, and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Where the problem is. Problems in an @REP block are reported for
+    /// the first expansion they turn up in.
+    pub span: Span,
+    /// The syntax that was expected at span, for text that didn't parse.
+    pub expected: Option<String>,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(span: Span, message: S) -> ParseError {
+        ParseError {
+            span,
+            expected: None,
+            message: message.into(),
+        }
+    }
+
+    /// The text at span didn't fit the expected syntax. found describes
+    /// what was there instead.
+    pub fn expected(span: Span, expected: &str, found: &str) -> ParseError {
+        ParseError {
+            span,
+            expected: Some(expected.to_string()),
+            message: format!("expected {}, found {}", expected, found),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
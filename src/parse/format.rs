@@ -0,0 +1,122 @@
+//! Writes scripts back out as EXA source.
+
+use itertools::Itertools;
+
+use super::super::vm::instruction::Instruction;
+use super::error::ParseError;
+use super::lexer::{lex_all, Comment, Line, Piece};
+use super::parse_text;
+use super::parts::parse_line;
+use super::preprocess::render;
+
+/// EXA source for instructions, one to a line. Parsing it gives the same
+/// instructions back.
+pub fn print_text(insts: &[Instruction]) -> String {
+    insts.iter().map(|i| format!("{}\n", i)).collect()
+}
+
+/// Rewrite a script in canonical form: uppercase, one space between
+/// arguments, no indentation and no more than one blank line in a row.
+/// Comments, MARKs, DATA and @REP blocks stay where they are, so the
+/// result always parses to the same instructions as the original.
+/// Scripts that don't parse are an error rather than formatted.
+pub fn format_text(i: &str) -> Result<String, ParseError> {
+    parse_text(i)?;
+    let mut out = String::new();
+    let mut blank = false;
+    for line in lex_all(i)? {
+        let text = format_line(&line);
+        if text.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        out.push_str(&text);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn format_line(line: &Line) -> String {
+    let mut out = match line.tokens.is_empty() {
+        true => String::new(),
+        false => format_code(line),
+    };
+    match &line.comment {
+        Some(Comment::Note(text)) if text.is_empty() => out.push_str("NOTE"),
+        Some(Comment::Note(text)) => out.push_str(&format!("NOTE {}", text)),
+        Some(Comment::Semicolon(text)) => {
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            out.push(';');
+            out.push_str(text);
+        }
+        None => {}
+    }
+    out
+}
+
+fn format_code(line: &Line) -> String {
+    if let Ok(rendered) = render(line, None) {
+        if let Ok(("", inst)) = parse_line(&format!("{}\n", rendered.text)) {
+            return inst.to_string();
+        }
+    }
+
+    // Lines with @{...} in them can't be parsed until their @REP block is
+    // expanded, and macros and lines in @REP 0 blocks aren't instructions
+    // at all. Everything parses the same whatever its case, so these are
+    // just uppercased.
+    line.tokens
+        .iter()
+        .map(|t| {
+            t.pieces
+                .iter()
+                .map(|p| match p {
+                    Piece::Text(text) => text.to_ascii_uppercase(),
+                    Piece::Expansion { start, step } => format!("@{{{},{}}}", start, step),
+                })
+                .join("")
+        })
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_print_text() {
+        let insts = parse_text("mark top\n copy 'hi' #sqr0\ntest x < -3\nvoid m\ndata 1 'two' 3\n")
+            .unwrap();
+        assert_eq!(
+            print_text(&insts),
+            "MARK TOP\nCOPY 'HI' #SQR0\nTEST X < -3\nVOID M\nDATA 1 'TWO' 3\n"
+        );
+    }
+
+    #[test]
+    fn test_format_text() {
+        let source = "\n\n  note don't touch\nlink  +800 ;go;go\n\n\n@rep 2\n\tcopy @{1,2}   x\n  mark l@{0,1};  keep  this\n @end\nnote\n\nhalt\n\n";
+        assert_eq!(
+            format_text(source).unwrap(),
+            "NOTE don't touch\nLINK 800 ;go;go\n\n@REP 2\nCOPY @{1,2} X\nMARK L@{0,1} ;  keep  this\n@END\nNOTE\n\nHALT\n"
+        );
+    }
+
+    #[test]
+    fn test_format_unparsed_lines() {
+        assert_eq!(
+            format_text("@rep 0\nnot an instruction\n@end\n").unwrap(),
+            "@REP 0\nNOT AN INSTRUCTION\n@END\n"
+        );
+        assert_eq!(
+            format_text("noop\nbad\n").unwrap_err().to_string(),
+            "line 2, column 1: expected an instruction, found `bad`"
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Splits EXA source into tokens, one line at a time, keeping comments
+//! aside for anything that writes the source back out.
+
+use super::super::vm::instruction::Span;
+use super::error::ParseError;
+
+/// Part of a token. Tokens are usually a single piece, but @{...} can sit
+/// right up against text, as in `MARK LOOP@{0,1}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Piece {
+    /// Text as written, including the quotes around keywords.
+    Text(String),
+    /// @{start,step}, which becomes start + step * n in the nth
+    /// expansion of the @REP block it's in.
+    Expansion { start: i32, step: i32 },
+}
+
+/// A run of source with no whitespace in it, outside of keywords.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    /// 1-based column of the token's first character.
+    pub column: usize,
+    pub pieces: Vec<Piece>,
+}
+
+impl Token {
+    /// The token's text, if it has no expansions in it.
+    pub fn text(&self) -> Option<&str> {
+        match self.pieces.as_slice() {
+            [Piece::Text(text)] => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Whether the token is the given word, ignoring case.
+    pub fn is(&self, word: &str) -> bool {
+        self.text().is_some_and(|t| t.eq_ignore_ascii_case(word))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Comment {
+    /// Everything after a ;, as written.
+    Semicolon(String),
+    /// Everything after NOTE, for lines starting with NOTE. These lines
+    /// have no tokens.
+    Note(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// 1-based line number.
+    pub number: usize,
+    pub tokens: Vec<Token>,
+    /// Column just past the last token.
+    pub end: usize,
+    pub comment: Option<Comment>,
+}
+
+impl Line {
+    pub fn span(&self) -> Span {
+        Span::new(self.number, self.tokens[0].column)
+    }
+
+    /// Where the token at index starts, or the end of the line if there
+    /// aren't that many tokens.
+    pub fn span_at(&self, index: usize) -> Span {
+        match self.tokens.get(index) {
+            Some(token) => Span::new(self.number, token.column),
+            None => Span::new(self.number, self.end),
+        }
+    }
+}
+
+/// Tokenize source, leaving out lines with nothing but comments or
+/// whitespace in them. Anything from a ; to the end of the line is a
+/// comment, as is any line starting with NOTE.
+pub fn lex(i: &str) -> Result<Vec<Line>, ParseError> {
+    let lines = lex_all(i)?;
+    Ok(lines.into_iter().filter(|l| !l.tokens.is_empty()).collect())
+}
+
+/// Tokenize every line of source, blank or not.
+pub fn lex_all(i: &str) -> Result<Vec<Line>, ParseError> {
+    i.lines()
+        .enumerate()
+        .map(|(idx, text)| lex_line(idx + 1, text))
+        .collect()
+}
+
+fn lex_line(number: usize, text: &str) -> Result<Line, ParseError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens: Vec<Token> = vec![];
+    let mut pos = 0;
+    let mut end = 1;
+    let mut comment = None;
+    loop {
+        while pos < chars.len() && chars[pos].is_whitespace() {
+            pos += 1;
+        }
+        // NOTE is only a comment where an instruction would go
+        if tokens.len() == 1 && tokens[0].is("note") {
+            let rest: String = chars[pos..].iter().collect();
+            comment = Some(Comment::Note(rest.trim_end().to_string()));
+            tokens.clear();
+            break;
+        }
+        if pos == chars.len() {
+            break;
+        }
+        if chars[pos] == ';' {
+            let rest: String = chars[pos + 1..].iter().collect();
+            comment = Some(Comment::Semicolon(rest.trim_end().to_string()));
+            break;
+        }
+
+        let mut token = Token {
+            column: pos + 1,
+            pieces: vec![],
+        };
+        let mut text = String::new();
+        while pos < chars.len() && !chars[pos].is_whitespace() && chars[pos] != ';' {
+            let column = pos + 1;
+            match chars[pos] {
+                '\'' => {
+                    let end = match chars[pos + 1..].iter().position(|c| *c == '\'') {
+                        Some(end) => pos + 1 + end,
+                        None => {
+                            let span = Span::new(number, chars.len() + 1);
+                            return Err(ParseError::expected(
+                                span,
+                                "' to close keyword",
+                                "end of line",
+                            ));
+                        }
+                    };
+                    text.extend(&chars[pos..=end]);
+                    pos = end + 1;
+                }
+                '@' if chars.get(pos + 1) == Some(&'{') => {
+                    let close = chars[pos..].iter().position(|c| *c == '}');
+                    let inner: Option<String> =
+                        close.map(|c| chars[pos + 2..pos + c].iter().collect());
+                    let expansion = inner.as_deref().and_then(parse_expansion);
+                    let (start, step) = match expansion {
+                        Some(expansion) => expansion,
+                        None => {
+                            let found: String = chars[pos..]
+                                .iter()
+                                .take_while(|c| !c.is_whitespace())
+                                .collect();
+                            return Err(ParseError::expected(
+                                Span::new(number, column),
+                                "@{start,step}",
+                                &format!("`{}`", found),
+                            ));
+                        }
+                    };
+                    if !text.is_empty() {
+                        token.pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    token.pieces.push(Piece::Expansion { start, step });
+                    pos += close.unwrap() + 1;
+                }
+                c => {
+                    text.push(c);
+                    pos += 1;
+                }
+            }
+        }
+        if !text.is_empty() {
+            token.pieces.push(Piece::Text(text));
+        }
+        tokens.push(token);
+        end = pos + 1;
+    }
+    Ok(Line {
+        number,
+        tokens,
+        end,
+        comment,
+    })
+}
+
+fn parse_expansion(inner: &str) -> Option<(i32, i32)> {
+    let (start, step) = inner.split_once(',')?;
+    Some((start.trim().parse().ok()?, step.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Piece {
+        Piece::Text(s.into())
+    }
+
+    fn token(column: usize, pieces: Vec<Piece>) -> Token {
+        Token { column, pieces }
+    }
+
+    #[test]
+    fn test_lex_line() {
+        let line = lex_line(3, "  copy 'two words;' x\t; comment 'unclosed").unwrap();
+        assert_eq!(line.number, 3);
+        assert_eq!(
+            line.tokens,
+            vec![
+                token(3, vec![text("copy")]),
+                token(8, vec![text("'two words;'")]),
+                token(21, vec![text("x")]),
+            ]
+        );
+        assert_eq!(line.span(), Span::new(3, 3));
+        assert_eq!(line.span_at(3), Span::new(3, 22));
+        assert_eq!(
+            line.comment,
+            Some(Comment::Semicolon(" comment 'unclosed".into()))
+        );
+    }
+
+    #[test]
+    fn test_lex_expansions() {
+        let line = lex_line(1, "mark a@{0,1}b @{ -5 , 10 }").unwrap();
+        assert_eq!(
+            line.tokens,
+            vec![
+                token(1, vec![text("mark")]),
+                token(
+                    6,
+                    vec![text("a"), Piece::Expansion { start: 0, step: 1 }, text("b")]
+                ),
+                token(
+                    15,
+                    vec![Piece::Expansion {
+                        start: -5,
+                        step: 10
+                    }]
+                ),
+            ]
+        );
+        assert_eq!(line.span_at(3), Span::new(1, 27));
+    }
+
+    #[test]
+    fn test_lex_notes() {
+        let source = "NOTE isn't the end \n  note\nmark denote\ncopy notepad x\n\n ; \n";
+        let lines = lex(source).unwrap();
+        let numbers: Vec<usize> = lines.iter().map(|l| l.number).collect();
+        assert_eq!(numbers, vec![3, 4]);
+        assert_eq!(lines[0].tokens[1], token(6, vec![text("denote")]));
+
+        let comments: Vec<Option<Comment>> = lex_all(source)
+            .unwrap()
+            .into_iter()
+            .map(|l| l.comment)
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                Some(Comment::Note("isn't the end".into())),
+                Some(Comment::Note("".into())),
+                None,
+                None,
+                None,
+                Some(Comment::Semicolon("".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_errors() {
+        let error = |s: &str| lex(s).unwrap_err().to_string();
+        assert_eq!(
+            error("copy 'open x\n"),
+            "line 1, column 13: expected ' to close keyword, found end of line"
+        );
+        assert_eq!(
+            error("noop\ncopy @{1} x\n"),
+            "line 2, column 6: expected @{start,step}, found `@{1}`"
+        );
+        assert_eq!(
+            error("copy @{1,2 x\n"),
+            "line 1, column 6: expected @{start,step}, found `@{1,2`"
+        );
+    }
+}
@@ -1,205 +1,288 @@
-extern crate nom;
-
-mod parts;
-mod preprocess;
-
-use super::vm::instruction::{Instruction, Target};
-use parts::parse_line;
-use preprocess::preprocess_text;
-
-use nom::multi::many0;
-
-pub fn parse_text(i: &str) -> Result<Vec<Instruction>, String> {
-    let text = preprocess_text(i);
-
-    let parsed = many0(parse_line)(&text);
-    let insts = match parsed {
-        Ok(p) => p.1,
-        Err(e) => return Err(e.to_string()),
-    };
-
-    validate_instructions(&insts)?;
-
-    Ok(insts)
-}
-
-fn validate_instructions(insts: &Vec<Instruction>) -> Result<(), String> {
-    for i in insts.iter() {
-        match i {
-            Instruction::Copy(a, b) => validate_targets(&[a, b])?,
-            Instruction::Addi(a, b, c) => validate_targets(&[a, b, c])?,
-            Instruction::Subi(a, b, c) => validate_targets(&[a, b, c])?,
-            Instruction::Muli(a, b, c) => validate_targets(&[a, b, c])?,
-            Instruction::Divi(a, b, c) => validate_targets(&[a, b, c])?,
-            Instruction::Modi(a, b, c) => validate_targets(&[a, b, c])?,
-            Instruction::Swiz(a, b, c) => validate_targets(&[a, b, c])?,
-            Instruction::Test(a, _comp, b) => validate_targets(&[a, b])?,
-            Instruction::Link(a) => validate_targets(&[a])?,
-            Instruction::Host(a) => validate_targets(&[a])?,
-            Instruction::Grab(a) => validate_targets(&[a])?,
-            Instruction::File(a) => validate_targets(&[a])?,
-            Instruction::Seek(a) => validate_targets(&[a])?,
-            Instruction::Rand(a, b, c) => validate_targets(&[a, b, c])?,
-            _ => (),
-        }
-    }
-
-    Ok(())
-}
-
-fn validate_targets(ts: &[&Target]) -> Result<(), String> {
-    let mut found_ms = 0;
-    for t in ts.iter() {
-        match t {
-            Target::Literal(value) => {
-                if *value < -9999 || *value > 9999 {
-                    return Err("literal out of range".into());
-                }
-            }
-            Target::Register(specifier) => {
-                if specifier == "m" {
-                    found_ms += 1;
-                }
-            }
-        }
-    }
-    if found_ms > 1 {
-        return Err("cannot reference M register more than once in one instruction".into());
-    }
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    use super::super::vm::instruction::Target;
-    use super::*;
-
-    #[test]
-    fn test_parse_empty() {
-        assert_eq!(parse_text(""), Ok(vec![]),);
-    }
-
-    #[test]
-    fn test_parse_text() {
-        let s = "LINK 800
-        copy   1    x \t
-
-@rep 2
- addi @{-5,-4} 1 x ; comment
-     @end
-muli 1 0 #nrv
-note we groovin";
-        assert_eq!(
-            parse_text(s),
-            Ok(vec![
-                Instruction::Link(Target::Literal(800)),
-                Instruction::Copy(Target::Literal(1), Target::Register(String::from("x"))),
-                Instruction::Addi(
-                    Target::Literal(-5),
-                    Target::Literal(1),
-                    Target::Register(String::from("x"))
-                ),
-                Instruction::Addi(
-                    Target::Literal(-9),
-                    Target::Literal(1),
-                    Target::Register(String::from("x"))
-                ),
-                Instruction::Muli(
-                    Target::Literal(1),
-                    Target::Literal(0),
-                    Target::Register(String::from("#nrv"))
-                ),
-            ])
-        );
-    }
-
-    #[test]
-    fn test_literal_bounds() {
-        let s = "addi -9999 9999 x\n";
-        assert_eq!(
-            parse_text(s),
-            Ok(vec![Instruction::Addi(
-                Target::Literal(-9999),
-                Target::Literal(9999),
-                Target::Register("x".into()),
-            )])
-        );
-
-        let s = "copy 10000 x\n";
-        assert_eq!(parse_text(s), Err("literal out of range".into()),);
-    }
-
-    #[test]
-    fn test_m_limit() {
-        let s = "copy 1 m\n";
-        assert_eq!(
-            parse_text(s),
-            Ok(vec![Instruction::Copy(
-                Target::Literal(1),
-                Target::Register("m".into()),
-            )])
-        );
-
-        let s = "copy m m\n";
-        assert_eq!(
-            parse_text(s),
-            Err("cannot reference M register more than once in one instruction".into()),
-        );
-    }
-
-    #[test]
-    fn test_mrd() {
-        let s = "test mrd\n noop\n";
-        assert_eq!(
-            parse_text(s),
-            Ok(vec![Instruction::TestMrd, Instruction::Noop])
-        );
-    }
-
-    #[test]
-    fn test_bad_label_parse() {
-        let s = "MODE\n ; INIT STATE\n DATA 0 0 0 0 0 0 0 0\n DATA 0 0 0 0 0 0 0 0\n \n ; INIT 2 RANDOMS\n RAND 0 15 X\n \n SEEK X\n COPY 1 F\n \n MARK INITLOOP\n SEEK -9999\n RAND 0 15 X\n SEEK X\n TEST F = 0\n FJMP INITLOOP\n SEEK -1\n COPY 1 F\n \n ; RENDER BOARD STATE\n COPY 0 X\n SEEK -9999\n \n MARK RENDER\n COPY F T\n REPL SPRITE\n ADDI X 1 X\n TEST EOF\n FJMP RENDER\n COPY 0 X \n JUMP WAIT\n \n MARK SPRITE\n LINK 801\n COPY T CO\n TEST T = 0\n TJMP BLANKSPRITE\n ADDI 327 CO GP\n MARK BLANKSPRITE\n COPY CO T\n MODI X 4 CO\n MULI 25 CO T\n ADDI 5 T GX\n \n DIVI X 4 CO\n MULI 25 CO T\n ADDI 5 T GY\n \n MARK FOREVER\n WAIT\n JUMP FOREVER\n \n MARK WAIT\n DROP\n VOID M\n REPL KILLER\n @REP 17\n COPY T T\n @END\n GRAB 400\n JUMP RENDER\n \n MARK KILLER\n LINK 801\n @REP 16\n KILL\n @END\n HALT\n";
-        let parsed = parse_text(s).unwrap();
-        for i in parsed.iter() {
-            match i {
-                Instruction::Mark(ref label) => {
-                    if label == "killer" {
-                        return;
-                    }
-                }
-                _ => (),
-            }
-        }
-        println!("{:?}", parsed);
-        assert!(false, "killer mark not found");
-    }
-
-    #[test]
-    fn test_hardware_reg() {
-        let s = "link 801\n copy 60 #sqr0\n mark a\n wait\n jump a\n";
-        let parsed = parse_text(s).unwrap();
-        assert_eq!(
-            parsed,
-            vec![
-                Instruction::Link(Target::Literal(801)),
-                Instruction::Copy(Target::Literal(60), Target::Register("#sqr0".into())),
-                Instruction::Mark("a".into()),
-                Instruction::Wait,
-                Instruction::Jump("a".into()),
-            ]
-        )
-    }
-
-    #[test]
-    fn test_mean_macro_comment() {
-        let s = "copy 1 x\n@REP 0;[X] ARGUMENT:\n;-200 SHIFT UP\n; 200 SHIFT DOWN\n;  -2 SHIFT LEFT\n@END  ;   2 SHIFT RIGHT\ncopy 2 t\n";
-        let parsed = parse_text(s).unwrap();
-        assert_eq!(
-            parsed,
-            vec![
-                Instruction::Copy(Target::Literal(1), Target::Register("x".into())),
-                Instruction::Copy(Target::Literal(2), Target::Register("t".into()))
-            ]
-        );
-    }
-}
+extern crate nom;
+
+mod error;
+mod format;
+mod lexer;
+mod parts;
+mod preprocess;
+
+use super::vm::instruction::{Instruction, Span, Target};
+use parts::{diagnose, parse_line};
+use preprocess::{preprocess, SourceLine};
+
+pub use error::ParseError;
+pub use format::{format_text, print_text};
+
+pub fn parse_text(i: &str) -> Result<Vec<Instruction>, ParseError> {
+    let (insts, _) = parse_text_with_spans(i)?;
+    Ok(insts)
+}
+
+/// Parse text, also returning where each instruction came from.
+pub fn parse_text_with_spans(i: &str) -> Result<(Vec<Instruction>, Vec<Span>), ParseError> {
+    let mut insts = vec![];
+    let mut spans = vec![];
+    for line in preprocess(i)? {
+        let inst = parse_source_line(&line)?;
+        validate_instruction(&inst).map_err(|e| ParseError::new(line.span, e))?;
+        insts.push(inst);
+        spans.push(line.span);
+    }
+    Ok((insts, spans))
+}
+
+fn parse_source_line(line: &SourceLine) -> Result<Instruction, ParseError> {
+    let text = format!("{}\n", line.text);
+    if let Ok(("", inst)) = parse_line(&text) {
+        return Ok(inst);
+    }
+
+    let (offset, expected) = diagnose(&line.text);
+    let found = match line.text[offset..].split_whitespace().next() {
+        Some(token) => format!("`{}`", token),
+        None => String::from("end of line"),
+    };
+    let span = Span {
+        column: line.column(offset),
+        ..line.span
+    };
+    Err(ParseError::expected(span, expected, &found))
+}
+
+fn validate_instruction(inst: &Instruction) -> Result<(), String> {
+    match inst {
+        Instruction::Copy(a, b) => validate_targets(&[a, b]),
+        Instruction::Addi(a, b, c) => validate_targets(&[a, b, c]),
+        Instruction::Subi(a, b, c) => validate_targets(&[a, b, c]),
+        Instruction::Muli(a, b, c) => validate_targets(&[a, b, c]),
+        Instruction::Divi(a, b, c) => validate_targets(&[a, b, c]),
+        Instruction::Modi(a, b, c) => validate_targets(&[a, b, c]),
+        Instruction::Swiz(a, b, c) => validate_targets(&[a, b, c]),
+        Instruction::Test(a, _comp, b) => validate_targets(&[a, b]),
+        Instruction::Link(a) => validate_targets(&[a]),
+        Instruction::Host(a) => validate_targets(&[a]),
+        Instruction::Grab(a) => validate_targets(&[a]),
+        Instruction::File(a) => validate_targets(&[a]),
+        Instruction::Seek(a) => validate_targets(&[a]),
+        Instruction::Rand(a, b, c) => validate_targets(&[a, b, c]),
+        _ => Ok(()),
+    }
+}
+
+fn validate_targets(ts: &[&Target]) -> Result<(), String> {
+    let mut found_ms = 0;
+    for t in ts.iter() {
+        match t {
+            Target::Literal(value) => {
+                if *value < -9999 || *value > 9999 {
+                    return Err("literal out of range".into());
+                }
+            }
+            Target::Keyword(_) => (),
+            Target::Register(specifier) => {
+                if specifier == "m" {
+                    found_ms += 1;
+                }
+            }
+        }
+    }
+    if found_ms > 1 {
+        return Err("cannot reference M register more than once in one instruction".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::vm::instruction::Target;
+    use super::*;
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(parse_text(""), Ok(vec![]),);
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let s = "LINK 800
+        copy   1    x \t
+
+@rep 2
+ addi @{-5,-4} 1 x ; comment
+     @end
+muli 1 0 #nrv
+note we groovin";
+        assert_eq!(
+            parse_text(s),
+            Ok(vec![
+                Instruction::Link(Target::Literal(800)),
+                Instruction::Copy(Target::Literal(1), Target::Register(String::from("x"))),
+                Instruction::Addi(
+                    Target::Literal(-5),
+                    Target::Literal(1),
+                    Target::Register(String::from("x"))
+                ),
+                Instruction::Addi(
+                    Target::Literal(-9),
+                    Target::Literal(1),
+                    Target::Register(String::from("x"))
+                ),
+                Instruction::Muli(
+                    Target::Literal(1),
+                    Target::Literal(0),
+                    Target::Register(String::from("#nrv"))
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_literal_bounds() {
+        let s = "addi -9999 9999 x\n";
+        assert_eq!(
+            parse_text(s),
+            Ok(vec![Instruction::Addi(
+                Target::Literal(-9999),
+                Target::Literal(9999),
+                Target::Register("x".into()),
+            )])
+        );
+
+        let s = "noop\n  copy 10000 x\n";
+        assert_eq!(
+            parse_text(s),
+            Err(ParseError::new(Span::new(2, 3), "literal out of range")),
+        );
+    }
+
+    #[test]
+    fn test_m_limit() {
+        let s = "copy 1 m\n";
+        assert_eq!(
+            parse_text(s),
+            Ok(vec![Instruction::Copy(
+                Target::Literal(1),
+                Target::Register("m".into()),
+            )])
+        );
+
+        let s = "copy m m\n";
+        assert_eq!(
+            parse_text(s).unwrap_err().message,
+            "cannot reference M register more than once in one instruction",
+        );
+    }
+
+    #[test]
+    fn test_mrd() {
+        let s = "test mrd\n noop\n";
+        assert_eq!(
+            parse_text(s),
+            Ok(vec![Instruction::TestMrd, Instruction::Noop])
+        );
+    }
+
+    #[test]
+    fn test_bad_label_parse() {
+        let s = "MODE\n ; INIT STATE\n DATA 0 0 0 0 0 0 0 0\n DATA 0 0 0 0 0 0 0 0\n \n ; INIT 2 RANDOMS\n RAND 0 15 X\n \n SEEK X\n COPY 1 F\n \n MARK INITLOOP\n SEEK -9999\n RAND 0 15 X\n SEEK X\n TEST F = 0\n FJMP INITLOOP\n SEEK -1\n COPY 1 F\n \n ; RENDER BOARD STATE\n COPY 0 X\n SEEK -9999\n \n MARK RENDER\n COPY F T\n REPL SPRITE\n ADDI X 1 X\n TEST EOF\n FJMP RENDER\n COPY 0 X \n JUMP WAIT\n \n MARK SPRITE\n LINK 801\n COPY T CO\n TEST T = 0\n TJMP BLANKSPRITE\n ADDI 327 CO GP\n MARK BLANKSPRITE\n COPY CO T\n MODI X 4 CO\n MULI 25 CO T\n ADDI 5 T GX\n \n DIVI X 4 CO\n MULI 25 CO T\n ADDI 5 T GY\n \n MARK FOREVER\n WAIT\n JUMP FOREVER\n \n MARK WAIT\n DROP\n VOID M\n REPL KILLER\n @REP 17\n COPY T T\n @END\n GRAB 400\n JUMP RENDER\n \n MARK KILLER\n LINK 801\n @REP 16\n KILL\n @END\n HALT\n";
+        let parsed = parse_text(s).unwrap();
+        for i in parsed.iter() {
+            match i {
+                Instruction::Mark(ref label) => {
+                    if label == "killer" {
+                        return;
+                    }
+                }
+                _ => (),
+            }
+        }
+        println!("{:?}", parsed);
+        assert!(false, "killer mark not found");
+    }
+
+    #[test]
+    fn test_hardware_reg() {
+        let s = "link 801\n copy 60 #sqr0\n mark a\n wait\n jump a\n";
+        let parsed = parse_text(s).unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                Instruction::Link(Target::Literal(801)),
+                Instruction::Copy(Target::Literal(60), Target::Register("#sqr0".into())),
+                Instruction::Mark("a".into()),
+                Instruction::Wait,
+                Instruction::Jump("a".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn test_mean_macro_comment() {
+        let s = "copy 1 x\n@REP 0;[X] ARGUMENT:\n;-200 SHIFT UP\n; 200 SHIFT DOWN\n;  -2 SHIFT LEFT\n@END  ;   2 SHIFT RIGHT\ncopy 2 t\n";
+        let parsed = parse_text(s).unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                Instruction::Copy(Target::Literal(1), Target::Register("x".into())),
+                Instruction::Copy(Target::Literal(2), Target::Register("t".into()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let s = "noop\n@rep 2\n  copy @{1,1} x ; comment\n@end\n\thalt\n";
+        let (insts, spans) = parse_text_with_spans(s).unwrap();
+        assert_eq!(insts.len(), spans.len());
+        let rep = |rep| Span {
+            line: 3,
+            column: 3,
+            rep: Some(rep),
+        };
+        assert_eq!(
+            spans,
+            vec![Span::new(1, 1), rep(0), rep(1), Span::new(5, 2)]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |s: &str| parse_text(s).unwrap_err().to_string();
+        assert_eq!(
+            error("noop\n  cpy 1 x\n"),
+            "line 2, column 3: expected an instruction, found `cpy`"
+        );
+        assert_eq!(
+            error("copy 1\n"),
+            "line 1, column 7: expected COPY R/N R, found end of line"
+        );
+        assert_eq!(
+            error("test x ! 1\n"),
+            "line 1, column 8: expected TEST R/N =/</> R/N, TEST MRD or TEST EOF, found `!`"
+        );
+        assert_eq!(
+            error("noop\nhalt now ; comment\n"),
+            "line 2, column 6: expected end of line, found `now`"
+        );
+        assert_eq!(
+            error("@rep 3\n copy @{9998,1} x\n@end\n"),
+            "line 2, column 2 (@REP expansion 2): literal out of range"
+        );
+
+        let e = parse_text("copy 1\n").unwrap_err();
+        assert_eq!(e.expected, Some("COPY R/N R".into()));
+    }
+
+    #[test]
+    fn test_parse_rest_of_script() {
+        // Lines after a bad one used to be dropped without complaint
+        assert!(parse_text("copy 1 x\ngarbage\nnoop\n").is_err());
+        assert_eq!(
+            parse_text("copy 1 x\nnoop").unwrap(),
+            vec![
+                Instruction::Copy(Target::Literal(1), Target::Register("x".into())),
+                Instruction::Noop
+            ]
+        );
+    }
+}
@@ -2,15 +2,16 @@ extern crate nom;
 
 use nom::{
     branch::alt,
-    bytes::complete::{tag_no_case, take_while},
+    bytes::complete::{tag, tag_no_case, take_while},
     character::complete::{line_ending, one_of, space0, space1},
     combinator::{map, map_res},
     multi::many1,
-    sequence::{terminated, tuple},
+    sequence::{delimited, terminated, tuple},
     IResult,
 };
 
 use super::super::vm::instruction::{Comparator, Instruction, Label, Target};
+use super::super::vm::value::Value;
 
 fn is_digit_or_sign(c: char) -> bool {
     c.is_digit(10) || c == '+' || c == '-'
@@ -28,6 +29,25 @@ fn parse_literal(i: &str) -> IResult<&str, i32> {
     map_res(take_while(is_digit_or_sign), to_i32)(i)
 }
 
+/// Keywords are wrapped in single quotes and stored uppercase.
+fn parse_keyword(i: &str) -> IResult<&str, String> {
+    map(
+        delimited(
+            tag("'"),
+            take_while(|c: char| c != '\'' && c != '\n'),
+            tag("'"),
+        ),
+        |s: &str| s.to_ascii_uppercase(),
+    )(i)
+}
+
+fn parse_value(i: &str) -> IResult<&str, Value> {
+    alt((
+        map(parse_literal, Value::Number),
+        map(parse_keyword, Value::Keyword),
+    ))(i)
+}
+
 fn parse_register(i: &str) -> IResult<&str, String> {
     map(take_while(is_alphanum_or_hash), |s: &str| {
         s.to_ascii_lowercase()
@@ -45,6 +65,9 @@ fn parse_target(i: &str) -> IResult<&str, Target> {
     if let Ok(parsed) = parse_literal(i) {
         return Ok((parsed.0, Target::Literal(parsed.1)));
     }
+    if let Ok(parsed) = parse_keyword(i) {
+        return Ok((parsed.0, Target::Keyword(parsed.1)));
+    }
 
     parse_register_target(i)
 }
@@ -208,7 +231,7 @@ fn parse_kill(i: &str) -> IResult<&str, Instruction> {
 }
 
 fn parse_host(i: &str) -> IResult<&str, Instruction> {
-    let t = tuple((tag_no_case("kill"), space1, parse_register_target))(i)?;
+    let t = tuple((tag_no_case("host"), space1, parse_register_target))(i)?;
     Ok((t.0, Instruction::Host(t.1 .2)))
 }
 
@@ -294,7 +317,7 @@ fn parse_data(i: &str) -> IResult<&str, Instruction> {
     let t = tuple((
         tag_no_case("data"),
         space1,
-        many1(terminated(parse_literal, space0)),
+        many1(terminated(parse_value, space0)),
     ))(i)?;
     Ok((t.0, Instruction::Data(t.1 .2)))
 }
@@ -333,6 +356,61 @@ pub fn parse_line(i: &str) -> IResult<&str, Instruction> {
     Ok((t.0, t.1 .0))
 }
 
+type Parser = fn(&str) -> IResult<&str, Instruction>;
+
+/// For a line parse_line won't take, how far into the line parsing got
+/// and the syntax that was expected there, in the game's R/N notation.
+/// The line shouldn't include its line ending.
+pub fn diagnose(i: &str) -> (usize, &'static str) {
+    let opcode = i.split_whitespace().next().unwrap_or("");
+    let (parsers, syntax): (&[Parser], &'static str) = match opcode.to_ascii_lowercase().as_str() {
+        "copy" => (&[parse_copy], "COPY R/N R"),
+        "addi" => (&[parse_addi], "ADDI R/N R/N R"),
+        "subi" => (&[parse_subi], "SUBI R/N R/N R"),
+        "muli" => (&[parse_muli], "MULI R/N R/N R"),
+        "divi" => (&[parse_divi], "DIVI R/N R/N R"),
+        "modi" => (&[parse_modi], "MODI R/N R/N R"),
+        "swiz" => (&[parse_swiz], "SWIZ R/N R/N R"),
+        "mark" => (&[parse_mark], "MARK L"),
+        "jump" => (&[parse_jump], "JUMP L"),
+        "tjmp" => (&[parse_tjmp], "TJMP L"),
+        "fjmp" => (&[parse_fjmp], "FJMP L"),
+        "test" => (
+            &[parse_test, parse_test_mrd, parse_test_eof],
+            "TEST R/N =/</> R/N, TEST MRD or TEST EOF",
+        ),
+        "repl" => (&[parse_repl], "REPL L"),
+        "halt" => (&[parse_halt], "HALT"),
+        "kill" => (&[parse_kill], "KILL"),
+        "link" => (&[parse_link], "LINK R/N"),
+        "host" => (&[parse_host], "HOST R"),
+        "mode" => (&[parse_mode], "MODE"),
+        "void" => (&[parse_void_m, parse_void_f], "VOID M or VOID F"),
+        "make" => (&[parse_make], "MAKE"),
+        "grab" => (&[parse_grab], "GRAB R/N"),
+        "file" => (&[parse_file], "FILE R"),
+        "seek" => (&[parse_seek], "SEEK R/N"),
+        "drop" => (&[parse_drop], "DROP"),
+        "wipe" => (&[parse_wipe], "WIPE"),
+        "noop" => (&[parse_noop], "NOOP"),
+        "rand" => (&[parse_rand], "RAND R/N R/N R"),
+        "wait" => (&[parse_wait], "WAIT"),
+        "data" => (&[parse_data], "DATA N/K..."),
+        _ => return (i.len() - i.trim_start().len(), "an instruction"),
+    };
+
+    let mut furthest = 0;
+    for parser in parsers {
+        let reached = match parser(i) {
+            Ok((rest, _)) => return (i.len() - rest.trim_start().len(), "end of line"),
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => i.len() - e.input.len(),
+            Err(nom::Err::Incomplete(_)) => i.len(),
+        };
+        furthest = furthest.max(reached);
+    }
+    (furthest, syntax)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -376,6 +454,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_keyword() {
+        assert_eq!(parse_keyword("'ok' x"), Ok((" x", String::from("OK"))));
+        assert_eq!(
+            parse_keyword("'Two Words'"),
+            Ok(("", String::from("TWO WORDS")))
+        );
+        assert!(parse_keyword("'unterminated\n'").is_err());
+        assert!(parse_keyword("ok").is_err());
+
+        assert_eq!(
+            parse_target("'core' ok"),
+            Ok((" ok", Target::Keyword(String::from("CORE")))),
+        );
+    }
+
+    #[test]
+    fn test_host() {
+        assert_eq!(
+            parse_line("HOST X\nok"),
+            Ok(("ok", Instruction::Host(Target::Register(String::from("x")))))
+        );
+        assert!(parse_line("host 'core'\n").is_err());
+    }
+
     #[test]
     fn test_label() {
         assert_eq!(
@@ -431,11 +534,21 @@ mod tests {
     fn test_data() {
         assert_eq!(
             parse_data("data 1\n"),
-            Ok(("\n", Instruction::Data(vec![1]))),
+            Ok(("\n", Instruction::Data(vec![Value::Number(1)]))),
         );
         assert_eq!(
             parse_data("data 1 2 3\n"),
-            Ok(("\n", Instruction::Data(vec![1, 2, 3]))),
+            Ok((
+                "\n",
+                Instruction::Data(vec![Value::Number(1), Value::Number(2), Value::Number(3)])
+            )),
+        );
+        assert_eq!(
+            parse_data("data 'eof' -1\n"),
+            Ok((
+                "\n",
+                Instruction::Data(vec![Value::Keyword("EOF".into()), Value::Number(-1)])
+            )),
         );
     }
 
@@ -478,6 +591,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_diagnose() {
+        assert_eq!(diagnose("cpy 1 x"), (0, "an instruction"));
+        assert_eq!(diagnose("copy 1"), (6, "COPY R/N R"));
+        assert_eq!(
+            diagnose("test x ! 1"),
+            (7, "TEST R/N =/</> R/N, TEST MRD or TEST EOF")
+        );
+        assert_eq!(diagnose("void x"), (5, "VOID M or VOID F"));
+        assert_eq!(diagnose("halt   now"), (7, "end of line"));
+    }
+
     #[test]
     fn test_label_with_underscore() {
         assert_eq!(
@@ -1,178 +1,440 @@
-use regex::Regex;
+use std::convert::TryFrom;
+use std::fmt::Write;
 
-fn strip_whitespace(i: &str) -> String {
-    let leading = Regex::new(r"(?m)^\s+").unwrap();
-    let replaced = leading.replace_all(i, "");
+use super::super::vm::instruction::Span;
+use super::error::ParseError;
+use super::lexer::{lex, Line, Piece};
 
-    let trailing = Regex::new(r"(?m)[[:blank:]]+$").unwrap();
-    trailing.replace_all(&replaced, "").to_owned().to_string()
+/// A line of source with comments and extra whitespace taken off and
+/// @{...} expanded, along with where it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLine {
+    /// The line's tokens, separated by single spaces.
+    pub text: String,
+    pub span: Span,
+    /// Where each token starts in text, and its column in the source.
+    starts: Vec<(usize, usize)>,
 }
 
-fn strip_comments(i: &str) -> String {
-    let inline = Regex::new(r"(?m);.*$").unwrap();
-    let replaced = inline.replace_all(i, "");
-
-    let note = Regex::new(r"(?mi)note.*$").unwrap();
-    note.replace_all(&replaced, "").to_owned().to_string()
+impl SourceLine {
+    /// Source column of the character at offset in text.
+    pub fn column(&self, offset: usize) -> usize {
+        let (start, column) = self
+            .starts
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= offset)
+            .copied()
+            .unwrap_or((0, self.span.column));
+        column + self.text[start..offset].chars().count()
+    }
 }
 
-fn strip_empty_lines(i: &str) -> String {
-    let empty = Regex::new(r"(?ms)^[[:blank:]]*\n").unwrap();
-    empty.replace_all(i, "").to_owned().to_string()
-}
+/// Most lines @REP blocks can expand a script to, so a few short lines
+/// can't ask for a program too big to hold.
+const MAX_LINES: usize = 10_000;
 
-fn expand_macros(i: &str) -> String {
-    let macros =
-        Regex::new(r"(?is)@rep[[:blank:]]+(\d+)[[:blank:]]*\n(.*?)[[:blank:]]?@end[[:blank:]]*\n")
-            .unwrap();
-    let expansions = Regex::new(r"@\{(-?\d+),(-?\d+)\}").unwrap();
+enum Block {
+    Line(Line),
+    Rep {
+        count: usize,
+        body: Vec<Block>,
+        span: Span,
+        /// How many lines the block expands to, saturating.
+        lines: usize,
+    },
+}
 
-    let mut out = String::with_capacity(i.len());
+impl Block {
+    fn lines(&self) -> usize {
+        match self {
+            Block::Line(_) => 1,
+            Block::Rep { lines, .. } => *lines,
+        }
+    }
+}
 
-    let mut furthest_read: usize = 0;
+/// Break source into the lines that hold instructions, with @REP blocks
+/// expanded. Blocks can be nested, in which case @{...} counts expansions
+/// of the innermost block it's in.
+pub fn preprocess(i: &str) -> Result<Vec<SourceLine>, ParseError> {
+    let blocks = group(&mut lex(i)?.into_iter(), None)?;
+    let mut lines = vec![];
+    expand(&blocks, None, &mut lines)?;
+    Ok(lines)
+}
 
-    for caps in macros.captures_iter(i) {
-        out.push_str(&i[furthest_read..caps.get(0).unwrap().start()]);
+/// Read lines up to the @END of the @REP block opened at open, or to the
+/// end of the script at the top level, grouping any @REP blocks on the way.
+fn group(
+    lines: &mut impl Iterator<Item = Line>,
+    open: Option<Span>,
+) -> Result<Vec<Block>, ParseError> {
+    let mut blocks = vec![];
+    while let Some(line) = lines.next() {
+        if line.tokens[0].is("@rep") {
+            let count = match line.tokens.get(1).and_then(|t| t.text()) {
+                Some(count) => count.parse().ok(),
+                None => None,
+            };
+            let count = match count {
+                Some(count) => count,
+                None => return Err(expected_at(&line, 1, "a repeat count")),
+            };
+            if line.tokens.len() > 2 {
+                return Err(expected_at(&line, 2, "end of line"));
+            }
+            let body = group(lines, Some(line.span()))?;
+            let body_lines = body
+                .iter()
+                .fold(0, |n: usize, b| n.saturating_add(b.lines()));
+            blocks.push(Block::Rep {
+                count,
+                body,
+                span: line.span(),
+                lines: count.saturating_mul(body_lines),
+            });
+        } else if line.tokens[0].is("@end") {
+            if line.tokens.len() > 1 {
+                return Err(expected_at(&line, 1, "end of line"));
+            }
+            return match open {
+                Some(_) => Ok(blocks),
+                None => Err(ParseError::new(line.span(), "@END without @REP")),
+            };
+        } else {
+            blocks.push(Block::Line(line));
+        }
+    }
 
-        let reps: i16 = caps.get(1).unwrap().as_str().parse().unwrap();
+    match open {
+        Some(span) => Err(ParseError::expected(
+            span,
+            "@END to close @REP",
+            "end of script",
+        )),
+        None => Ok(blocks),
+    }
+}
 
-        let raw_body = caps.get(2).unwrap().as_str();
-        let raw_expansions: Vec<(&str, i16, i16)> = expansions
-            .captures_iter(raw_body)
-            .map(|c| {
-                (
-                    c.get(0).unwrap().as_str(),
-                    c.get(1).unwrap().as_str().parse().unwrap(),
-                    c.get(2).unwrap().as_str().parse().unwrap(),
-                )
-            })
-            .collect();
+fn expected_at(line: &Line, index: usize, expected: &str) -> ParseError {
+    let found = match line.tokens.get(index).and_then(|t| t.text()) {
+        Some(text) => format!("`{}`", text),
+        None if index < line.tokens.len() => String::from("`@{...}`"),
+        None => String::from("end of line"),
+    };
+    ParseError::expected(line.span_at(index), expected, &found)
+}
 
-        for iteration in 0..reps {
-            let mut replaced_body = raw_body.to_string();
-            for exp in &raw_expansions {
-                let value = exp.1 + (iteration * exp.2);
-                replaced_body = replaced_body.replace(exp.0, &value.to_string());
+/// Write out blocks as lines, where rep is the expansion of the innermost
+/// @REP block being written, if any.
+fn expand(
+    blocks: &[Block],
+    rep: Option<usize>,
+    out: &mut Vec<SourceLine>,
+) -> Result<(), ParseError> {
+    for block in blocks {
+        match block {
+            Block::Line(line) => out.push(render(line, rep)?),
+            // Skipping these also keeps nested empty blocks from spinning
+            Block::Rep { lines: 0, .. } => (),
+            Block::Rep {
+                count,
+                body,
+                span,
+                lines,
+            } => {
+                if out.len().saturating_add(*lines) > MAX_LINES {
+                    let message = format!("@REP expands to more than {} lines", MAX_LINES);
+                    return Err(ParseError::new(*span, message));
+                }
+                for iteration in 0..*count {
+                    expand(body, Some(iteration), out)?;
+                }
             }
-
-            out.push_str(&replaced_body);
         }
-
-        furthest_read = caps.get(0).unwrap().end();
     }
-
-    // Add everything past the last macro match
-    out.push_str(&i[furthest_read..]);
-    out
+    Ok(())
 }
 
-pub fn preprocess_text(i: &str) -> String {
-    let mut out = strip_whitespace(i);
-    out = strip_comments(&out);
-    out = strip_empty_lines(&out);
-    expand_macros(&out)
+/// Put a line's tokens back together, with @{...} filled in for the
+/// given expansion of its @REP block.
+pub fn render(line: &Line, rep: Option<usize>) -> Result<SourceLine, ParseError> {
+    let mut text = String::new();
+    let mut starts = vec![];
+    for token in line.tokens.iter() {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        starts.push((text.len(), token.column));
+        for piece in token.pieces.iter() {
+            match (piece, rep) {
+                (Piece::Text(t), _) => text.push_str(t),
+                (Piece::Expansion { start, step }, Some(n)) => {
+                    let value = i32::try_from(n)
+                        .ok()
+                        .and_then(|n| step.checked_mul(n))
+                        .and_then(|offset| start.checked_add(offset));
+                    match value {
+                        Some(value) => write!(text, "{}", value).unwrap(),
+                        None => {
+                            let span = Span {
+                                rep,
+                                ..Span::new(line.number, token.column)
+                            };
+                            return Err(ParseError::new(span, "literal out of range"));
+                        }
+                    }
+                }
+                (Piece::Expansion { .. }, None) => {
+                    let span = Span::new(line.number, token.column);
+                    return Err(ParseError::new(span, "@{...} can only be used inside @REP"));
+                }
+            }
+        }
+    }
+    Ok(SourceLine {
+        text,
+        span: Span { rep, ..line.span() },
+        starts,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn texts(i: &str) -> Vec<String> {
+        let lines = preprocess(i).expect("failed to preprocess");
+        lines.into_iter().map(|l| l.text).collect()
+    }
+
+    fn error(i: &str) -> String {
+        preprocess(i)
+            .expect_err("should not preprocess")
+            .to_string()
+    }
+
     #[test]
     fn test_strip_whitespace() {
-        assert_eq!(strip_whitespace(" t "), String::from("t"));
+        assert_eq!(texts(" t "), vec!["t"]);
+        assert_eq!(texts(" \t\tcontent  ok   \t"), vec!["content ok"]);
         assert_eq!(
-            strip_whitespace(" \t\tcontent ok   \t"),
-            String::from("content ok")
-        );
-        assert_eq!(
-            strip_whitespace(" line 1\n\tline two   \nline three"),
-            String::from("line 1\nline two\nline three")
+            texts(" line 1\n\tline two   \nline three"),
+            vec!["line 1", "line two", "line three"]
         );
+        assert_eq!(texts("copy ' a  b ' x"), vec!["copy ' a  b ' x"]);
     }
 
     #[test]
     fn test_strip_comments() {
+        assert_eq!(texts("nothing to see here\n"), vec!["nothing to see here"]);
+        assert_eq!(texts("nothing to ;see here\n"), vec!["nothing to"]);
+        assert_eq!(texts("nothing to;;;see here\n"), vec!["nothing to"]);
+        assert!(texts("; full line comment \n").is_empty());
+        assert!(texts("NOTE nothing to see here \n").is_empty());
+        assert!(texts("  note ; also nothing\n").is_empty());
+    }
+
+    // NOTE is only a comment where an instruction would go, so it used to
+    // eat the end of lines like these
+    #[test]
+    fn test_note_in_words() {
         assert_eq!(
-            strip_comments("nothing to see here\n"),
-            String::from("nothing to see here\n")
+            texts("mark denote\njump NOTEPAD\ncopy 'note' x\nkeep note inline\n"),
+            vec![
+                "mark denote",
+                "jump NOTEPAD",
+                "copy 'note' x",
+                "keep note inline"
+            ]
         );
+    }
+
+    #[test]
+    fn test_strip_empty_lines() {
         assert_eq!(
-            strip_comments("nothing to ;see here\n"),
-            String::from("nothing to \n")
+            texts("  some bullshit\n\n\n  ok\n"),
+            vec!["some bullshit", "ok"]
         );
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        assert_eq!(texts("nothing\nto expand\n"), vec!["nothing", "to expand"]);
+
         assert_eq!(
-            strip_comments("nothing to;;;see here\n"),
-            String::from("nothing to\n")
+            texts("header\n@rep 5\nbooya\n@end\n"),
+            vec!["header", "booya", "booya", "booya", "booya", "booya"],
         );
-        assert_eq!(strip_comments("; full line comment \n"), String::from("\n"));
+
         assert_eq!(
-            strip_comments("NOTE nothing to see here \n"),
-            String::from("\n")
+            texts("@rep 2\nlink @{3,5}\ncopy @{1,2} x\n@end\n"),
+            vec!["link 3", "copy 1 x", "link 8", "copy 3 x"],
         );
+
         assert_eq!(
-            strip_comments("can also note inline\n"),
-            String::from("can also \n")
+            texts("@rep 2\nlink @{-1,-4}\ncopy @{-5,3} x\n@end\n"),
+            vec!["link -1", "copy -5 x", "link -5", "copy -2 x"],
         );
     }
 
     #[test]
-    fn test_strip_empty_lines() {
+    fn test_multiple_macros() {
         assert_eq!(
-            strip_empty_lines("  some bullshit\n\n\n  ok\n"),
-            String::from("  some bullshit\n  ok\n")
-        );
+            texts("@rep 2\nnoop\n@end\n@rep 2\ncopy 1 x\n@end\n"),
+            vec!["noop", "noop", "copy 1 x", "copy 1 x"],
+        )
     }
 
     #[test]
-    fn test_expand_macros() {
+    fn test_macro_expand_value_multiple_digits() {
         assert_eq!(
-            expand_macros("nothing\nto expand\n"),
-            String::from("nothing\nto expand\n"),
+            texts("@rep 2\ncopy @{20,40} x\n @end\n"),
+            vec!["copy 20 x", "copy 60 x"],
         );
+    }
 
+    #[test]
+    fn test_expand_in_tokens() {
         assert_eq!(
-            expand_macros("header\n@rep 5\nbooya\n@end\n"),
-            String::from("header\nbooya\nbooya\nbooya\nbooya\nbooya\n"),
+            texts("@REP 2\nmark l@{0,1}\ncopy '@{0,1}' x\n@END\n"),
+            vec!["mark l0", "copy '@{0,1}' x", "mark l1", "copy '@{0,1}' x"],
         );
+    }
 
+    #[test]
+    fn test_nested_rep() {
         assert_eq!(
-            expand_macros("@rep 2\nlink @{3,5}\ncopy @{1,2} x\n@end\n"),
-            String::from("link 3\ncopy 1 x\nlink 8\ncopy 3 x\n"),
+            texts("@rep 2\ncopy @{1,1} x\n  @rep 3\n  addi @{0,10} x x\n  @end\n@end\nhalt\n"),
+            vec![
+                "copy 1 x",
+                "addi 0 x x",
+                "addi 10 x x",
+                "addi 20 x x",
+                "copy 2 x",
+                "addi 0 x x",
+                "addi 10 x x",
+                "addi 20 x x",
+                "halt"
+            ],
         );
+        assert_eq!(texts("@rep 0\n@rep 5\nnoop\n@end\n@end\n").len(), 0);
+    }
+
+    // Apparently you can use @REP 0 to easily disable code blocks
+    #[test]
+    fn test_rep_0() {
+        assert_eq!(texts("@rep 0\ncopy 1 x\n@end\nnoop\n"), vec!["noop"])
+    }
+
+    #[test]
+    fn test_rep_0_no_contents() {
+        assert!(texts("@rep 0\n@end\n").is_empty())
+    }
 
+    #[test]
+    fn test_spans() {
+        let text =
+            "copy 1 x\n\n; comment\n@rep 2\n  addi x 1 x\nnote hi\n\tsubi x 1 x\n@end\nhalt\n";
+        let spans: Vec<Span> = preprocess(text)
+            .unwrap()
+            .into_iter()
+            .map(|l| l.span)
+            .collect();
+        let rep = |line, column, rep| Span {
+            line,
+            column,
+            rep: Some(rep),
+        };
         assert_eq!(
-            expand_macros("@rep 2\nlink @{-1,-4}\ncopy @{-5,3} x\n@end\n"),
-            String::from("link -1\ncopy -5 x\nlink -5\ncopy -2 x\n"),
+            spans,
+            vec![
+                Span::new(1, 1),
+                rep(5, 3, 0),
+                rep(7, 2, 0),
+                rep(5, 3, 1),
+                rep(7, 2, 1),
+                Span::new(9, 1),
+            ]
         );
     }
 
     #[test]
-    fn test_multiple_macros() {
-        assert_eq!(
-            expand_macros("@rep 2\nnoop\n@end\n@rep 2\ncopy 1 x\n@end\n"),
-            String::from("noop\nnoop\ncopy 1 x\ncopy 1 x\n"),
-        )
+    fn test_columns() {
+        let lines = preprocess("@rep 2\n  copy   @{100,1}\tx\n@end\n").unwrap();
+        let line = &lines[1];
+        assert_eq!(line.text, "copy 101 x");
+        assert_eq!(line.column(0), 3);
+        assert_eq!(line.column(5), 10);
+        assert_eq!(line.column(9), 19);
+        assert_eq!(line.column(10), 20);
     }
 
     #[test]
-    fn test_macro_expand_value_multiple_digits() {
+    fn test_rep_errors() {
+        assert_eq!(
+            error("noop\n  @end\n"),
+            "line 2, column 3: @END without @REP"
+        );
+        assert_eq!(
+            error("@rep 1\nnoop\n@end\n@end\n"),
+            "line 4, column 1: @END without @REP"
+        );
+        assert_eq!(
+            error("noop\n@rep 2\nnoop\n"),
+            "line 2, column 1: expected @END to close @REP, found end of script"
+        );
+        assert_eq!(
+            error("@rep 2\n@rep 2\nnoop\n@end\n"),
+            "line 1, column 1: expected @END to close @REP, found end of script"
+        );
+        assert_eq!(
+            error("@rep x\nnoop\n@end\n"),
+            "line 1, column 6: expected a repeat count, found `x`"
+        );
+        assert_eq!(
+            error("@rep\nnoop\n@end\n"),
+            "line 1, column 5: expected a repeat count, found end of line"
+        );
+        assert_eq!(
+            error("@rep 2 3\nnoop\n@end\n"),
+            "line 1, column 8: expected end of line, found `3`"
+        );
+        assert_eq!(
+            error("@rep 1\n@end now\n"),
+            "line 2, column 6: expected end of line, found `now`"
+        );
         assert_eq!(
-            expand_macros("@rep 2\ncopy @{20,40} x\n @end\n"),
-            String::from("copy 20 x\ncopy 60 x\n"),
+            error("noop\n copy @{1,1} x\n"),
+            "line 2, column 7: @{...} can only be used inside @REP"
         );
     }
 
-    // Apparently you can use @REP 0 to easily disable code blocks
     #[test]
-    fn test_rep_0() {
+    fn test_expansion_overflow() {
         assert_eq!(
-            expand_macros("@rep 0\ncopy 1 x\n@end\nnoop\n"),
-            String::from("noop\n"),
-        )
+            error("@rep 3\ncopy @{2000000000,2000000000} x\n@end\n"),
+            "line 2, column 6 (@REP expansion 1): literal out of range"
+        );
+        assert_eq!(
+            error("@rep 2\ncopy @{-2147483648,-1} x\n@end\n"),
+            "line 2, column 6 (@REP expansion 1): literal out of range"
+        );
     }
 
     #[test]
-    fn test_rep_0_no_contents() {
-        assert_eq!(expand_macros("@rep 0\n@end\n"), String::from(""),)
+    fn test_rep_limit() {
+        assert_eq!(texts("@rep 10000\nnoop\n@end\n").len(), 10_000);
+        assert_eq!(
+            error("noop\n@rep 10000\nnoop\n@end\n"),
+            "line 2, column 1: @REP expands to more than 10000 lines"
+        );
+        assert_eq!(
+            error("@rep 2\n  @rep 99999999999\n  noop\n  @end\n@end\n"),
+            "line 1, column 1: @REP expands to more than 10000 lines"
+        );
+        // Nothing to expand, however many times
+        assert!(texts("@rep 99999\n@rep 99999\n@rep 99999\n@end\n@end\n@end\n").is_empty());
     }
 }
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use itertools::Itertools;
+
+use super::super::vm::file::File;
+use super::super::vm::value::Value;
+use super::super::vm::VM;
+
+/// A condition the network has to be in for a test run to pass.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Goal {
+    /// A file with exactly these contents sits in host. If id is set, it
+    /// has to be that particular file.
+    File {
+        host: String,
+        id: Option<i32>,
+        contents: Vec<Value>,
+    },
+    /// A hardware register in host holds value.
+    Register {
+        host: String,
+        register: String,
+        value: Value,
+    },
+    /// Every EXA has halted or been killed.
+    AllExasHalted,
+    /// Every file the EXAs made has been wiped, apart from the ones
+    /// asked for by File goals.
+    NoFilesLeftBehind,
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Goal::File {
+                host,
+                id: Some(id),
+                contents,
+            } => write!(
+                f,
+                "file {} in {} contains [{}]",
+                id,
+                host,
+                contents.iter().join(", ")
+            ),
+            Goal::File { host, contents, .. } => {
+                write!(
+                    f,
+                    "file in {} contains [{}]",
+                    host,
+                    contents.iter().join(", ")
+                )
+            }
+            Goal::Register {
+                host,
+                register,
+                value,
+            } => write!(f, "{} in {} is {}", register, host, value),
+            Goal::AllExasHalted => write!(f, "all exas halted"),
+            Goal::NoFilesLeftBehind => write!(f, "no files left behind"),
+        }
+    }
+}
+
+/// Checks goals against a VM. start_files are the ids of the files that
+/// were in the network before any EXA ran.
+pub struct GoalChecker<'g> {
+    goals: &'g [Goal],
+    start_files: HashSet<i32>,
+}
+
+impl<'g> GoalChecker<'g> {
+    pub fn new(goals: &'g [Goal], vm: &VM) -> GoalChecker<'g> {
+        let start_files = vm
+            .hosts
+            .values()
+            .flat_map(|h| h.files.iter().map(|f| f.id))
+            .collect();
+        GoalChecker { goals, start_files }
+    }
+
+    /// Return the first goal that doesn't hold, along with the reason.
+    pub fn first_failure(&self, vm: &VM) -> Option<(&'g Goal, String)> {
+        for goal in self.goals.iter() {
+            if let Err(reason) = self.check(goal, vm) {
+                return Some((goal, reason));
+            }
+        }
+        None
+    }
+
+    pub fn check(&self, goal: &Goal, vm: &VM) -> Result<(), String> {
+        match goal {
+            Goal::File { host, id, contents } => {
+                let h = match vm.hosts.get(host) {
+                    Some(h) => h,
+                    None => return Err(format!("unknown host {}", host)),
+                };
+                let found = h
+                    .files
+                    .iter()
+                    .any(|f| id.is_none_or(|id| id == f.id) && f.contents == *contents);
+                if found {
+                    return Ok(());
+                }
+                match id.and_then(|id| h.files.iter().find(|f| f.id == id)) {
+                    Some(f) => Err(format!(
+                        "file {} in {} contains [{}]",
+                        f.id,
+                        host,
+                        f.contents.iter().join(", ")
+                    )),
+                    None => Err(format!("no matching file in {}", host)),
+                }
+            }
+            Goal::Register {
+                host,
+                register,
+                value,
+            } => {
+                let h = match vm.hosts.get(host) {
+                    Some(h) => h,
+                    None => return Err(format!("unknown host {}", host)),
+                };
+                match h.registers.get(&register.to_ascii_lowercase()) {
+                    Some(r) if r.peek() == *value => Ok(()),
+                    Some(r) => Err(format!("{} in {} is {}", register, host, r.peek())),
+                    None => Err(format!("unknown register {} in {}", register, host)),
+                }
+            }
+            Goal::AllExasHalted => {
+                let running = vm.exas.iter().filter(|e| !e.is_fatal()).count();
+                if running == 0 {
+                    Ok(())
+                } else {
+                    Err(format!("{} exas still running", running))
+                }
+            }
+            Goal::NoFilesLeftBehind => {
+                for exa in vm.exas.iter() {
+                    if let Some(f) = &exa.file {
+                        if !self.start_files.contains(&f.id) {
+                            return Err(format!("file {} held by {}", f.id, exa.name));
+                        }
+                    }
+                }
+                for host in vm.hosts.values().sorted() {
+                    for f in host.files.iter() {
+                        if !self.start_files.contains(&f.id) && !self.wanted(&host.name, f) {
+                            return Err(format!("file {} left in {}", f.id, host.name));
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn wanted(&self, host_name: &str, file: &File) -> bool {
+        self.goals.iter().any(|g| match g {
+            Goal::File { host, id, contents } => {
+                host == host_name && id.is_none_or(|id| id == file.id) && *contents == file.contents
+            }
+            _ => false,
+        })
+    }
+}
@@ -0,0 +1,229 @@
+//! EXAPUNKS-style puzzles. A puzzle generates randomized test cases,
+//! each of which is a network plus the goals a solution has to reach in
+//! it. The Runner plays a solution through many cases and reports the
+//! first goal that failed in each.
+
+mod goal;
+mod score;
+
+use std::fmt;
+
+use super::network::{ExaSpec, Network};
+
+pub use goal::{Goal, GoalChecker};
+pub use score::{solution_size, Histogram, Score};
+
+/// A single test run: the network the solution starts in, the host its
+/// EXAs are placed in, and the goals it has to reach.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestCase {
+    pub network: Network,
+    pub start_host: String,
+    pub goals: Vec<Goal>,
+}
+
+/// Produces randomized test cases. Anything that builds a TestCase
+/// from an RNG works, so closures can be used directly.
+pub trait Puzzle {
+    fn generate(&self, rng: &fastrand::Rng) -> TestCase;
+}
+
+impl<F> Puzzle for F
+where
+    F: Fn(&fastrand::Rng) -> TestCase,
+{
+    fn generate(&self, rng: &fastrand::Rng) -> TestCase {
+        self(rng)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Failure {
+    /// The network could not be built with the solution in it.
+    Setup(String),
+    /// A goal did not hold when the run ended. timed_out is set if the
+    /// run was cut off by the cycle limit.
+    Goal {
+        goal: Goal,
+        reason: String,
+        timed_out: bool,
+    },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Setup(e) => write!(f, "setup failed: {}", e),
+            Failure::Goal {
+                goal,
+                reason,
+                timed_out,
+            } => {
+                write!(f, "goal \"{}\" failed: {}", goal, reason)?;
+                if *timed_out {
+                    write!(f, " (timed out)")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaseResult {
+    pub index: usize,
+    /// Seed the test case was generated from.
+    pub seed: u64,
+    pub cycles: u32,
+    /// LINKs and KILLs executed during the run.
+    pub activity: u32,
+    pub failure: Option<Failure>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// Instructions in the solution after macro expansion, or None if it
+    /// doesn't parse, in which case every run fails to set up.
+    pub size: Option<usize>,
+    pub cases: Vec<CaseResult>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.cases.iter().all(|c| c.passed())
+    }
+
+    pub fn first_failure(&self) -> Option<&CaseResult> {
+        self.cases.iter().find(|c| !c.passed())
+    }
+
+    /// Score the solution across every run. Like the game, a solution
+    /// only gets a score once it passes all of them.
+    pub fn score(&self) -> Option<Score> {
+        if self.cases.is_empty() || !self.passed() {
+            return None;
+        }
+        Some(Score {
+            size: self.size?,
+            cycles: self.cases.iter().map(|c| c.cycles).collect(),
+            activity: self.cases.iter().map(|c| c.activity).collect(),
+        })
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let passed = self.cases.iter().filter(|c| c.passed()).count();
+        write!(f, "{} / {} test runs passed", passed, self.cases.len())?;
+        if let Some(score) = self.score() {
+            write!(f, "\n{}", score)?;
+        }
+        if let Some(case) = self.first_failure() {
+            write!(
+                f,
+                "\nrun {} (seed {}): {}",
+                case.index,
+                case.seed,
+                case.failure.as_ref().unwrap()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Validates solutions the way EXAPUNKS does, over a batch of randomized
+/// runs. Runs are reproducible: the same seed generates the same cases.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Runner {
+    pub runs: usize,
+    pub seed: u64,
+    pub max_cycles: u32,
+}
+
+impl Runner {
+    pub fn new(seed: u64) -> Runner {
+        Runner {
+            runs: 100,
+            seed,
+            max_cycles: 10000,
+        }
+    }
+
+    pub fn run(&self, puzzle: &dyn Puzzle, solution: &[ExaSpec]) -> Report {
+        let cases = (0..self.runs)
+            .map(|index| {
+                let seed = self.seed.wrapping_add(index as u64);
+                let rng = fastrand::Rng::with_seed(seed);
+                let mut case = puzzle.generate(&rng);
+                if case.network.seed.is_none() {
+                    case.network.seed = Some(rng.u64(..));
+                }
+
+                let mut result = self.run_case(&case, solution);
+                result.index = index;
+                result.seed = seed;
+                result
+            })
+            .collect();
+        Report {
+            size: solution_size(solution).ok(),
+            cases,
+        }
+    }
+
+    /// Run the solution in a single test case. The run ends as soon as
+    /// every goal holds, once all EXAs are gone, or at the cycle limit.
+    pub fn run_case(&self, case: &TestCase, solution: &[ExaSpec]) -> CaseResult {
+        let mut result = CaseResult {
+            index: 0,
+            seed: 0,
+            cycles: 0,
+            activity: 0,
+            failure: None,
+        };
+
+        let mut network = case.network.clone();
+        match network.hosts.iter_mut().find(|h| h.name == case.start_host) {
+            Some(host) => host.exas.extend(solution.iter().cloned()),
+            None => {
+                result.failure = Some(Failure::Setup(format!(
+                    "unknown start host {}",
+                    case.start_host
+                )));
+                return result;
+            }
+        }
+        let mut vm = match network.build() {
+            Ok(vm) => vm,
+            Err(e) => {
+                result.failure = Some(Failure::Setup(e.to_string()));
+                return result;
+            }
+        };
+
+        let checker = GoalChecker::new(&case.goals, &vm);
+        loop {
+            let failure = checker.first_failure(&vm);
+            let timed_out = vm.cycle >= self.max_cycles;
+            if failure.is_none() || vm.exas.is_empty() || timed_out {
+                result.cycles = vm.cycle;
+                result.activity = vm.links_traversed + vm.kills;
+                result.failure = failure.map(|(goal, reason)| Failure::Goal {
+                    goal: goal.clone(),
+                    reason,
+                    timed_out,
+                });
+                return result;
+            }
+
+            vm.run_cycle();
+            vm.clean_up_exas();
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::iter::FromIterator;
+
+use super::super::network::ExaSpec;
+use super::super::parse::parse_text;
+use super::super::vm::instruction::Instruction;
+
+/// How many runs ended up with each value, like the histograms shown
+/// after solving a puzzle in the game.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    counts: BTreeMap<u32, usize>,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram::default()
+    }
+
+    pub fn add(&mut self, value: u32) {
+        *self.counts.entry(value).or_insert(0) += 1;
+    }
+
+    pub fn runs(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    pub fn min(&self) -> Option<u32> {
+        self.counts.keys().next().copied()
+    }
+
+    pub fn max(&self) -> Option<u32> {
+        self.counts.keys().next_back().copied()
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        let runs = self.runs();
+        if runs == 0 {
+            return None;
+        }
+        let total: f64 = self
+            .counts
+            .iter()
+            .map(|(value, count)| *value as f64 * *count as f64)
+            .sum();
+        Some(total / runs as f64)
+    }
+
+    /// Each value seen along with the number of runs that had it,
+    /// lowest value first.
+    pub fn counts(&self) -> impl Iterator<Item = (u32, usize)> + '_ {
+        self.counts.iter().map(|(value, count)| (*value, *count))
+    }
+}
+
+impl FromIterator<u32> for Histogram {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Histogram {
+        let mut histogram = Histogram::new();
+        for value in iter {
+            histogram.add(value);
+        }
+        histogram
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.min(), self.max(), self.mean()) {
+            (Some(min), Some(max), _) if min == max => write!(f, "{}", min),
+            (Some(min), Some(max), Some(mean)) => {
+                write!(f, "{}-{} (mean {:.1})", min, max, mean)
+            }
+            _ => write!(f, "-"),
+        }
+    }
+}
+
+/// A solution's score over every test run. Size is the same for every
+/// run, cycles and activity are collected per run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Score {
+    pub size: usize,
+    pub cycles: Histogram,
+    pub activity: Histogram,
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cycles {}, size {}, activity {}",
+            self.cycles, self.size, self.activity
+        )
+    }
+}
+
+/// Count the instructions in a solution's EXAs after macros have been
+/// expanded. MARKs and NOTEs don't take up any space.
+pub fn solution_size(solution: &[ExaSpec]) -> Result<usize, String> {
+    let mut size = 0;
+    for exa in solution.iter() {
+        let mut script = exa.script.clone();
+        if !script.ends_with('\n') {
+            script.push('\n');
+        }
+        let insts = parse_text(&script).map_err(|e| format!("exa {}: {}", exa.name, e))?;
+        size += insts
+            .iter()
+            .filter(|i| !matches!(i, Instruction::Mark(_)))
+            .count();
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let histogram: Histogram = vec![8, 12, 8, 10].into_iter().collect();
+        assert_eq!(histogram.runs(), 4);
+        assert_eq!(histogram.min(), Some(8));
+        assert_eq!(histogram.max(), Some(12));
+        assert_eq!(histogram.mean(), Some(9.5));
+        assert_eq!(
+            histogram.counts().collect::<Vec<_>>(),
+            vec![(8, 2), (10, 1), (12, 1)]
+        );
+        assert_eq!(histogram.to_string(), "8-12 (mean 9.5)");
+
+        let empty = Histogram::new();
+        assert_eq!(empty.mean(), None);
+        assert_eq!(empty.to_string(), "-");
+    }
+
+    #[test]
+    fn test_solution_size() {
+        let solution = vec![
+            ExaSpec {
+                name: "XA".into(),
+                script: "note hi\n mark a\n @rep 3\n addi x @{1,1} x\n @end\n jump a".into(),
+                local: false,
+            },
+            ExaSpec {
+                name: "XB".into(),
+                script: "halt\n".into(),
+                local: false,
+            },
+        ];
+        assert_eq!(solution_size(&solution), Ok(5));
+    }
+}
@@ -1,7 +1,7 @@
+use std::error::Error;
 use std::f32;
 
-use fastrand;
-
+use super::state::{StateReader, StateWriter};
 use super::VM;
 
 pub trait AudioSample {
@@ -60,6 +60,22 @@ impl Default for SquareWave {
     }
 }
 
+impl SquareWave {
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_float(self.pos);
+        w.write_float(self.frequency);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.pos = r.read_float()?;
+        self.frequency = r.read_float()?;
+        if !(0.0..=44100.0).contains(&self.pos) {
+            return Err("invalid wave position in save state".into());
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct TriangleWave {
     samples: Vec<i16>,
@@ -106,6 +122,22 @@ impl Default for TriangleWave {
     }
 }
 
+impl TriangleWave {
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_float(self.pos);
+        w.write_float(self.frequency);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.pos = r.read_float()?;
+        self.frequency = r.read_float()?;
+        if !(0.0..=44100.0).contains(&self.pos) {
+            return Err("invalid wave position in save state".into());
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct Noise {
     samples: Vec<i16>,
@@ -138,11 +170,14 @@ impl AudioSample for Noise {
     }
 }
 
-impl Default for Noise {
-    fn default() -> Self {
+impl Noise {
+    /// The noise channel loops over a fixed table of samples, which is
+    /// generated from the VM's seed so that audio is reproducible too.
+    pub fn with_seed(seed: u64) -> Self {
+        let rng = fastrand::Rng::with_seed(seed);
         let mut samples = vec![0; 4000];
         for idx in 0..4000 {
-            let mut value = fastrand::i16(-5000..=5000);
+            let mut value = rng.i16(-5000..=5000);
             if value < 0 {
                 value -= 15000;
             } else {
@@ -160,7 +195,20 @@ impl Default for Noise {
     }
 }
 
-impl<'a> VM<'a> {
+impl Noise {
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_len(self.pos);
+        w.write_int(self.undersample);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.pos = r.read_len()? % self.samples.len();
+        self.undersample = r.read_int()?;
+        Ok(())
+    }
+}
+
+impl VM {
     /// Return interleaved stereo audio stream for a single
     /// 60hz frame of the VM.
     pub fn audio_frame(&mut self) -> &[i16; (44100 / 60) * 2] {
@@ -168,15 +216,16 @@ impl<'a> VM<'a> {
             return &self.audio_buffer;
         }
 
-        let sqr0_value = self.redshift.as_ref().unwrap().sqr0.borrow().value;
-        let sqr1_value = self.redshift.as_ref().unwrap().sqr1.borrow().value;
-        let tri0_value = self.redshift.as_ref().unwrap().tri0.borrow().value;
-        let nse0_value = self.redshift.as_ref().unwrap().nse0.borrow().value;
+        let sqr0_value = self.redshift_register("sound", "#sqr0");
+        let sqr1_value = self.redshift_register("sound", "#sqr1");
+        let tri0_value = self.redshift_register("sound", "#tri0");
+        let nse0_value = self.redshift_register("sound", "#nse0");
 
-        let mut sqr0_wave = self.redshift.as_ref().unwrap().sqr0_wave.borrow_mut();
-        let mut sqr1_wave = self.redshift.as_ref().unwrap().sqr1_wave.borrow_mut();
-        let mut tri0_wave = self.redshift.as_ref().unwrap().tri0_wave.borrow_mut();
-        let mut nse0_wave = self.redshift.as_ref().unwrap().nse0_wave.borrow_mut();
+        let redshift = self.redshift.as_mut().unwrap();
+        let sqr0_wave = &mut redshift.sqr0_wave;
+        let sqr1_wave = &mut redshift.sqr1_wave;
+        let tri0_wave = &mut redshift.tri0_wave;
+        let nse0_wave = &mut redshift.nse0_wave;
 
         sqr0_wave.set_frequency(sqr0_value);
         sqr1_wave.set_frequency(sqr1_value);
@@ -1,12 +1,16 @@
 use std::error::Error;
+use std::sync::Arc;
 
 use super::error::ExaError;
 use super::exa::Exa;
+use super::state::{read_value, write_value, StateReader, StateWriter};
+use super::trace::{Bus, Event};
+use super::value::Value;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Message {
-    pub sender: String,
-    pub value: i32,
+    pub sender: Arc<str>,
+    pub value: Value,
 }
 
 /// MessageBus, aka the M register.
@@ -20,6 +24,9 @@ pub struct MessageBus {
     messages: Vec<Message>,
     visible: usize,
     read_available: bool,
+    // Set while the VM is tracing. Events wait here until the EXA that
+    // caused them collects them with take_events.
+    trace: Option<(Bus, Vec<Event>)>,
 }
 
 impl MessageBus {
@@ -28,6 +35,19 @@ impl MessageBus {
             messages: vec![],
             visible: 0,
             read_available: true,
+            trace: None,
+        }
+    }
+
+    /// Start or stop recording sends and receives, labelled with bus.
+    pub fn set_tracing(&mut self, bus: Option<Bus>) {
+        self.trace = bus.map(|b| (b, vec![]));
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event> {
+        match &mut self.trace {
+            Some((_, events)) => std::mem::take(events),
+            None => vec![],
         }
     }
 
@@ -35,33 +55,46 @@ impl MessageBus {
         self.messages.len() != 0
     }
 
-    pub fn read(&mut self) -> Result<Message, Box<dyn Error>> {
+    pub fn read(&mut self) -> Result<Message, ExaError> {
         if !self.read_available {
-            return Err(ExaError::Blocking("no available read bandwidth on bus").into());
+            return Err(ExaError::BusBandwidthExceeded);
         }
 
         if self.visible == 0 || self.messages.len() == 0 {
-            return Err(ExaError::Blocking("no messages available to read").into());
+            return Err(ExaError::NoMessages);
         }
 
         let read = self.messages.remove(0);
         self.visible -= 1;
         self.read_available = false;
+        if let Some((bus, events)) = &mut self.trace {
+            events.push(Event::Receive {
+                bus: bus.clone(),
+                sender: read.sender.to_string(),
+                value: read.value.clone(),
+            });
+        }
         Ok(read)
     }
 
-    pub fn write(&mut self, sender: &Exa, value: i32) -> Result<(), Box<dyn Error>> {
+    pub fn write(&mut self, sender: &Exa, value: Value) -> Result<(), ExaError> {
+        if let Some((bus, events)) = &mut self.trace {
+            events.push(Event::Send {
+                bus: bus.clone(),
+                value: value.clone(),
+            });
+        }
         self.messages.push(Message {
-            sender: sender.name.to_string(),
+            sender: sender.name.clone(),
             value: value,
         });
 
-        Err(ExaError::Freezing("bus write successful, freezing until it is read").into())
+        Err(ExaError::AwaitingReader)
     }
 
     pub fn on_kill_exa(&mut self, exa_name: &str) {
         for (idx, message) in self.messages.iter().enumerate() {
-            if message.sender == exa_name {
+            if *message.sender == *exa_name {
                 self.messages.remove(idx);
                 return;
             }
@@ -74,4 +107,36 @@ impl MessageBus {
         self.read_available = true;
         self.visible = self.messages.len();
     }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_len(self.messages.len());
+        for message in self.messages.iter() {
+            w.write_string(&message.sender);
+            write_value(w, &message.value);
+        }
+        w.write_len(self.visible);
+        w.write_bool(self.read_available);
+    }
+
+    pub fn load_state(r: &mut StateReader) -> Result<MessageBus, Box<dyn Error>> {
+        let mut messages = vec![];
+        for _ in 0..r.read_len()? {
+            let sender = r.read_string()?;
+            messages.push(Message {
+                sender: sender.into(),
+                value: read_value(r)?,
+            });
+        }
+        let visible = r.read_len()?;
+        if visible > messages.len() {
+            return Err("invalid message bus in save state".into());
+        }
+
+        Ok(MessageBus {
+            messages,
+            visible,
+            read_available: r.read_bool()?,
+            trace: None,
+        })
+    }
 }
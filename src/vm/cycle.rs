@@ -1,28 +1,14 @@
-use fastrand;
-
 use super::error::ExaError;
-use super::exa::Exa;
-use super::{Shared, VM};
-
-// min_x, max_x, min_y, max_y
-type CellBound = (i32, i32, i32, i32);
+use super::rewind::Input;
+use super::terminated::Terminated;
+use super::trace::Event;
+use super::VM;
 
-static CELL_BOUNDS: [CellBound; 12] = [
-    (-10, 25, -10, 30),
-    (15, 60, -10, 30),
-    (50, 95, -10, 30),
-    (85, 130, -10, 30),
-    (-10, 25, 20, 70),
-    (15, 60, 20, 70),
-    (50, 95, 20, 70),
-    (85, 130, 20, 70),
-    (-10, 25, 60, 110),
-    (15, 60, 60, 110),
-    (50, 95, 60, 110),
-    (85, 130, 60, 110),
-];
+// min_x, max_x, min_y, max_y of where an EXA can be and still collide.
+// Further off screen than this and its sprite can't be seen anyway.
+const COLLISION_BOUNDS: (i32, i32, i32, i32) = (-10, 130, -10, 110);
 
-impl<'a> VM<'a> {
+impl VM {
     /// Run the VM for one animation frame at 30hz. The
     /// tricky part here is that how many cycles constitutes
     /// a frame seems to be undefined and/or dynamic.
@@ -59,43 +45,70 @@ impl<'a> VM<'a> {
         }
     }
 
-    pub fn unfreeze_waiters(&self) {
-        for e in self.exas.iter() {
-            let mut exa = e.borrow_mut();
+    pub fn unfreeze_waiters(&mut self) {
+        self.record_input(Input::UnfreezeWaiters);
+        for exa in self.exas.iter_mut() {
             if exa.waiting {
                 exa.waiting = false;
-                exa.unfreeze();
+                exa.unfreeze(self.cycle);
             }
         }
     }
 
-    pub fn run_cycle(&mut self) {
-        // Reset traversal status on all host links. These can only
-        // support one EXA per cycle, others need to block.
-        for h in self.hosts.values() {
-            for link in h.borrow_mut().links.values_mut() {
-                link.traversed_this_cycle = false;
+    // Sort and sweep. Once EXAs are sorted by x, each one only needs
+    // checking against the ones after it that are less than a sprite's
+    // width further right, and of those only the ones less than a
+    // sprite's height away get their sprites compared.
+    fn detect_collisions(&mut self) {
+        let (min_x, max_x, min_y, max_y) = COLLISION_BOUNDS;
+        self.colliders.clear();
+        for (idx, exa) in self.exas.iter().enumerate() {
+            let (x, y) = exa.coords();
+            if !exa.sprite.is_empty() && min_x <= x && x <= max_x && min_y <= y && y <= max_y {
+                self.colliders.push((x, y, idx));
             }
         }
+        self.colliders.sort_unstable();
 
-        // Clean up EXAs with fatal errors last cycle
+        for (i, (left_x, left_y, left)) in self.colliders.iter().enumerate() {
+            for (right_x, right_y, right) in self.colliders[i + 1..].iter() {
+                if right_x - left_x >= 10 {
+                    break;
+                }
+                if (right_y - left_y).abs() >= 10 {
+                    continue;
+                }
+                // Collisions are symmetric, so which is which doesn't matter
+                let (first, second) = (*left.min(right), *left.max(right));
+                let (head, tail) = self.exas.split_at_mut(second);
+                head[first].update_collision(&mut tail[0]);
+            }
+        }
+    }
+
+    /// Remove EXAs that hit a fatal error, dropping any file they held
+    /// into their host. Runs at the start of every cycle, but can be
+    /// called early to inspect the network as the next cycle will see it.
+    pub fn clean_up_exas(&mut self) {
         let mut i = 0;
         while i != self.exas.len() {
-            let exa = &self.exas[i];
-            if exa.borrow().is_fatal() {
-                {
-                    if exa.borrow().file.is_some() {
-                        let dropped_file = exa.borrow_mut().file.take().unwrap();
-                        exa.borrow().host.borrow_mut().files.push(dropped_file);
-                    } else {
-                        exa.borrow_mut().host.borrow_mut().free_slot();
+            let exa = &mut self.exas[i];
+            if exa.is_fatal() {
+                if self.terminated.is_enabled() {
+                    if let Some(entry) = Terminated::from_exa(exa, &self.hosts) {
+                        self.terminated.push(entry);
                     }
                 }
+                let host = &mut self.hosts[exa.host];
+                match exa.file.take() {
+                    Some(dropped_file) => host.files.push(dropped_file),
+                    None => host.free_slot(),
+                }
 
                 // purge any messages sent by this exa from message buses
-                self.bus.borrow_mut().on_kill_exa(&exa.borrow().name);
+                self.bus.on_kill_exa(&exa.name);
                 for host in self.hosts.values_mut() {
-                    host.borrow_mut().bus.on_kill_exa(&exa.borrow().name);
+                    host.bus.on_kill_exa(&exa.name);
                 }
 
                 self.exas.remove(i);
@@ -103,15 +116,41 @@ impl<'a> VM<'a> {
                 i += 1;
             }
         }
+    }
+
+    /// Run every EXA for one cycle. If a debugger has stepped partway
+    /// into a cycle, this finishes that cycle instead.
+    pub fn run_cycle(&mut self) {
+        if !self.mid_cycle {
+            self.begin_cycle();
+        }
+        while self.step_exa().is_some() {}
+        self.end_cycle();
+    }
+
+    /// Everything that happens in a cycle before EXAs run their
+    /// instructions, ending with the order they'll run in.
+    pub(crate) fn begin_cycle(&mut self) {
+        self.mid_cycle = true;
+
+        // Reset traversal status on all host links. These can only
+        // support one EXA per cycle, others need to block.
+        for h in self.hosts.values_mut() {
+            for link in h.links.values_mut() {
+                link.traversed_this_cycle = false;
+            }
+        }
+
+        // Clean up EXAs with fatal errors last cycle
+        self.clean_up_exas();
 
-        // Collision detection. Quadratic for now, let's see if we can
-        // get away with it. We'll do some filtering to make it faster.
+        // Collision detection
         let mut uses_ci = false;
 
         if self.redshift.is_some() {
-            for exa in self.exas.clone().into_iter() {
-                exa.borrow_mut().reset_collision();
-                if exa.borrow().will_use_ci_this_cycle() {
+            for exa in self.exas.iter_mut() {
+                exa.reset_collision();
+                if exa.will_use_ci_this_cycle() {
                     uses_ci = true;
                 }
             }
@@ -121,59 +160,34 @@ impl<'a> VM<'a> {
             // every cycle, so there's no point in calculating it if it will
             // not be used.
             if uses_ci {
-                // Before running collision detection against each Exa, we'll
-                // separate the grid into 12 partially-overlapping cells. Since
-                // we need to do a quadratic pass over the Exa set, splitting it
-                // up some should help keep our runtime down quite a bit.
-                let mut cells: Vec<Vec<Shared<Exa>>> = vec![vec![]; 12];
-
-                let collision_exas = self
-                    .exas
-                    .clone()
-                    .into_iter()
-                    .filter(|e| !e.borrow().sprite.is_empty);
-
-                for e in collision_exas {
-                    let (ref self_x, ref self_y) = e.borrow().coords();
-
-                    for (idx, bounds) in CELL_BOUNDS.iter().enumerate() {
-                        let (min_x, max_x, min_y, max_y) = bounds;
-                        if min_x <= self_x && max_x >= self_x && min_y <= self_y && max_y >= self_y
-                        {
-                            cells[idx].push(e.clone());
-                        }
-                    }
-                }
-
-                for cell in cells.iter() {
-                    for (left_idx, left_exa) in cell.iter().enumerate() {
-                        for right_exa in cell[left_idx + 1..].iter() {
-                            left_exa.borrow_mut().update_collision(&right_exa.borrow());
-                        }
-                    }
-                }
+                self.detect_collisions();
             }
         }
 
         // Run message buses
-        self.bus.borrow_mut().run_cycle();
+        self.bus.run_cycle();
         for host in self.hosts.values_mut() {
-            host.borrow_mut().bus.run_cycle();
+            host.bus.run_cycle();
         }
 
         // Run KILLs. These seem to have a special execution order
         // so they need to go before other EXA commands. KILLs are based
         // on positioning at the start of the cycle, and if you get killed,
         // you don't get to run anything else this cycle.
-        let killers = self
-            .exas
-            .iter()
-            .filter(|e| e.borrow().will_kill_this_cycle());
-
-        for killer in killers {
-            let kill_target = self.kill_target(&killer.borrow());
-            if kill_target.is_some() {
-                kill_target.unwrap().borrow_mut().error = Some(ExaError::Fatal("killed").into());
+        for i in 0..self.exas.len() {
+            if !self.exas[i].will_kill_this_cycle() {
+                continue;
+            }
+            self.kills += 1;
+            let kill_target = self.kill_target(i);
+            if self.trace.is_some() {
+                let target = kill_target.map(|t| self.exas[t].name.to_string());
+                let (name, pc) = (self.exas[i].name.clone(), self.exas[i].pc());
+                self.trace(&name, pc, Event::Kill { target });
+            }
+            if let Some(target) = kill_target {
+                let by = self.exas[i].name.clone();
+                self.exas[target].fail(ExaError::Killed(by), self.cycle);
             }
         }
 
@@ -181,109 +195,80 @@ impl<'a> VM<'a> {
         // like it needs to happen after everything
         // else, since it'll return True even if, on that cycle, reading
         // would have actually blocked. But the *next* cycle it won't block.
-        for exa in self.exas.iter() {
-            let mut e = exa.borrow_mut();
+        for e in self.exas.iter_mut() {
             e.ran_test_mrd_this_cycle = e.will_test_mrd_this_cycle();
         }
 
-        self.exa_stack.clone_from(&self.exas);
-        self.exa_stack.retain(|exa| {
-            let e = exa.borrow();
-            !e.is_frozen() && !e.is_fatal()
-        });
-
-        // Shuffling here is important because it's the only way we have
-        // of randomizing which EXAs get messages off the message buses
-        // in which order. Plenty of games rely on that being random.
-        // This is disableable for the sake of tests.
-        if self.randomize_exa_order {
-            fastrand::shuffle(&mut self.exa_stack);
-        }
-
-        while self.exa_stack.len() != 0 {
-            let exa = self.exa_stack.remove(0);
-            let mut exa_mut = exa.borrow_mut();
-            let result = exa_mut.run_cycle(self);
+        self.run_queue.clear();
+        self.run_queue.extend(
+            self.exas
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| !e.is_frozen() && !e.is_fatal())
+                .map(|(i, _)| i),
+        );
+
+        // The order here is the only way we have of deciding which EXAs
+        // get messages off the message buses in which order.
+        let queue = self.run_queue.make_contiguous();
+        self.scheduler
+            .order(self.cycle, queue, &self.exas, &self.rng);
+    }
 
-            if let Some(e) = &result.unfreeze_exa {
-                let to_unfreeze = self.get_exa(e);
-                to_unfreeze.borrow_mut().unfreeze();
-                if !to_unfreeze.borrow_mut().is_fatal() {
-                    self.exa_stack.push(to_unfreeze);
+    /// Run the next EXA's instruction for this cycle, returning where
+    /// that EXA is in exas. None means every EXA has had its turn.
+    pub(crate) fn step_exa(&mut self) -> Option<usize> {
+        let index = self.run_queue.pop_front()?;
+
+        // The EXA is taken out of exas while it runs, so it can have the
+        // rest of the VM to itself, and then put back where it was.
+        let mut exa = self.exas.swap_remove(index);
+        let unfreeze_exa = exa.run_cycle(self).unfreeze_exa.clone();
+        self.exas.push(exa);
+        let last = self.exas.len() - 1;
+        self.exas.swap(index, last);
+        self.exas.append(&mut self.spawned);
+
+        if let Some(name) = unfreeze_exa {
+            if let Some(i) = self.exas.iter().position(|e| e.name == name) {
+                let to_unfreeze = &mut self.exas[i];
+                to_unfreeze.unfreeze(self.cycle);
+                if !to_unfreeze.is_fatal() {
+                    self.run_queue.push_back(i);
                 }
             }
         }
+        Some(index)
+    }
 
+    pub(crate) fn end_cycle(&mut self) {
         // Run the TEST MRDs from earlier.
-        for exa in self
-            .exas
-            .iter()
-            .filter(|e| e.borrow().ran_test_mrd_this_cycle == true)
-        {
-            exa.borrow_mut().test_mrd();
+        for exa in self.exas.iter_mut().filter(|e| e.ran_test_mrd_this_cycle) {
+            exa.test_mrd(&self.bus, &self.hosts);
         }
 
         self.cycle += 1;
+        self.mid_cycle = false;
+        self.checkpoint_if_due();
     }
 
-    /// Kill targeting seems pretty complex, and I haven't been able to
-    /// fully reverse engineer it. We're going to try this for now and hope
-    /// we don't run into any programs that are actually relying on the
-    /// exact kill behavior in the retail game. Kill targets are prioritized
-    /// based on:
-    /// - whether they are also performing a KILL this turn
-    /// - whether they are in our EXA chain, and newer than us
-    /// - whether they are in our EXA chain, and older than us
-    /// - everyone else
-    /// We take the first group that has any members, and pick a random
-    /// as-of-yet unkilled member from it, then kill it.
-    fn kill_target(&self, killer: &Exa<'a>) -> Option<Shared<Exa<'a>>> {
-        let k = killer;
-        let host_exas: Vec<Shared<Exa<'a>>> = self
-            .exas
-            .clone()
-            .into_iter()
-            .filter(|e| *e.borrow() != *k && *e.borrow().host == *k.host)
-            .collect();
-
-        if host_exas.len() == 0 {
+    /// Everyone killer could kill, handed to the kill policy to choose
+    /// from. Kill targets are based on positioning at the start of the
+    /// cycle.
+    fn kill_target(&mut self, killer: usize) -> Option<usize> {
+        let exas = &self.exas;
+        let host = exas[killer].host;
+        self.kill_candidates.clear();
+        self.kill_candidates
+            .extend((0..exas.len()).filter(|i| *i != killer && exas[*i].host == host));
+
+        if self.kill_candidates.is_empty() {
             return None;
         }
 
-        let other_killers: Vec<Shared<Exa<'a>>> = host_exas
-            .clone()
-            .into_iter()
-            .filter(|e| e.borrow().will_kill_this_cycle())
-            .collect();
-
-        if other_killers.len() != 0 {
-            let choice = &other_killers[fastrand::usize(..other_killers.len())];
-            return Some(choice.clone());
-        }
-
-        let descendants: Vec<Shared<Exa<'a>>> = host_exas
-            .clone()
-            .into_iter()
-            .filter(|e| e.borrow().descendant_of(killer))
-            .collect();
-
-        if descendants.len() != 0 {
-            let choice = &descendants[fastrand::usize(..descendants.len())];
-            return Some(choice.clone());
-        }
-
-        let ancestors: Vec<Shared<Exa<'a>>> = host_exas
-            .clone()
-            .into_iter()
-            .filter(|e| e.borrow().ancestor_of(killer))
-            .collect();
-
-        if ancestors.len() != 0 {
-            let choice = &ancestors[fastrand::usize(..ancestors.len())];
-            return Some(choice.clone());
-        }
-
-        let choice = &host_exas[fastrand::usize(..host_exas.len())];
-        Some(choice.clone())
+        let choice = self
+            .kill_policy
+            .choose(&exas[killer], &self.kill_candidates, exas, &self.rng);
+        self.kill_candidates.get(choice).copied()
     }
 }
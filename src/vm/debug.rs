@@ -0,0 +1,264 @@
+//! Debugger support for the VM: breakpoints, watchpoints, stepping one
+//! EXA instruction at a time, and a structured view of every EXA.
+//!
+//! Breakpoints and watchpoints live on the VM but are not part of save
+//! states.
+
+use std::fmt;
+
+use super::error::ExaError;
+use super::exa::Mode;
+use super::file::File;
+use super::instruction::Instruction;
+use super::value::Value;
+use super::VM;
+
+/// Where in an EXA's script to stop.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Location {
+    /// 1-based line in the EXA's source.
+    Line(usize),
+    /// The instruction right after a MARK.
+    Label(String),
+}
+
+/// Stop before the named EXA runs the instruction at location. The name
+/// can also be the name of an original EXA, which matches every EXA it
+/// REPLs as well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub exa: String,
+    pub location: Location,
+}
+
+/// A register to keep an eye on. Names are as written in EXA code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Watch {
+    Exa { exa: String, register: String },
+    Host { host: String, register: String },
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watch::Exa { exa, register } => write!(f, "{} in exa {}", register, exa),
+            Watch::Host { host, register } => write!(f, "{} in host {}", register, host),
+        }
+    }
+}
+
+/// Why run_until_break stopped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Break {
+    /// exa is about to run the instruction at the breakpoint.
+    Breakpoint { exa: String, breakpoint: Breakpoint },
+    /// A watched register changed. Values are None while the register
+    /// doesn't exist, e.g. before its EXA is spawned or after it dies.
+    Watchpoint {
+        watch: Watch,
+        old: Option<Value>,
+        new: Option<Value>,
+    },
+    /// Every EXA is gone.
+    Halted,
+    /// The cycle limit passed to run_until_break was reached.
+    CycleLimit,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExaStatus {
+    Running,
+    /// Waiting to retry its current instruction, e.g. on an M read.
+    Blocked(ExaError),
+    /// Not running at all until something else releases it, e.g. after
+    /// an M write waiting for a reader.
+    Frozen(ExaError),
+    /// Will be removed at the start of the next cycle.
+    Dead(ExaError),
+}
+
+/// Snapshot of an EXA for debuggers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExaInfo {
+    pub name: String,
+    pub host: String,
+    pub pc: usize,
+    /// Source line of the next instruction, if known.
+    pub line: Option<usize>,
+    pub instruction: Option<Instruction>,
+    pub mode: Mode,
+    /// Every register the EXA can access, in X, T, GX, GY, GZ, GP, CI,
+    /// CO order.
+    pub registers: Vec<(String, Value)>,
+    pub file: Option<File>,
+    pub file_pointer: isize,
+    pub status: ExaStatus,
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    // Watched registers along with their value as of the last check
+    watches: Vec<(Watch, Option<Value>)>,
+    // Set when run_until_break stops at a breakpoint, so the next run
+    // doesn't stop at the same spot straight away
+    at_breakpoint: bool,
+}
+
+impl VM {
+    pub fn add_breakpoint(&mut self, exa: &str, location: Location) {
+        let location = match location {
+            Location::Label(l) => Location::Label(l.to_ascii_lowercase()),
+            l => l,
+        };
+        self.debugger.breakpoints.push(Breakpoint {
+            exa: exa.to_string(),
+            location,
+        });
+    }
+
+    pub fn remove_breakpoint(&mut self, exa: &str, location: &Location) {
+        self.debugger.breakpoints.retain(|b| {
+            let same = match (&b.location, location) {
+                (Location::Label(a), Location::Label(b)) => a.eq_ignore_ascii_case(b),
+                (a, b) => a == b,
+            };
+            !(b.exa == exa && same)
+        });
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.debugger.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watch: Watch) {
+        let value = self.watched_value(&watch);
+        self.debugger.watches.push((watch, value));
+    }
+
+    pub fn remove_watchpoint(&mut self, watch: &Watch) {
+        self.debugger.watches.retain(|(w, _)| w != watch);
+    }
+
+    /// Run the next EXA instruction, starting a new cycle if needed.
+    /// Returns the name of the EXA that ran, or None if nothing was left
+    /// to run this cycle and the cycle was finished instead.
+    pub fn step_instruction(&mut self) -> Option<String> {
+        self.debugger.at_breakpoint = false;
+        if !self.mid_cycle {
+            self.begin_cycle();
+        }
+
+        let exa = self.step_exa();
+        if exa.is_none() || self.run_queue.is_empty() {
+            self.end_cycle();
+        }
+        exa.map(|i| self.exas[i].name.to_string())
+    }
+
+    /// Whether step_instruction has stopped partway through a cycle.
+    pub fn mid_cycle(&self) -> bool {
+        self.mid_cycle
+    }
+
+    /// Step instructions until a breakpoint or watchpoint triggers, every
+    /// EXA is gone, or max_cycles more cycles have run.
+    pub fn run_until_break(&mut self, max_cycles: u32) -> Break {
+        let limit = self.cycle.saturating_add(max_cycles);
+        let mut check_breakpoints = !self.debugger.at_breakpoint;
+
+        loop {
+            if check_breakpoints {
+                if let Some(b) = self.breakpoint_hit() {
+                    self.debugger.at_breakpoint = true;
+                    return b;
+                }
+            }
+            check_breakpoints = true;
+
+            if self.exas.is_empty() && !self.mid_cycle {
+                return Break::Halted;
+            }
+            if self.cycle >= limit {
+                return Break::CycleLimit;
+            }
+
+            self.step_instruction();
+            if let Some(b) = self.watch_hit() {
+                return b;
+            }
+        }
+    }
+
+    /// Snapshot of every EXA, in the order they were spawned.
+    pub fn exa_info(&self) -> Vec<ExaInfo> {
+        self.exas.iter().map(|e| e.info(&self.hosts)).collect()
+    }
+
+    /// Forget where the debugger last stopped, after the VM has jumped
+    /// somewhere else entirely.
+    pub(crate) fn reset_debugger_position(&mut self) {
+        self.debugger.at_breakpoint = false;
+        for i in 0..self.debugger.watches.len() {
+            let value = self.watched_value(&self.debugger.watches[i].0);
+            self.debugger.watches[i].1 = value;
+        }
+    }
+
+    fn breakpoint_hit(&mut self) -> Option<Break> {
+        if self.debugger.breakpoints.is_empty() {
+            return None;
+        }
+        // The next EXA to run is only known once its cycle has started
+        if !self.mid_cycle {
+            if self.exas.is_empty() {
+                return None;
+            }
+            self.begin_cycle();
+        }
+
+        let next = &self.exas[*self.run_queue.front()?];
+        self.debugger
+            .breakpoints
+            .iter()
+            .find(|b| {
+                (*b.exa == *next.name || b.exa == next.lineage()) && next.at_location(&b.location)
+            })
+            .map(|b| Break::Breakpoint {
+                exa: next.name.to_string(),
+                breakpoint: b.clone(),
+            })
+    }
+
+    fn watch_hit(&mut self) -> Option<Break> {
+        let mut hit = None;
+        for i in 0..self.debugger.watches.len() {
+            let value = self.watched_value(&self.debugger.watches[i].0);
+            let (watch, old) = &mut self.debugger.watches[i];
+            if *old != value && hit.is_none() {
+                hit = Some(Break::Watchpoint {
+                    watch: watch.clone(),
+                    old: old.clone(),
+                    new: value.clone(),
+                });
+            }
+            *old = value;
+        }
+        hit
+    }
+
+    fn watched_value(&self, watch: &Watch) -> Option<Value> {
+        match watch {
+            Watch::Exa { exa, register } => self
+                .exas
+                .iter()
+                .find(|e| *e.name == **exa)
+                .and_then(|e| e.register_value(register)),
+            Watch::Host { host, register } => self.hosts.get(host).and_then(|h| {
+                h.registers
+                    .get(&register.to_ascii_lowercase())
+                    .map(|r| r.peek())
+            }),
+        }
+    }
+}
@@ -1,25 +1,164 @@
+use std::borrow::Cow;
 use std::error;
 use std::fmt;
+use std::sync::Arc;
 
-#[derive(Debug, Clone)]
-pub enum ExaError<'a> {
-    Blocking(&'a str),
-    Fatal(&'a str),
+/// How an error affects the EXA that hit it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The EXA retries the same instruction next cycle.
+    Blocking,
     /// Freezing errors cause an Exa to skip processing cycles until
     /// the freeze is released by an outside process. One use case here
     /// is freezing an Exa after an M write until it is read by
     /// another Exa.
-    Freezing(&'a str),
+    Freezing,
+    /// The EXA dies at the start of the next cycle.
+    Fatal,
 }
 
-impl<'a> fmt::Display for ExaError<'a> {
+impl fmt::Display for Severity {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            ExaError::Blocking(m) => write!(f, "blocking: {}", m),
-            ExaError::Fatal(m) => write!(f, "fatal: {}", m),
-            ExaError::Freezing(m) => write!(f, "freezing: {}", m),
+        match self {
+            Severity::Blocking => write!(f, "blocking"),
+            Severity::Freezing => write!(f, "freezing"),
+            Severity::Fatal => write!(f, "fatal"),
         }
     }
 }
 
-impl<'a> error::Error for ExaError<'a> {}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExaError {
+    /// Catch-alls for hardware registers and anything else without a
+    /// more specific variant.
+    Blocking(Cow<'static, str>),
+    Freezing(Cow<'static, str>),
+    Fatal(Cow<'static, str>),
+
+    NoMessages,
+    BusBandwidthExceeded,
+    LinkBandwidthExceeded,
+    HostFull,
+
+    AwaitingReader,
+    Waiting,
+
+    OutOfInstructions,
+    Halted,
+    /// Killed by the named EXA.
+    Killed(Arc<str>),
+    UnknownLabel(String),
+    InvalidLinkId(i32),
+    DivideByZero,
+    NumberRequired,
+    WriteToLiteral,
+    ReadOnlyRegister,
+    WriteOnlyRegister,
+    DeactivatedRegister,
+    UnknownRegister(String),
+    NoFileHeld,
+    FileAlreadyHeld,
+    FileNotFound(i32),
+    EndOfFile,
+    InvalidRandRange,
+    /// A hardware register failed with something other than an ExaError.
+    Hardware(String),
+}
+
+impl ExaError {
+    pub fn severity(&self) -> Severity {
+        match self {
+            ExaError::Blocking(_)
+            | ExaError::NoMessages
+            | ExaError::BusBandwidthExceeded
+            | ExaError::LinkBandwidthExceeded
+            | ExaError::HostFull => Severity::Blocking,
+            ExaError::Freezing(_) | ExaError::AwaitingReader | ExaError::Waiting => {
+                Severity::Freezing
+            }
+            _ => Severity::Fatal,
+        }
+    }
+
+    /// Unwrap an error from a HardwareRegister hook.
+    pub fn from_hardware(e: Box<dyn error::Error>) -> ExaError {
+        match e.downcast::<ExaError>() {
+            Ok(e) => *e,
+            Err(e) => ExaError::Hardware(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ExaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExaError::Blocking(m) | ExaError::Freezing(m) | ExaError::Fatal(m) => {
+                write!(f, "{}", m)
+            }
+            ExaError::NoMessages => write!(f, "no messages available to read"),
+            ExaError::BusBandwidthExceeded => write!(f, "no available read bandwidth on bus"),
+            ExaError::LinkBandwidthExceeded => write!(f, "link bandwidth exceeded"),
+            ExaError::HostFull => write!(f, "host has no remaining capacity"),
+            ExaError::AwaitingReader => {
+                write!(f, "bus write successful, freezing until it is read")
+            }
+            ExaError::Waiting => write!(f, "waiting"),
+            ExaError::OutOfInstructions => write!(f, "out of instructions"),
+            ExaError::Halted => write!(f, "explicit halt"),
+            ExaError::Killed(by) => write!(f, "killed by {}", by),
+            ExaError::UnknownLabel(l) => write!(f, "unknown label {}", l),
+            ExaError::InvalidLinkId(id) => write!(f, "invalid link id {}", id),
+            ExaError::DivideByZero => write!(f, "divide by zero"),
+            ExaError::NumberRequired => write!(f, "numeric value required"),
+            ExaError::WriteToLiteral => write!(f, "cannot write to literal"),
+            ExaError::ReadOnlyRegister => write!(f, "attempt to write to read-only register"),
+            ExaError::WriteOnlyRegister => write!(f, "attempt to read from write-only register"),
+            ExaError::DeactivatedRegister => write!(f, "attempt to access deactivated register"),
+            ExaError::UnknownRegister(r) => write!(f, "unknown register {}", r),
+            ExaError::NoFileHeld => write!(f, "no file is held"),
+            ExaError::FileAlreadyHeld => write!(f, "cannot grab a second file"),
+            ExaError::FileNotFound(id) => write!(f, "file id {} not found", id),
+            ExaError::EndOfFile => write!(f, "cannot access file at append position"),
+            ExaError::InvalidRandRange => write!(f, "invalid rand range"),
+            ExaError::Hardware(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl error::Error for ExaError {}
+
+/// An ExaError along with where and when it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    pub error: ExaError,
+    pub exa: Arc<str>,
+    pub pc: usize,
+    /// Source line of the instruction at pc, if known.
+    pub line: Option<usize>,
+    pub cycle: u32,
+}
+
+impl Fault {
+    pub fn severity(&self) -> Severity {
+        self.error.severity()
+    }
+}
+
+/// Reads like "XA:3 died at line 12: divide by zero on cycle 4411".
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.severity() {
+            Severity::Blocking => "blocked",
+            Severity::Freezing => "froze",
+            Severity::Fatal => "died",
+        };
+        write!(f, "{} {} at ", self.exa, what)?;
+        match self.line {
+            Some(line) => write!(f, "line {}", line)?,
+            None => write!(f, "pc {}", self.pc)?,
+        }
+        write!(f, ": {} on cycle {}", self.error, self.cycle)
+    }
+}
+
+impl error::Error for Fault {}
@@ -1,14 +1,14 @@
-use std::error::Error;
-use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
-use fastrand;
-
-use super::super::error::ExaError;
+use super::super::bus::MessageBus;
+use super::super::error::{ExaError, Severity};
 use super::super::file::File;
-use super::super::instruction::{Comparator, Instruction, Target};
-use super::super::register::Register;
-use super::super::VM;
-use super::super::{Permissions, Shared};
+use super::super::instruction::Comparator;
+use super::super::program::{Jump, Op, Operand, Reg};
+use super::super::register::{HardwareRegister, Register};
+use super::super::trace::Event;
+use super::super::value::Value;
+use super::super::{Hosts, VM};
 use super::sprite::Sprite;
 use super::{Exa, Mode};
 
@@ -22,33 +22,34 @@ fn clamp(value: i32, min: i32, max: i32) -> i32 {
     }
 }
 
-/// Splits a value into a vec of its digits. Throws away the sign, and
-/// pads so that there are always exactly 4 digits.
-fn int_to_digits(value: i32) -> Vec<u32> {
-    let mut value_string = value.abs().to_string();
-
-    if value_string.len() < 4 {
-        let mut new_string = String::from("");
-        for _ in 0..4 - value_string.len() {
-            new_string.push_str("0");
-        }
-        new_string.push_str(&value_string);
-        value_string = new_string;
+/// Fit a value being written into a register's range, if it has one.
+fn clamp_to(range: Option<(i32, i32)>, value: Value) -> Result<Value, ExaError> {
+    match (range, value) {
+        (Some((min, max)), Value::Number(n)) => Ok(Value::Number(clamp(n, min, max))),
+        (Some(_), Value::Keyword(_)) => Err(ExaError::NumberRequired),
+        (None, value) => Ok(value),
     }
+}
 
-    value_string
-        .chars()
-        .map(|d| d.to_digit(10).unwrap())
-        .collect::<Vec<u32>>()
+/// Splits a value into its digits, most significant first. Throws away
+/// the sign, and pads so that there are always exactly 4 digits.
+fn int_to_digits(value: i32) -> [u32; 4] {
+    let value = value.unsigned_abs();
+    [
+        value / 1000 % 10,
+        value / 100 % 10,
+        value / 10 % 10,
+        value % 10,
+    ]
 }
 
-type ExaResult = Result<(), Box<dyn Error>>;
+type ExaResult = Result<(), ExaError>;
 
 /// Used to report any information from an EXA's cycle run
 /// back up the chain to the VM.
 #[derive(Debug, PartialEq, Eq)]
 pub struct CycleResult {
-    pub unfreeze_exa: Option<String>,
+    pub unfreeze_exa: Option<Arc<str>>,
 }
 
 impl CycleResult {
@@ -57,208 +58,234 @@ impl CycleResult {
     }
 }
 
-impl<'a> Exa<'a> {
-    pub fn run_cycle(&mut self, vm: &mut VM<'a>) -> &CycleResult {
+impl Exa {
+    pub fn run_cycle(&mut self, vm: &mut VM) -> &CycleResult {
         // Reset result struct to pass up to VM
         self.result = CycleResult::new();
 
-        if self.instructions.len() == 0 {
-            self.error = Some(ExaError::Fatal("out of instructions").into());
+        if self.program.is_empty() {
+            self.fail(ExaError::OutOfInstructions, vm.cycle);
+            if vm.is_tracing() {
+                self.trace_outcome(vm, &Op::Noop, self.pc, None);
+            }
             return &self.result;
         }
 
-        let result = match &self.instructions[self.pc].clone() {
-            Instruction::Link(ref dest) => self.link(dest),
-            Instruction::Copy(ref src, ref dest) => self.copy(src, dest),
-            Instruction::Addi(ref left, ref right, ref dest) => self.addi(left, right, dest),
-            Instruction::Subi(ref left, ref right, ref dest) => self.subi(left, right, dest),
-            Instruction::Muli(ref left, ref right, ref dest) => self.muli(left, right, dest),
-            Instruction::Divi(ref left, ref right, ref dest) => self.divi(left, right, dest),
-            Instruction::Modi(ref left, ref right, ref dest) => self.modi(left, right, dest),
-            Instruction::Swiz(ref input, ref mask, ref dest) => self.swiz(input, mask, dest),
-            Instruction::Jump(ref label) => self.jump(label),
-            Instruction::Tjmp(ref label) => self.tjmp(label),
-            Instruction::Fjmp(ref label) => self.fjmp(label),
-            Instruction::Test(ref left, ref comp, ref right) => self.test(left, comp, right),
-            Instruction::Repl(ref label) => self.repl(vm, label),
-            Instruction::Mode => {
+        let pc = self.pc;
+        // Keeps the program alive while self is borrowed mutably below
+        let program = self.program.clone();
+        let op = &program.ops[pc];
+        let held_file = self.file.as_ref().map(|f| f.id);
+        if vm.is_tracing() {
+            let event = Event::Instruction {
+                instruction: program.source[pc].clone(),
+            };
+            vm.trace(&self.name, pc, event);
+        }
+
+        let result = match op {
+            Op::Link(ref dest) => self.link(vm, dest),
+            Op::Copy(ref src, ref dest) => self.copy(vm, src, dest),
+            Op::Addi(ref left, ref right, ref dest) => self.addi(vm, left, right, dest),
+            Op::Subi(ref left, ref right, ref dest) => self.subi(vm, left, right, dest),
+            Op::Muli(ref left, ref right, ref dest) => self.muli(vm, left, right, dest),
+            Op::Divi(ref left, ref right, ref dest) => self.divi(vm, left, right, dest),
+            Op::Modi(ref left, ref right, ref dest) => self.modi(vm, left, right, dest),
+            Op::Swiz(ref input, ref mask, ref dest) => self.swiz(vm, input, mask, dest),
+            Op::Jump(ref target) => self.jump(target),
+            Op::Tjmp(ref target) => self.tjmp(target),
+            Op::Fjmp(ref target) => self.fjmp(target),
+            Op::Test(ref left, ref comp, ref right) => self.test(vm, left, comp, right),
+            Op::Repl(ref target) => self.repl(vm, target),
+            Op::Mode => {
                 match self.mode {
                     Mode::Local => self.mode = Mode::Global,
                     Mode::Global => self.mode = Mode::Local,
                 }
                 Ok(())
             }
-            Instruction::VoidM => self.read_register("m").map(|_| ()),
-            Instruction::Make => self.make_file(),
-            Instruction::Drop => self.drop_file(),
-            Instruction::Wipe => self.wipe_file(),
-            Instruction::Grab(ref file_target) => self.grab_file(file_target),
-            Instruction::Halt => Err(ExaError::Fatal("explicit halt").into()),
-            Instruction::Seek(ref target) => self.seek_file(target),
-            Instruction::VoidF => self.void_file(),
-            Instruction::File(ref target) => self.file_command(target),
-            Instruction::TestEof => self.test_eof(),
-            Instruction::Rand(ref lo, ref hi, ref dest) => self.rand(lo, hi, dest),
-            Instruction::Noop => Ok(()),
-            Instruction::Mark(_) => panic!("marks should have been preprocessed out"),
-            // host is unsupported because we don't support keywords. convert to noop
-            Instruction::Host(_) => Ok(()),
+            Op::VoidM => self.read_from_bus(vm).map(|_| ()),
+            Op::Make => self.make_file(vm),
+            Op::Drop => self.drop_file(vm),
+            Op::Wipe => self.wipe_file(),
+            Op::Grab(ref file_target) => self.grab_file(vm, file_target),
+            Op::Halt => Err(ExaError::Halted),
+            Op::Seek(ref target) => self.seek_file(vm, target),
+            Op::VoidF => self.void_file(),
+            Op::File(ref target) => self.file_command(vm, target),
+            Op::TestEof => self.test_eof(),
+            Op::Rand(ref lo, ref hi, ref dest) => self.rand(vm, lo, hi, dest),
+            Op::Noop => Ok(()),
+            Op::Host(ref dest) => self.host(vm, dest),
             // kills are handled in the VM's run_cycle, before everything else
-            Instruction::Kill => Ok(()),
+            Op::Kill => Ok(()),
             // test mrd is handled in the VM's run_cycle, after everything else
-            Instruction::TestMrd => Ok(()),
-            // data is handled when EXAs are spawned at the beginning of the program
-            Instruction::Data(_) => panic!("datas should have been preprocessed out"),
+            Op::TestMrd => Ok(()),
             // waits freeze until the draw routine unfreezes
-            Instruction::Wait => {
+            Op::Wait => {
                 self.waiting = true;
-                Err(ExaError::Freezing("waiting").into())
+                Err(ExaError::Waiting)
             }
         };
 
-        self.error = match result {
-            Ok(_) => None,
-            Err(e) => Some(e),
-        };
-
-        if self.error.is_none() {
-            self.pc += 1;
-
-            if self.pc > self.instructions.len() - 1 {
-                self.error = Some(ExaError::Fatal("out of instructions").into());
+        match result {
+            Ok(_) => {
+                self.error = None;
+                self.advance(vm.cycle);
             }
+            Err(e) => self.fail(e, vm.cycle),
+        }
+
+        if vm.is_tracing() {
+            self.trace_outcome(vm, op, pc, held_file);
         }
 
         return &self.result;
     }
 
-    pub fn unfreeze(&mut self) {
-        if !self.is_frozen() {
-            panic!("cannot call unfreeze on {}, exa is not frozen", self.name);
+    /// Report everything the instruction at pc did that isn't traced
+    /// where it happens: M traffic, file handling and how it ended.
+    fn trace_outcome(&mut self, vm: &mut VM, op: &Op, pc: usize, held_file: Option<i32>) {
+        let mut events = vm.bus.take_events();
+        events.extend(vm.hosts[self.host].bus.take_events());
+
+        let failed = self.error.is_some() && self.pc == pc;
+        if !failed {
+            let event = match op {
+                Op::Grab(_) => self.file.as_ref().map(|f| Event::Grab { file: f.id }),
+                Op::Drop => held_file.map(|file| Event::Drop { file }),
+                Op::Wipe => held_file.map(|file| Event::Wipe { file }),
+                _ => None,
+            };
+            events.extend(event);
         }
 
-        self.error = None;
-        self.pc += 1;
-        if self.pc > self.instructions.len() - 1 {
-            self.error = Some(ExaError::Fatal("out of instructions").into());
+        if let Some(e) = &self.error {
+            let reason = e.error.to_string();
+            events.push(match e.severity() {
+                Severity::Blocking => Event::Blocked { reason },
+                Severity::Freezing => Event::Frozen { reason },
+                Severity::Fatal => Event::Fatal { reason },
+            });
+        }
+
+        for event in events {
+            vm.trace(&self.name, pc, event);
         }
     }
 
-    fn link(&mut self, dest: &Target) -> ExaResult {
-        let link_id = self.read_target(dest)?;
+    pub fn unfreeze(&mut self, cycle: u32) {
+        if !self.is_frozen() {
+            panic!("cannot call unfreeze on {}, exa is not frozen", self.name);
+        }
 
-        let start_host = self.host.clone();
-        let start_host_name = start_host.borrow().name.to_string();
-        {
-            let s = &mut start_host.borrow_mut();
-            let links = &mut s.links;
-            let link = links.get_mut(&link_id);
+        self.error = None;
+        self.advance(cycle);
+    }
 
-            if link.is_none() {
-                return Err(ExaError::Fatal("invalid link id").into());
-            }
+    /// Move on to the next instruction, dying if there isn't one. The
+    /// fault points at the last instruction that ran.
+    fn advance(&mut self, cycle: u32) {
+        // Wrapping, since jumps to pc 0 leave it one before the start
+        let next = self.pc.wrapping_add(1);
+        if next > self.program.len() - 1 {
+            self.fail(ExaError::OutOfInstructions, cycle);
+        }
+        self.pc = next;
+    }
 
-            let l = link.unwrap();
-            if l.traversed_this_cycle {
-                return Err(ExaError::Blocking("link bandwidth exceeded").into());
-            }
+    fn link(&mut self, vm: &mut VM, dest: &Operand) -> ExaResult {
+        let link_id = self.read_number(vm, dest)?;
 
-            let mut to_host = l.to_host.borrow_mut();
-            to_host
-                .reserve_slot()
-                .map_err(|_| Box::new(ExaError::Blocking("destination host is full")))?;
+        let from = self.host;
+        let to = match vm.hosts[from].links.get(&link_id) {
+            None => return Err(ExaError::InvalidLinkId(link_id)),
+            Some(l) if l.traversed_this_cycle => return Err(ExaError::LinkBandwidthExceeded),
+            Some(l) => l.to_host,
+        };
 
-            for back_link in to_host.links.values_mut() {
-                if back_link.to_host_name == start_host_name {
-                    back_link.traversed_this_cycle = true;
-                }
+        let to_host = &mut vm.hosts[to];
+        to_host.reserve_slot()?;
+        for back_link in to_host.links.values_mut() {
+            if back_link.to_host == from {
+                back_link.traversed_this_cycle = true;
             }
+        }
 
+        let from_host = &mut vm.hosts[from];
+        if let Some(l) = from_host.links.get_mut(&link_id) {
             l.traversed_this_cycle = true;
-            self.host = l.to_host.clone();
         }
+        from_host.free_slot();
+        self.host = to;
 
-        start_host.borrow_mut().free_slot();
+        vm.links_traversed += 1;
+        if vm.is_tracing() {
+            let event = Event::Link {
+                link: link_id,
+                from: vm.hosts[from].name.clone(),
+                to: vm.hosts[to].name.clone(),
+            };
+            vm.trace(&self.name, self.pc, event);
+        }
 
         Ok(())
     }
 
-    fn copy(&mut self, src: &Target, dest: &Target) -> ExaResult {
-        let src_value = self.read_target(src)?;
-
-        match dest {
-            Target::Literal(_) => Err(ExaError::Fatal("cannot write to literal").into()),
-            Target::Register(r) => self.write_register(r, src_value),
-        }
+    fn copy(&mut self, vm: &mut VM, src: &Operand, dest: &Operand) -> ExaResult {
+        let src_value = self.read_operand(vm, src)?;
+        self.write_operand(vm, dest, src_value)
     }
 
-    fn addi(&mut self, left: &Target, right: &Target, dest: &Target) -> ExaResult {
-        let value = self.read_target(left)? + self.read_target(right)?;
-
-        match dest {
-            Target::Literal(_) => Err(ExaError::Fatal("cannot write to literal").into()),
-            Target::Register(r) => self.write_register(r, clamp(value, -9999, 9999)),
-        }
+    fn addi(&mut self, vm: &mut VM, left: &Operand, right: &Operand, dest: &Operand) -> ExaResult {
+        let value = self.read_number(vm, left)? + self.read_number(vm, right)?;
+        self.write_operand(vm, dest, Value::Number(clamp(value, -9999, 9999)))
     }
 
-    fn subi(&mut self, left: &Target, right: &Target, dest: &Target) -> ExaResult {
-        let value = self.read_target(left)? - self.read_target(right)?;
-
-        match dest {
-            Target::Literal(_) => Err(ExaError::Fatal("cannot write to literal").into()),
-            Target::Register(r) => self.write_register(r, clamp(value, -9999, 9999)),
-        }
+    fn subi(&mut self, vm: &mut VM, left: &Operand, right: &Operand, dest: &Operand) -> ExaResult {
+        let value = self.read_number(vm, left)? - self.read_number(vm, right)?;
+        self.write_operand(vm, dest, Value::Number(clamp(value, -9999, 9999)))
     }
 
-    fn muli(&mut self, left: &Target, right: &Target, dest: &Target) -> ExaResult {
-        let value = self.read_target(left)? * self.read_target(right)?;
-
-        match dest {
-            Target::Literal(_) => Err(ExaError::Fatal("cannot write to literal").into()),
-            Target::Register(r) => self.write_register(r, clamp(value, -9999, 9999)),
-        }
+    fn muli(&mut self, vm: &mut VM, left: &Operand, right: &Operand, dest: &Operand) -> ExaResult {
+        let value = self.read_number(vm, left)? * self.read_number(vm, right)?;
+        self.write_operand(vm, dest, Value::Number(clamp(value, -9999, 9999)))
     }
 
-    fn divi(&mut self, left: &Target, right: &Target, dest: &Target) -> ExaResult {
-        let right = self.read_target(right)?;
+    fn divi(&mut self, vm: &mut VM, left: &Operand, right: &Operand, dest: &Operand) -> ExaResult {
+        let right = self.read_number(vm, right)?;
         if right == 0 {
-            return Err(ExaError::Fatal("divide by zero").into());
+            return Err(ExaError::DivideByZero);
         }
 
-        let value = self.read_target(left)? / right;
+        let value = self.read_number(vm, left)? / right;
 
-        match dest {
-            Target::Literal(_) => Err(ExaError::Fatal("cannot write to literal").into()),
-            Target::Register(r) => self.write_register(r, clamp(value, -9999, 9999)),
-        }
+        self.write_operand(vm, dest, Value::Number(clamp(value, -9999, 9999)))
     }
 
-    fn modi(&mut self, left: &Target, right: &Target, dest: &Target) -> ExaResult {
-        let right = self.read_target(right)?;
+    fn modi(&mut self, vm: &mut VM, left: &Operand, right: &Operand, dest: &Operand) -> ExaResult {
+        let right = self.read_number(vm, right)?;
         if right == 0 {
-            return Err(ExaError::Fatal("divide by zero").into());
+            return Err(ExaError::DivideByZero);
         }
 
-        let left = self.read_target(left)?;
+        let left = self.read_number(vm, left)?;
         let r = left % right;
         let value = if r < 0 { r + right } else { r };
 
-        match dest {
-            Target::Literal(_) => Err(ExaError::Fatal("cannot write to literal").into()),
-            Target::Register(r) => self.write_register(r, clamp(value, -9999, 9999)),
-        }
+        self.write_operand(vm, dest, Value::Number(clamp(value, -9999, 9999)))
     }
 
-    fn swiz(&mut self, input: &Target, mask: &Target, dest: &Target) -> ExaResult {
+    fn swiz(&mut self, vm: &mut VM, input: &Operand, mask: &Operand, dest: &Operand) -> ExaResult {
         let mut value: i32 = 0;
-        let input_value = self.read_target(input)?;
-        let mask_value = self.read_target(mask)?;
+        let input_value = self.read_number(vm, input)?;
+        let mask_value = self.read_number(vm, mask)?;
 
         let mut input_digits = int_to_digits(input_value);
         input_digits.reverse();
         let mask_digits = int_to_digits(mask_value);
 
-        for (idx, m_digit) in mask_digits.into_iter().enumerate() {
+        for (idx, m_digit) in mask_digits.iter().copied().enumerate() {
             match m_digit {
                 1..=4 => {
                     let power = ((idx as i32) - 3).abs() as u32;
@@ -274,39 +301,42 @@ impl<'a> Exa<'a> {
             value *= -1;
         }
 
-        match dest {
-            Target::Literal(_) => Err(ExaError::Fatal("cannot write to literal").into()),
-            Target::Register(r) => self.write_register(r, value),
-        }
+        self.write_operand(vm, dest, Value::Number(value))
     }
 
-    fn jump(&mut self, label: &String) -> ExaResult {
-        match self.labels.get(label) {
-            Some(position) => {
+    fn jump(&mut self, target: &Jump) -> ExaResult {
+        match target {
+            Jump::Pc(pc) => {
                 // Need to -1 because we will increase the PC
                 // when we return back to run_cycle
-                self.pc = *position - 1;
+                self.pc = pc.wrapping_sub(1);
                 Ok(())
             }
-            _ => Err(ExaError::Fatal("unknown label").into()),
+            Jump::Unknown(label) => Err(ExaError::UnknownLabel(label.clone())),
         }
     }
 
-    fn tjmp(&mut self, label: &String) -> ExaResult {
-        if self.read_register("t")? != 0 {
-            return self.jump(label);
+    fn tjmp(&mut self, target: &Jump) -> ExaResult {
+        if self.read_reg(Reg::T)? != Value::Number(0) {
+            return self.jump(target);
         }
         Ok(())
     }
-    fn fjmp(&mut self, label: &String) -> ExaResult {
-        if self.read_register("t")? == 0 {
-            return self.jump(label);
+    fn fjmp(&mut self, target: &Jump) -> ExaResult {
+        if self.read_reg(Reg::T)? == Value::Number(0) {
+            return self.jump(target);
         }
         Ok(())
     }
 
-    fn test(&mut self, left: &Target, comp: &Comparator, right: &Target) -> ExaResult {
-        let (l, r) = (self.read_target(left)?, self.read_target(right)?);
+    fn test(
+        &mut self,
+        vm: &mut VM,
+        left: &Operand,
+        comp: &Comparator,
+        right: &Operand,
+    ) -> ExaResult {
+        let (l, r) = (self.read_operand(vm, left)?, self.read_operand(vm, right)?);
 
         let is_true: bool;
         match comp {
@@ -315,46 +345,48 @@ impl<'a> Exa<'a> {
             Comparator::LessThan => is_true = l < r,
         }
 
-        self.write_register("t", if is_true { 1 } else { 0 })?;
-        Ok(())
+        self.write_reg(Reg::T, Value::Number(if is_true { 1 } else { 0 }))
     }
 
-    fn repl(&mut self, vm: &mut VM<'a>, label: &String) -> ExaResult {
-        let target_pc = match self.labels.get(label) {
-            Some(position) => Ok(*position),
-            _ => Err(Box::new(ExaError::Fatal("unknown label"))),
-        }?;
+    fn repl(&mut self, vm: &mut VM, target: &Jump) -> ExaResult {
+        let target_pc = match target {
+            Jump::Pc(pc) => *pc,
+            Jump::Unknown(label) => return Err(ExaError::UnknownLabel(label.clone())),
+        };
 
         self.inner_repl(vm, target_pc)?;
+        if vm.is_tracing() {
+            let child = vm.spawned.last().unwrap().name.to_string();
+            vm.trace(&self.name, self.pc, Event::Repl { child });
+        }
         Ok(())
     }
 
     /// TEST MRD is a special little snowflake and is run by the VM
     /// after all other processing. It won't ever error, so we don't
     /// return an ExaResult from it.
-    pub fn test_mrd(&mut self) {
+    pub fn test_mrd(&mut self, global_bus: &MessageBus, hosts: &Hosts) {
         let ready = match self.mode {
-            Mode::Global => self.global_bus.borrow().has_messages(),
-            Mode::Local => self.host.borrow().bus.has_messages(),
+            Mode::Global => global_bus.has_messages(),
+            Mode::Local => hosts[self.host].bus.has_messages(),
         };
-        self.write_register("t", if ready { 1 } else { 0 })
+        self.write_reg(Reg::T, Value::Number(if ready { 1 } else { 0 }))
             .expect("error writing to T from test mrd");
     }
 
-    fn make_file(&mut self) -> ExaResult {
+    fn make_file(&mut self, vm: &mut VM) -> ExaResult {
         if self.file.is_some() {
-            return Err(ExaError::Fatal("cannot grab a second file").into());
+            return Err(ExaError::FileAlreadyHeld);
         }
-        let file_id = self.file_counter.fetch_add(1, Ordering::Relaxed);
-        self.file = Some(File::new(file_id, vec![]));
+        self.file = Some(File::new(vm.next_file_id(), vec![]));
         Ok(())
     }
 
-    fn drop_file(&mut self) -> ExaResult {
+    fn drop_file(&mut self, vm: &mut VM) -> ExaResult {
         if self.file.is_none() {
-            return Err(ExaError::Fatal("no file is held").into());
+            return Err(ExaError::NoFileHeld);
         }
-        let mut host_mut = self.host.borrow_mut();
+        let host_mut = &mut vm.hosts[self.host];
         host_mut.reserve_slot()?;
 
         let f = self.file.take();
@@ -366,7 +398,7 @@ impl<'a> Exa<'a> {
 
     fn wipe_file(&mut self) -> ExaResult {
         if self.file.is_none() {
-            return Err(ExaError::Fatal("no file is held").into());
+            return Err(ExaError::NoFileHeld);
         }
 
         self.file = None;
@@ -375,15 +407,16 @@ impl<'a> Exa<'a> {
         Ok(())
     }
 
-    fn grab_file(&mut self, file_target: &Target) -> ExaResult {
-        let file_id = self.read_target(file_target)?;
+    fn grab_file(&mut self, vm: &mut VM, file_target: &Operand) -> ExaResult {
+        let file_id = self.read_number(vm, file_target)?;
         if self.file.is_some() {
-            return Err(ExaError::Fatal("cannot grab a second file").into());
+            return Err(ExaError::FileAlreadyHeld);
         }
 
+        let host = &mut vm.hosts[self.host];
         let mut ok = false;
         {
-            let files = &mut self.host.borrow_mut().files;
+            let files = &mut host.files;
             let mut i = 0;
             while i < files.len() {
                 let f = &files[i];
@@ -398,19 +431,19 @@ impl<'a> Exa<'a> {
         }
 
         if ok {
-            self.host.borrow_mut().free_slot();
+            host.free_slot();
             Ok(())
         } else {
-            Err(ExaError::Fatal("file id not found").into())
+            Err(ExaError::FileNotFound(file_id))
         }
     }
 
-    fn seek_file(&mut self, target: &Target) -> ExaResult {
+    fn seek_file(&mut self, vm: &mut VM, target: &Operand) -> ExaResult {
         if self.file.is_none() {
-            return Err(ExaError::Fatal("no file is held").into());
+            return Err(ExaError::NoFileHeld);
         }
 
-        let seek_amount = self.read_target(target)?;
+        let seek_amount = self.read_number(vm, target)?;
         self.file_pointer += seek_amount as isize;
 
         if self.file_pointer < 0 {
@@ -424,12 +457,12 @@ impl<'a> Exa<'a> {
 
     fn void_file(&mut self) -> ExaResult {
         if self.file.is_none() {
-            return Err(ExaError::Fatal("no file is held").into());
+            return Err(ExaError::NoFileHeld);
         }
 
         let f = self.file.as_mut().unwrap();
         if self.file_pointer >= f.contents.len() as isize {
-            return Err(ExaError::Fatal("cannot void from file at append position").into());
+            return Err(ExaError::EndOfFile);
         }
 
         f.contents.remove(self.file_pointer as usize);
@@ -437,104 +470,114 @@ impl<'a> Exa<'a> {
         Ok(())
     }
 
-    fn file_command(&mut self, target: &Target) -> ExaResult {
+    fn file_command(&mut self, vm: &mut VM, target: &Operand) -> ExaResult {
         if self.file.is_none() {
-            return Err(ExaError::Fatal("no file is held").into());
+            return Err(ExaError::NoFileHeld);
         }
 
         let file_id = self.file.as_ref().unwrap().id;
-        match target {
-            Target::Literal(_) => Err(ExaError::Fatal("cannot write to literal").into()),
-            Target::Register(r) => self.write_register(r, file_id),
-        }
+        self.write_operand(vm, target, Value::Number(file_id))
     }
 
     fn test_eof(&mut self) -> ExaResult {
         if self.file.is_none() {
-            return Err(ExaError::Fatal("no file is held").into());
+            return Err(ExaError::NoFileHeld);
         }
         let at_end = self.file_pointer == self.file.as_ref().unwrap().contents.len() as isize;
         let value = if at_end { 1 } else { 0 };
 
-        return self.write_register("t", value);
+        self.write_reg(Reg::T, Value::Number(value))
     }
 
-    fn rand(&mut self, lo: &Target, hi: &Target, dest: &Target) -> ExaResult {
-        let (lo_value, hi_value) = (self.read_target(lo)?, self.read_target(hi)?);
+    fn rand(&mut self, vm: &mut VM, lo: &Operand, hi: &Operand, dest: &Operand) -> ExaResult {
+        let (lo_value, hi_value) = (self.read_number(vm, lo)?, self.read_number(vm, hi)?);
         if lo_value > hi_value {
-            return Err(ExaError::Fatal("invalid rand range").into());
-        }
-
-        let value = fastrand::i32(lo_value..=hi_value);
-        match dest {
-            Target::Literal(_) => Err(ExaError::Fatal("cannot write to literal").into()),
-            Target::Register(r) => self.write_register(r, value),
-        }
-    }
-
-    fn read_target(&mut self, t: &Target) -> Result<i32, Box<dyn Error>> {
-        match t {
-            Target::Literal(l) => Ok(*l),
-            Target::Register(r) => self.read_register(r),
+            return Err(ExaError::InvalidRandRange);
+        }
+
+        let value = vm.rng.i32(lo_value..=hi_value);
+        self.write_operand(vm, dest, Value::Number(value))
+    }
+
+    /// HOST writes the name of the EXA's current host as a keyword.
+    fn host(&mut self, vm: &mut VM, dest: &Operand) -> ExaResult {
+        let name = Value::keyword(&vm.hosts[self.host].name);
+        self.write_operand(vm, dest, name)
+    }
+
+    fn read_operand(&mut self, vm: &mut VM, o: &Operand) -> Result<Value, ExaError> {
+        match o {
+            Operand::Number(n) => Ok(Value::Number(*n)),
+            Operand::Keyword(k) => Ok(k.clone()),
+            Operand::Register(r) => self.read_reg(*r),
+            Operand::M => self.read_from_bus(vm),
+            Operand::F => self.read_from_file(),
+            // Embedders can hand back any number, but EXAs can only hold
+            // what fits in four digits
+            Operand::Hardware(slot) => match self
+                .hardware_register(&mut vm.hosts, *slot)?
+                .on_read()
+                .map_err(ExaError::from_hardware)?
+            {
+                Value::Number(n) => Ok(Value::Number(clamp(n, -9999, 9999))),
+                keyword => Ok(keyword),
+            },
+            Operand::Unknown(name) => Err(ExaError::UnknownRegister(name.clone())),
+        }
+    }
+
+    /// Same as read_operand, for the instructions that only work on numbers.
+    fn read_number(&mut self, vm: &mut VM, o: &Operand) -> Result<i32, ExaError> {
+        match self.read_operand(vm, o)? {
+            Value::Number(n) => Ok(n),
+            Value::Keyword(_) => Err(ExaError::NumberRequired),
+        }
+    }
+
+    fn write_operand(&mut self, vm: &mut VM, o: &Operand, value: Value) -> ExaResult {
+        match o {
+            Operand::Number(_) | Operand::Keyword(_) => Err(ExaError::WriteToLiteral),
+            Operand::Register(r) => self.write_reg(*r, value),
+            Operand::M => self.write_to_bus(vm, value),
+            Operand::F => self.write_to_file(value),
+            Operand::Hardware(slot) => {
+                let value = clamp_to(self.program.hardware[*slot].range, value)?;
+                self.hardware_register(&mut vm.hosts, *slot)?
+                    .on_write(value)
+                    .map_err(ExaError::from_hardware)
+            }
+            Operand::Unknown(name) => Err(ExaError::UnknownRegister(name.clone())),
         }
     }
 
-    pub fn read_register(&mut self, r_specifier: &str) -> Result<i32, Box<dyn Error>> {
-        if r_specifier == "m" {
-            return self.read_from_bus();
-        } else if r_specifier == "f" {
+    /// Read one of the EXA's own registers or F by name, the same as an
+    /// instruction naming it. M and hardware registers belong to the VM,
+    /// so they can't be read from here.
+    pub fn read_register(&mut self, r_specifier: &str) -> Result<Value, ExaError> {
+        if r_specifier == "f" {
             return self.read_from_file();
         }
 
-        let r = self.resolve_register(r_specifier)?;
-        let b = r.borrow();
-
-        match b.permissions {
-            Permissions::Denied => {
-                return Err(ExaError::Fatal("attempt to read from deactivated register").into());
-            }
-            Permissions::WriteOnly => {
-                return Err(ExaError::Fatal("attempt to read from write-only register").into())
-            }
-            _ => (),
+        match Reg::from_name(r_specifier) {
+            Some(r) => self.read_reg(r),
+            None => Err(ExaError::UnknownRegister(r_specifier.to_string())),
         }
-
-        Ok(b.value)
     }
 
-    fn write_register(&mut self, r_specifier: &str, value: i32) -> ExaResult {
-        if r_specifier == "m" {
-            return self.write_to_bus(value);
-        } else if r_specifier == "f" {
-            return self.write_to_file(value);
-        } else if r_specifier == "gp" {
-            return self.write_sprite(value);
-        }
-
-        let r = self.resolve_register(r_specifier)?;
-        let mut b = r.borrow_mut();
+    fn read_reg(&mut self, r: Reg) -> Result<Value, ExaError> {
+        self.reg(r).on_read().map_err(ExaError::from_hardware)
+    }
 
-        match b.permissions {
-            Permissions::Denied => {
-                return Err(ExaError::Fatal("attempt to write to deactivated register").into());
-            }
-            Permissions::ReadOnly => {
-                return Err(ExaError::Fatal("attempt to write to read-only register").into())
-            }
-            _ => (),
+    fn write_reg(&mut self, r: Reg, value: Value) -> ExaResult {
+        if r == Reg::GP {
+            return match value {
+                Value::Number(n) => self.write_sprite(n),
+                Value::Keyword(_) => Err(ExaError::NumberRequired),
+            };
         }
 
-        b.value = match r_specifier {
-            "gx" => clamp(value, -10, 120),
-            "gy" => clamp(value, -10, 100),
-            "gz" => clamp(value, -9, 9),
-            "#sqr0" => clamp(value, 0, 99),
-            "#sqr1" => clamp(value, 0, 99),
-            "#tri0" => clamp(value, 0, 99),
-            "#nse0" => clamp(value, 0, 99),
-            _ => value,
-        };
-        Ok(())
+        let value = clamp_to(r.range(), value)?;
+        self.reg(r).on_write(value).map_err(ExaError::from_hardware)
     }
 
     fn write_sprite(&mut self, value: i32) -> ExaResult {
@@ -553,24 +596,24 @@ impl<'a> Exa<'a> {
         Ok(())
     }
 
-    fn read_from_file(&mut self) -> Result<i32, Box<dyn Error>> {
+    fn read_from_file(&mut self) -> Result<Value, ExaError> {
         if self.file.is_none() {
-            return Err(ExaError::Fatal("no file is held").into());
+            return Err(ExaError::NoFileHeld);
         }
 
         let f = self.file.as_ref().unwrap();
         if self.file_pointer >= f.contents.len() as isize {
-            return Err(ExaError::Fatal("cannot read from file at append position").into());
+            return Err(ExaError::EndOfFile);
         }
 
-        let value = f.contents[self.file_pointer as usize];
+        let value = f.contents[self.file_pointer as usize].clone();
         self.file_pointer += 1;
         Ok(value)
     }
 
-    fn write_to_file(&mut self, value: i32) -> ExaResult {
+    fn write_to_file(&mut self, value: Value) -> ExaResult {
         if self.file.is_none() {
-            return Err(ExaError::Fatal("no file is held").into());
+            return Err(ExaError::NoFileHeld);
         }
 
         let f = self.file.as_mut().unwrap();
@@ -585,10 +628,10 @@ impl<'a> Exa<'a> {
         Ok(())
     }
 
-    pub fn read_from_bus(&mut self) -> Result<i32, Box<dyn Error>> {
+    pub fn read_from_bus(&mut self, vm: &mut VM) -> Result<Value, ExaError> {
         let message = match self.mode {
-            Mode::Global => self.global_bus.borrow_mut().read(),
-            Mode::Local => self.host.borrow_mut().bus.read(),
+            Mode::Global => vm.bus.read(),
+            Mode::Local => vm.hosts[self.host].bus.read(),
         }?;
 
         self.result.unfreeze_exa = Some(message.sender);
@@ -596,104 +639,84 @@ impl<'a> Exa<'a> {
         Ok(message.value)
     }
 
-    pub fn write_to_bus(&mut self, value: i32) -> ExaResult {
+    pub fn write_to_bus(&mut self, vm: &mut VM, value: Value) -> ExaResult {
         match self.mode {
-            Mode::Global => self.global_bus.borrow_mut().write(self, value),
-            Mode::Local => self.host.borrow_mut().bus.write(self, value),
-        }
+            Mode::Global => vm.bus.write(self, value),
+            Mode::Local => vm.hosts[self.host].bus.write(self, value),
+        }
+    }
+
+    /// The register behind one of the program's hardware slots, in the
+    /// current host. Where it lives is kept until the EXA changes hosts.
+    fn hardware_register<'h>(
+        &mut self,
+        hosts: &'h mut Hosts,
+        slot: usize,
+    ) -> Result<&'h mut dyn HardwareRegister, ExaError> {
+        if self.bound_host != Some(self.host) {
+            self.bound_host = Some(self.host);
+            self.hardware.clear();
+            self.hardware.resize(self.program.hardware.len(), None);
+        }
+
+        let registers = &mut hosts[self.host].registers;
+        let position = match self.hardware[slot] {
+            Some(position) => position,
+            None => {
+                let name = &self.program.hardware[slot].name;
+                let position = registers
+                    .position(&name.to_ascii_lowercase())
+                    .ok_or_else(|| ExaError::UnknownRegister(name.clone()))?;
+                self.hardware[slot] = Some(position);
+                position
+            }
+        };
+        Ok(registers.slot_mut(position))
     }
 
-    fn resolve_register(&self, r_specifier: &str) -> Result<Shared<Register>, Box<dyn Error>> {
-        if r_specifier.starts_with('#') {
-            return self.resolve_hardware_register(r_specifier);
-        } else {
-            return self.resolve_exa_register(r_specifier);
+    fn reg(&mut self, r: Reg) -> &mut Register {
+        match r {
+            Reg::X => &mut self.registers.x,
+            Reg::T => &mut self.registers.t,
+            Reg::GX => &mut self.registers.gx,
+            Reg::GY => &mut self.registers.gy,
+            Reg::GZ => &mut self.registers.gz,
+            Reg::GP => &mut self.registers.gp,
+            Reg::CI => &mut self.registers.ci,
+            Reg::CO => &mut self.registers.co,
         }
     }
 
-    fn resolve_hardware_register(
-        &self,
-        r_specifier: &str,
-    ) -> Result<Shared<Register>, Box<dyn Error>> {
-        let h = self.host.borrow();
-        let r = h.registers.get(&r_specifier.to_ascii_lowercase());
-
-        match r {
-            Some(reg) => Ok(reg.clone()),
-            None => Err(ExaError::Fatal("attempt to access unknown hardware register").into()),
-        }
-    }
-
-    fn resolve_exa_register(&self, r_specifier: &str) -> Result<Shared<Register>, Box<dyn Error>> {
-        let r = match r_specifier.to_ascii_lowercase().as_str() {
-            "x" => self.registers.x.clone(),
-            "t" => self.registers.t.clone(),
-            "gx" => self.registers.gx.clone(),
-            "gy" => self.registers.gy.clone(),
-            "gz" => self.registers.gz.clone(),
-            "gp" => self.registers.gp.clone(),
-            "ci" => self.registers.ci.clone(),
-            "co" => self.registers.co.clone(),
-            _ => return Err(ExaError::Fatal("attempted to access unknown exa register").into()),
-        };
-        Ok(r)
+    pub fn coords(&self) -> (i32, i32) {
+        (self.registers.gx.number(), self.registers.gy.number())
     }
 
-    pub fn coords(&self) -> (i32, i32) {
-        (
-            self.registers.gx.borrow().value,
-            self.registers.gy.borrow().value,
-        )
+    pub fn depth(&self) -> i32 {
+        self.registers.gz.number()
     }
 
     pub fn reset_collision(&mut self) {
-        self.registers.ci.borrow_mut().value = -9999;
-    }
-
-    pub fn update_collision(&mut self, other: &Exa) {
-        let (self_x, self_y) = (
-            self.registers.gx.borrow().value,
-            self.registers.gy.borrow().value,
-        );
-        let (other_x, other_y) = (
-            other.registers.gx.borrow().value,
-            other.registers.gy.borrow().value,
-        );
-
-        // Quick bounds check and bail
-        let (x_diff, y_diff) = (self_x - other_x, self_y - other_y);
-        if x_diff.abs() >= 10 || y_diff.abs() >= 10 {
-            return;
-        }
+        self.registers.ci.value = Value::Number(-9999);
+    }
 
-        for (idx, pixel) in self.sprite.pixels.iter().enumerate() {
-            if !*pixel {
-                continue;
-            }
-            let (row, col) = (idx as i32 / 10, idx as i32 % 10);
-            let (other_row, other_col) = (row + y_diff, col + x_diff);
-            if other_row < 0 || other_row > 9 || other_col < 0 || other_col > 9 {
-                continue;
-            }
+    pub fn update_collision(&mut self, other: &mut Exa) {
+        let (self_x, self_y) = self.coords();
+        let (other_x, other_y) = other.coords();
 
-            let other_idx = (other_row * 10 + other_col) as usize;
-            let other_pixel = other.sprite.pixels[other_idx];
-            if other_pixel {
-                let mut self_ci = self.registers.ci.borrow_mut();
-                let mut other_ci = other.registers.ci.borrow_mut();
-                let self_co = self.registers.co.borrow().value;
-                let other_co = other.registers.co.borrow().value;
+        let (dx, dy) = (other_x - self_x, other_y - self_y);
+        if !self.sprite.overlaps(&other.sprite, dx, dy) {
+            return;
+        }
 
-                if self_co > other_ci.value {
-                    other_ci.value = self_co;
-                }
+        let self_co = self.registers.co.number();
+        let other_co = other.registers.co.number();
 
-                if other_co > self_ci.value {
-                    self_ci.value = other_co;
-                }
+        if self_co > other.registers.ci.number() {
+            other.registers.ci.value = Value::Number(self_co);
+        }
 
-                return;
-            }
+        if other_co > self.registers.ci.number() {
+            self.registers.ci.value = Value::Number(other_co);
         }
     }
 }
@@ -704,11 +727,11 @@ mod tests {
 
     #[test]
     fn test_digits() {
-        assert_eq!(int_to_digits(1234), vec![1, 2, 3, 4]);
-        assert_eq!(int_to_digits(0), vec![0, 0, 0, 0]);
-        assert_eq!(int_to_digits(8), vec![0, 0, 0, 8]);
-        assert_eq!(int_to_digits(56), vec![0, 0, 5, 6]);
-        assert_eq!(int_to_digits(123), vec![0, 1, 2, 3]);
-        assert_eq!(int_to_digits(-9876), vec![9, 8, 7, 6]);
+        assert_eq!(int_to_digits(1234), [1, 2, 3, 4]);
+        assert_eq!(int_to_digits(0), [0, 0, 0, 0]);
+        assert_eq!(int_to_digits(8), [0, 0, 0, 8]);
+        assert_eq!(int_to_digits(56), [0, 0, 5, 6]);
+        assert_eq!(int_to_digits(123), [0, 1, 2, 3]);
+        assert_eq!(int_to_digits(-9876), [9, 8, 7, 6]);
     }
 }
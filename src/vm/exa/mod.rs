@@ -1,95 +1,73 @@
 mod cycle;
 pub mod sprite;
+mod state;
 
-use std::cell::RefCell;
-use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
-use std::rc::Rc;
-use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 
-use super::super::parse::parse_text;
-use super::bus::MessageBus;
-use super::error::ExaError;
+use super::super::parse::parse_text_with_spans;
+use super::debug::{ExaInfo, ExaStatus, Location};
+use super::error::{ExaError, Fault, Severity};
 use super::file::File;
-use super::instruction::{Instruction, Target};
+use super::instruction::Span;
+use super::program::{Op, Operand, Program, Reg};
 use super::register::Register;
+use super::trace::Bus;
+use super::value::Value;
 use super::Permissions;
-use super::{Host, Shared, VM};
+use super::{HostId, Hosts, VM};
 
 use cycle::CycleResult;
 use sprite::Sprite;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Registers {
-    x: Shared<Register>,
-    t: Shared<Register>,
-    gx: Shared<Register>,
-    gy: Shared<Register>,
-    gz: Shared<Register>,
-    gp: Shared<Register>,
-    ci: Shared<Register>,
-    co: Shared<Register>,
+    x: Register,
+    t: Register,
+    gx: Register,
+    gy: Register,
+    gz: Register,
+    gp: Register,
+    ci: Register,
+    co: Register,
 }
 
 impl Registers {
     pub fn new() -> Registers {
         Registers {
-            x: Register::new_shared(Permissions::ReadWrite, 0),
-            t: Register::new_shared(Permissions::ReadWrite, 0),
-            gx: Register::new_shared(Permissions::Denied, 0),
-            gy: Register::new_shared(Permissions::Denied, 0),
-            gz: Register::new_shared(Permissions::Denied, 0),
-            gp: Register::new_shared(Permissions::Denied, 0),
-            ci: Register::new_shared(Permissions::Denied, 0),
-            co: Register::new_shared(Permissions::Denied, 0),
+            x: Register::new(Permissions::ReadWrite, 0),
+            t: Register::new(Permissions::ReadWrite, 0),
+            gx: Register::new(Permissions::Denied, 0),
+            gy: Register::new(Permissions::Denied, 0),
+            gz: Register::new(Permissions::Denied, 0),
+            gp: Register::new(Permissions::Denied, 0),
+            ci: Register::new(Permissions::Denied, 0),
+            co: Register::new(Permissions::Denied, 0),
         }
     }
 
     pub fn new_redshift() -> Registers {
         Registers {
-            x: Register::new_shared(Permissions::ReadWrite, 0),
-            t: Register::new_shared(Permissions::ReadWrite, 0),
-            gx: Register::new_shared(Permissions::ReadWrite, 0),
-            gy: Register::new_shared(Permissions::ReadWrite, 0),
-            gz: Register::new_shared(Permissions::ReadWrite, 0),
-            gp: Register::new_shared(Permissions::WriteOnly, 0),
-            ci: Register::new_shared(Permissions::ReadOnly, -9999),
-            co: Register::new_shared(Permissions::ReadWrite, 0),
+            x: Register::new(Permissions::ReadWrite, 0),
+            t: Register::new(Permissions::ReadWrite, 0),
+            gx: Register::new(Permissions::ReadWrite, 0),
+            gy: Register::new(Permissions::ReadWrite, 0),
+            gz: Register::new(Permissions::ReadWrite, 0),
+            gp: Register::new(Permissions::WriteOnly, 0),
+            ci: Register::new(Permissions::ReadOnly, -9999),
+            co: Register::new(Permissions::ReadWrite, 0),
         }
     }
 
-    /// "True" clone that creates new registers for the descendant EXA,
-    /// not new references to the original (which is what happens with
-    /// a normal clone given we're working with Rcs)
+    /// Registers for a descendant EXA, which starts with everything but
+    /// GP and CI.
     pub fn clone_for_repl(&self) -> Registers {
         Registers {
-            x: Register::new_shared(
-                self.x.borrow().permissions.clone(),
-                self.x.borrow().value.clone(),
-            ),
-            t: Register::new_shared(
-                self.t.borrow().permissions.clone(),
-                self.t.borrow().value.clone(),
-            ),
-            gx: Register::new_shared(
-                self.gx.borrow().permissions.clone(),
-                self.gx.borrow().value.clone(),
-            ),
-            gy: Register::new_shared(
-                self.gy.borrow().permissions.clone(),
-                self.gy.borrow().value.clone(),
-            ),
-            gz: Register::new_shared(
-                self.gz.borrow().permissions.clone(),
-                self.gz.borrow().value.clone(),
-            ),
-            gp: Register::new_shared(self.gp.borrow().permissions.clone(), 0),
-            ci: Register::new_shared(self.ci.borrow().permissions.clone(), -9999),
-            co: Register::new_shared(
-                self.co.borrow().permissions.clone(),
-                self.co.borrow().value.clone(),
-            ),
+            gp: Register::new(self.gp.permissions.clone(), 0),
+            ci: Register::new(self.ci.permissions.clone(), -9999),
+            ..self.clone()
         }
     }
 }
@@ -110,26 +88,26 @@ impl fmt::Display for Mode {
 }
 
 #[derive(Debug)]
-pub struct Exa<'a> {
+pub struct Exa {
     base_name: String,
     spawn_id: u32,
-    pub name: String,
+    pub name: Arc<str>,
 
     registers: Registers,
     result: CycleResult,
-    spawn_counter: Rc<AtomicU32>,
-    file_counter: Rc<AtomicI32>,
+    spawn_counter: Arc<AtomicU32>,
 
     pc: usize,
-    instructions: Vec<Instruction>,
-    // Map of label name to index in self.instructions
-    labels: HashMap<String, usize>,
+    program: Arc<Program>,
+    // Slots in bound_host's registers for each of program.hardware,
+    // looked up as they're first used
+    bound_host: Option<HostId>,
+    hardware: Vec<Option<usize>>,
 
     pub mode: Mode,
-    global_bus: Shared<MessageBus>,
 
-    pub host: Shared<Host<'a>>,
-    pub error: Option<Box<dyn Error>>,
+    pub host: HostId,
+    pub error: Option<Fault>,
 
     file_pointer: isize,
     pub file: Option<File>,
@@ -140,84 +118,86 @@ pub struct Exa<'a> {
     pub waiting: bool,
 }
 
-impl PartialEq for Exa<'_> {
+impl PartialEq for Exa {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name
     }
 }
 
-impl Eq for Exa<'_> {}
+impl Eq for Exa {}
 
-impl<'a> Exa<'a> {
+impl Exa {
     /// Spawn an Exa in the specified Host, if there is available space.
-    pub fn spawn(
-        vm: &mut VM<'a>,
-        host: Shared<Host<'a>>,
+    pub fn spawn<'v>(
+        vm: &'v mut VM,
+        host: HostId,
         name: String,
         redshift: bool,
         script: &str,
-    ) -> Result<Shared<Exa<'a>>, Box<dyn Error>> {
+    ) -> Result<&'v mut Exa, Box<dyn Error>> {
         // TODO: VM check on name uniqueness
-        host.borrow_mut().reserve_slot()?;
-        let mut insts = parse_text(script).unwrap();
-        let data_file = Exa::extract_data(&mut insts, vm.file_counter.clone());
-        let labels = Exa::extract_labels(&mut insts);
-        let e = Rc::new(RefCell::new(Exa {
+        let (insts, spans) = parse_text_with_spans(script)?;
+        vm.hosts[host].reserve_slot()?;
+        let (program, data) = Program::compile(insts, spans);
+        let data_file = if data.is_empty() {
+            None
+        } else {
+            Some(File::new(vm.next_file_id(), data))
+        };
+        let e = Exa {
             base_name: name.clone(),
             spawn_id: 0,
-            name,
+            name: name.into(),
             registers: if redshift {
                 Registers::new_redshift()
             } else {
                 Registers::new()
             },
             pc: 0,
-            instructions: insts,
-            labels: labels,
+            program: Arc::new(program),
+            bound_host: None,
+            hardware: vec![],
             mode: Mode::Global,
             file_pointer: 0,
             file: data_file,
-            global_bus: vm.bus.clone(),
-            host: host,
+            host,
             error: None,
             result: CycleResult::new(),
-            spawn_counter: Rc::new(AtomicU32::new(1)),
-            file_counter: vm.file_counter.clone(),
+            spawn_counter: Arc::new(AtomicU32::new(1)),
             sprite: Sprite::empty(),
             ran_test_mrd_this_cycle: false,
             waiting: false,
-        }));
-        vm.register_exa(e.clone());
-        Ok(e)
+        };
+        Ok(vm.register_exa(e))
     }
 
-    pub fn inner_repl(&self, vm: &mut VM<'a>, pc: usize) -> Result<(), Box<dyn Error>> {
-        self.host.borrow_mut().reserve_slot()?;
+    /// REPL a child starting at pc. It joins the VM once this EXA has
+    /// finished its instruction.
+    pub(crate) fn inner_repl(&self, vm: &mut VM, pc: usize) -> Result<(), ExaError> {
+        vm.hosts[self.host].reserve_slot()?;
 
         let (name, spawn_id) = self.name_and_id_for_repl();
 
-        let e = Rc::new(RefCell::new(Exa {
+        vm.spawned.push(Exa {
             base_name: self.base_name.clone(),
             spawn_id: spawn_id,
-            name: name,
+            name: name.into(),
             registers: self.registers.clone_for_repl(),
             pc,
-            instructions: self.instructions.clone(),
-            labels: self.labels.clone(),
+            program: self.program.clone(),
+            bound_host: None,
+            hardware: vec![],
             mode: self.mode,
             file_pointer: 0,
             file: None,
-            global_bus: self.global_bus.clone(),
-            host: self.host.clone(),
+            host: self.host,
             error: None,
             result: CycleResult::new(),
             spawn_counter: self.spawn_counter.clone(),
-            file_counter: self.file_counter.clone(),
             sprite: self.sprite.clone(),
             ran_test_mrd_this_cycle: false,
             waiting: false,
-        }));
-        vm.register_exa(e);
+        });
         Ok(())
     }
 
@@ -229,169 +209,199 @@ impl<'a> Exa<'a> {
         return (name, num);
     }
 
-    fn extract_labels(instructions: &mut Vec<Instruction>) -> HashMap<String, usize> {
-        let mut m = HashMap::new();
-
-        let mut idx = 0;
-        while idx < instructions.len() {
-            let inst = &instructions[idx];
-            match inst {
-                Instruction::Mark(label) => {
-                    m.insert(label.to_string(), idx);
-                    instructions.remove(idx);
-                }
-                _ => idx += 1,
-            }
-        }
-
-        m
+    pub fn is_fatal(&self) -> bool {
+        self.error
+            .as_ref()
+            .is_some_and(|e| e.severity() == Severity::Fatal)
     }
 
-    fn extract_data(
-        instructions: &mut Vec<Instruction>,
-        file_counter: Rc<AtomicI32>,
-    ) -> Option<File> {
-        let mut contents: Vec<i32> = vec![];
-
-        let mut idx = 0;
-        while idx < instructions.len() {
-            let inst = &instructions[idx];
-            match inst {
-                Instruction::Data(data) => {
-                    contents.extend(data);
-                    instructions.remove(idx);
-                }
-                _ => idx += 1,
-            }
-        }
-
-        if contents.len() == 0 {
-            return None;
-        }
+    pub fn is_frozen(&self) -> bool {
+        self.error
+            .as_ref()
+            .is_some_and(|e| e.severity() == Severity::Freezing)
+    }
 
-        let file_id = file_counter.fetch_add(1, Ordering::Relaxed);
-        Some(File::new(file_id, contents))
+    /// Record error against the instruction at pc.
+    pub fn fail(&mut self, error: ExaError, cycle: u32) {
+        self.error = Some(Fault {
+            error,
+            exa: self.name.clone(),
+            pc: self.pc,
+            line: self.line(),
+            cycle,
+        });
     }
 
-    pub fn is_fatal(&self) -> bool {
-        match &self.error {
-            None => false,
-            Some(e) => match e.downcast_ref::<ExaError>() {
-                Some(e) => match *e {
-                    ExaError::Fatal(_) => true,
-                    _ => false,
-                },
-                _ => false,
-            },
-        }
+    pub fn will_kill_this_cycle(&self) -> bool {
+        matches!(self.program.ops.get(self.pc), Some(Op::Kill))
     }
 
-    pub fn is_frozen(&self) -> bool {
-        match &self.error {
-            None => false,
-            Some(e) => match e.downcast_ref::<ExaError>() {
-                Some(e) => match *e {
-                    ExaError::Freezing(_) => true,
-                    _ => false,
-                },
-                _ => false,
-            },
-        }
+    pub fn will_test_mrd_this_cycle(&self) -> bool {
+        matches!(self.program.ops.get(self.pc), Some(Op::TestMrd))
     }
 
-    pub fn will_kill_this_cycle(&self) -> bool {
-        if self.instructions.len() == 0 {
-            return false;
-        }
-        match &self.instructions[self.pc] {
-            Instruction::Kill => true,
+    pub fn will_use_ci_this_cycle(&self) -> bool {
+        let op = match self.program.ops.get(self.pc) {
+            Some(op) => op,
+            None => return false,
+        };
+        let ci = |o: &Operand| *o == Operand::Register(Reg::CI);
+        match op {
+            Op::Link(dest) | Op::Host(dest) | Op::Grab(dest) | Op::Seek(dest) | Op::File(dest) => {
+                ci(dest)
+            }
+            Op::Copy(a, b) | Op::Test(a, _, b) => ci(a) || ci(b),
+            Op::Addi(a, b, c)
+            | Op::Subi(a, b, c)
+            | Op::Muli(a, b, c)
+            | Op::Divi(a, b, c)
+            | Op::Modi(a, b, c)
+            | Op::Swiz(a, b, c)
+            | Op::Rand(a, b, c) => ci(a) || ci(b) || ci(c),
             _ => false,
         }
     }
 
-    pub fn will_test_mrd_this_cycle(&self) -> bool {
-        if self.pc >= self.instructions.len() {
-            return false;
-        }
-        match &self.instructions[self.pc] {
-            Instruction::TestMrd => true,
+    /// Which bus the EXA's next instruction reads M from, if it does.
+    pub fn will_read_m_this_cycle(&self, hosts: &Hosts) -> Option<Bus> {
+        let reads_m = match self.program.ops.get(self.pc)? {
+            Op::VoidM => true,
+            Op::Copy(src, _) | Op::Link(src) | Op::Grab(src) | Op::Seek(src) => *src == Operand::M,
+            Op::Addi(left, right, _)
+            | Op::Subi(left, right, _)
+            | Op::Muli(left, right, _)
+            | Op::Divi(left, right, _)
+            | Op::Modi(left, right, _)
+            | Op::Swiz(left, right, _)
+            | Op::Rand(left, right, _)
+            | Op::Test(left, _, right) => *left == Operand::M || *right == Operand::M,
             _ => false,
+        };
+        if reads_m {
+            Some(self.bus(hosts))
+        } else {
+            None
         }
     }
 
-    fn has_ci_target(targets: &[&Target]) -> bool {
-        for target in targets.iter() {
-            match target {
-                Target::Register(ref r) => {
-                    if r == "ci" {
-                        return true;
-                    }
-                }
-                _ => (),
-            }
+    fn bus(&self, hosts: &Hosts) -> Bus {
+        match self.mode {
+            Mode::Global => Bus::Global,
+            Mode::Local => Bus::Host(hosts[self.host].name.clone()),
         }
-        false
     }
 
-    pub fn will_use_ci_this_cycle(&self) -> bool {
-        if self.pc >= self.instructions.len() {
-            return false;
-        }
+    pub fn descendant_of(&self, other: &Exa) -> bool {
+        self.base_name == other.base_name && self.spawn_id > other.spawn_id
+    }
 
-        match &self.instructions[self.pc].clone() {
-            Instruction::Link(ref dest) => Exa::has_ci_target(&[dest]),
-            Instruction::Copy(ref src, ref dest) => Exa::has_ci_target(&[src, dest]),
-            Instruction::Addi(ref left, ref right, ref dest) => {
-                Exa::has_ci_target(&[left, right, dest])
-            }
-            Instruction::Subi(ref left, ref right, ref dest) => {
-                Exa::has_ci_target(&[left, right, dest])
-            }
-            Instruction::Muli(ref left, ref right, ref dest) => {
-                Exa::has_ci_target(&[left, right, dest])
-            }
-            Instruction::Divi(ref left, ref right, ref dest) => {
-                Exa::has_ci_target(&[left, right, dest])
-            }
-            Instruction::Swiz(ref input, ref mask, ref dest) => {
-                Exa::has_ci_target(&[input, mask, dest])
-            }
-            Instruction::Test(ref left, _, ref right) => Exa::has_ci_target(&[left, right]),
-            Instruction::Grab(ref file_target) => Exa::has_ci_target(&[file_target]),
-            Instruction::Seek(ref target) => Exa::has_ci_target(&[target]),
-            Instruction::File(ref target) => Exa::has_ci_target(&[target]),
-            Instruction::Rand(ref lo, ref hi, ref dest) => Exa::has_ci_target(&[lo, hi, dest]),
-            _ => false,
+    pub fn ancestor_of(&self, other: &Exa) -> bool {
+        self.base_name == other.base_name && self.spawn_id < other.spawn_id
+    }
+
+    /// Name of the EXA this one was REPLed from, or its own name if it
+    /// wasn't.
+    pub fn lineage(&self) -> &str {
+        &self.base_name
+    }
+
+    /// Which EXA this is in its lineage: 0 for the original, then 1, 2...
+    /// for each REPL in order.
+    pub fn spawn_id(&self) -> u32 {
+        self.spawn_id
+    }
+
+    /// Index of the next instruction to run.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Source line of the next instruction, if known.
+    pub fn line(&self) -> Option<usize> {
+        self.span().map(|s| s.line)
+    }
+
+    /// Where the next instruction came from in the source, if known.
+    pub fn span(&self) -> Option<Span> {
+        self.program.span(self.pc)
+    }
+
+    pub fn at_location(&self, location: &Location) -> bool {
+        match location {
+            Location::Line(line) => self.line() == Some(*line),
+            Location::Label(label) => self.program.labels.get(label) == Some(&self.pc),
         }
     }
 
-    pub fn descendant_of(&self, other: &Exa<'a>) -> bool {
-        self.base_name == other.base_name && self.spawn_id > other.spawn_id
+    /// Current value of one of the EXA's own registers, or None if it
+    /// doesn't have one by that name. Doesn't read M or F.
+    pub fn register_value(&self, name: &str) -> Option<Value> {
+        self.named_registers()
+            .into_iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, r)| r.value.clone())
     }
 
-    pub fn ancestor_of(&self, other: &Exa<'a>) -> bool {
-        self.base_name == other.base_name && self.spawn_id < other.spawn_id
+    /// Every register the EXA can access, in X, T, GX, GY, GZ, GP, CI,
+    /// CO order.
+    pub fn register_values(&self) -> Vec<(String, Value)> {
+        self.named_registers()
+            .into_iter()
+            .map(|(n, r)| (n.to_string(), r.value.clone()))
+            .collect()
     }
 
-    // Returns (x,y) vector of currently enabled pixels
-    pub fn pixels(&self) -> Vec<(usize, usize)> {
-        let x = self.registers.gx.borrow().value;
-        let y = self.registers.gy.borrow().value;
-        let mut v = vec![];
-        for (idx, pixel) in self.sprite.pixels.iter().enumerate() {
-            if *pixel {
-                let this_x = (idx as i32 % 10) + x;
-                let this_y = (idx as i32 / 10) + y;
-                if 0 <= this_x && 119 >= this_x && 0 <= this_y && 99 >= this_y {
-                    v.push((this_x as usize, this_y as usize));
-                }
-            }
+    fn named_registers(&self) -> Vec<(&'static str, &Register)> {
+        let r = &self.registers;
+        vec![
+            ("X", &r.x),
+            ("T", &r.t),
+            ("GX", &r.gx),
+            ("GY", &r.gy),
+            ("GZ", &r.gz),
+            ("GP", &r.gp),
+            ("CI", &r.ci),
+            ("CO", &r.co),
+        ]
+        .into_iter()
+        .filter(|(_, r)| r.permissions != Permissions::Denied)
+        .collect()
+    }
+
+    pub fn info(&self, hosts: &Hosts) -> ExaInfo {
+        let status = match &self.error {
+            None => ExaStatus::Running,
+            Some(f) => match f.severity() {
+                Severity::Blocking => ExaStatus::Blocked(f.error.clone()),
+                Severity::Freezing => ExaStatus::Frozen(f.error.clone()),
+                Severity::Fatal => ExaStatus::Dead(f.error.clone()),
+            },
+        };
+
+        ExaInfo {
+            name: self.name.to_string(),
+            host: hosts[self.host].name.clone(),
+            pc: self.pc,
+            line: self.line(),
+            instruction: self.program.source.get(self.pc).cloned(),
+            mode: self.mode,
+            registers: self.register_values(),
+            file: self.file.clone(),
+            file_pointer: self.file_pointer,
+            status,
         }
-        v
+    }
+
+    // Calls plot with the (x,y) of every currently enabled pixel
+    // that's on screen, with the whole sprite pushed x_offset pixels
+    // to the right first
+    pub fn draw<F: FnMut(usize, usize)>(&self, x_offset: i32, plot: F) {
+        let (x, y) = self.coords();
+        self.sprite.draw(x + x_offset, y, plot);
     }
 }
 
-impl fmt::Display for Exa<'_> {
+impl fmt::Display for Exa {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
@@ -400,25 +410,25 @@ impl fmt::Display for Exa<'_> {
         )?;
 
         if let Some(e) = &self.error {
-            write!(f, " (error: {})", e)?;
+            write!(f, " (error: {}: {})", e.severity(), e.error)?;
         } else {
             write!(f, " (error: None)")?;
         }
 
-        if self.pc < self.instructions.len() {
-            write!(f, "\n\tInst: {:?}", self.instructions[self.pc])?;
+        if let Some(instruction) = self.program.source.get(self.pc) {
+            write!(f, "\n\tInst: {:?}", instruction)?;
         }
 
         write!(
             f,
             "\n\tX: {} T: {} GX: {} GY: {} GZ: {} CI: {} CO: {}",
-            &self.registers.x.borrow().value,
-            &self.registers.t.borrow().value,
-            &self.registers.gx.borrow().value,
-            &self.registers.gy.borrow().value,
-            &self.registers.gz.borrow().value,
-            &self.registers.ci.borrow().value,
-            &self.registers.co.borrow().value,
+            &self.registers.x.value,
+            &self.registers.t.value,
+            &self.registers.gx.value,
+            &self.registers.gy.value,
+            &self.registers.gz.value,
+            &self.registers.ci.value,
+            &self.registers.co.value,
         )?;
 
         if let Some(file) = &self.file {
@@ -435,45 +445,18 @@ mod tests {
     use super::*;
 
     #[test]
-    fn extract_labels() {
-        let mut insts = vec![
-            Instruction::Mark("first".into()),
-            Instruction::Noop,
-            Instruction::Mark("second".into()),
-            Instruction::Mark("third".into()),
-            Instruction::Noop,
-        ];
-        let extracted = Exa::extract_labels(&mut insts);
-        assert_eq!(insts, vec![Instruction::Noop, Instruction::Noop]);
-        assert_eq!(*extracted.get("first").expect("not found"), 0);
-        assert_eq!(*extracted.get("second").expect("not found"), 1);
-        assert_eq!(*extracted.get("third").expect("not found"), 1);
-    }
-
-    #[test]
-    fn extract_labels_at_end() {
-        let mut insts = vec![
-            Instruction::Mark("first".into()),
-            Instruction::Noop,
-            Instruction::Mark("second".into()),
-            Instruction::Mark("third".into()),
-        ];
-        let extracted = Exa::extract_labels(&mut insts);
-        assert_eq!(insts, vec![Instruction::Noop]);
-        assert_eq!(*extracted.get("first").expect("not found"), 0);
-        assert_eq!(*extracted.get("second").expect("not found"), 1);
-        assert_eq!(*extracted.get("third").expect("not found"), 1);
-    }
-
-    #[test]
-    fn has_ci_target() {
-        assert_eq!(true, Exa::has_ci_target(&[&Target::Register("ci".into())]));
-        assert_eq!(
-            true,
-            Exa::has_ci_target(&[&Target::Literal(123), &Target::Register("ci".into())])
-        );
-        assert_eq!(false, Exa::has_ci_target(&[&Target::Literal(123)]));
-        assert_eq!(false, Exa::has_ci_target(&[&Target::Register("co".into())]));
-        assert_eq!(false, Exa::has_ci_target(&[]));
+    fn will_use_ci() {
+        let uses_ci = |script: &str| {
+            let mut vm = VM::new_redshift();
+            let core = vm.hosts.id("core").unwrap();
+            let exa = Exa::spawn(&mut vm, core, "x".into(), true, script).unwrap();
+            exa.will_use_ci_this_cycle()
+        };
+        assert!(uses_ci("copy ci x\n"));
+        assert!(uses_ci("modi 5 ci x\n"));
+        assert!(uses_ci("test 1 = ci\n"));
+        assert!(!uses_ci("copy co x\n"));
+        assert!(!uses_ci("addi 1 2 x\n"));
+        assert!(!uses_ci("noop\n"));
     }
 }
@@ -1,178 +1,246 @@
+/// A 10x10 sprite, stored as one bitmask per row with bit n set if the
+/// pixel in column n is lit. Collision checks and rendering work a whole
+/// row at a time.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Sprite {
-    pub pixels: [bool; 100],
-    pub is_empty: bool,
+    rows: [u16; 10],
 }
 
+const ROW_MASK: u16 = (1 << 10) - 1;
+
 impl Sprite {
     pub fn empty() -> Sprite {
-        Sprite {
-            pixels: [false; 100],
-            is_empty: true,
-        }
+        Sprite { rows: [0; 10] }
     }
 
+    // pixels are in row order, top left first
     pub fn from_pixels(pixels: [bool; 100]) -> Sprite {
-        let mut s = Sprite {
-            pixels,
-            is_empty: true,
-        };
-        s.update_is_empty();
+        let mut s = Sprite::empty();
+        for (idx, pixel) in pixels.iter().enumerate() {
+            if *pixel {
+                s.rows[idx / 10] |= 1 << (idx % 10);
+            }
+        }
         s
     }
 
     // shorthand is [number of false pixels, number of true pixels, number of false pixels...]
     // until you've covered all 100 of the pixels
     pub fn from_shorthand(shorthand: Vec<u32>) -> Sprite {
-        let total: u32 = shorthand.iter().sum();
+        Sprite::from_runs(&shorthand)
+    }
+
+    fn from_runs(runs: &[u32]) -> Sprite {
+        let total: u32 = runs.iter().sum();
         if total != 100 {
             panic!("invalid sprite shorthand, must sum to 100");
         }
 
         let mut pixels = [false; 100];
         let (mut idx, mut value) = (0, false);
-        for elem in shorthand.iter() {
+        for elem in runs.iter() {
             for _ in 0..*elem {
                 pixels[idx] = value;
                 idx += 1;
             }
             value = !value;
         }
-        let mut s = Sprite {
-            pixels,
-            is_empty: true,
-        };
-        s.update_is_empty();
-        s
+        Sprite::from_pixels(pixels)
     }
 
     pub fn from_builtin(code: u32) -> Sprite {
         match code {
             0 => Sprite::empty(),
-            1 => Sprite::from_shorthand(vec![
+            1 => Sprite::from_runs(&[
                 24, 1, 8, 1, 1, 1, 6, 1, 3, 1, 5, 1, 3, 1, 4, 7, 3, 1, 5, 1, 3, 1, 5, 1, 12,
             ]),
-            2 => Sprite::from_shorthand(vec![
+            2 => Sprite::from_runs(&[
                 21, 6, 4, 1, 5, 1, 3, 1, 5, 1, 3, 6, 4, 1, 5, 1, 3, 1, 5, 1, 3, 6, 13,
             ]),
-            3 => Sprite::from_shorthand(vec![
-                22, 5, 4, 1, 5, 1, 3, 1, 9, 1, 9, 1, 9, 1, 5, 1, 4, 5, 13,
-            ]),
-            4 => Sprite::from_shorthand(vec![
+            3 => Sprite::from_runs(&[22, 5, 4, 1, 5, 1, 3, 1, 9, 1, 9, 1, 9, 1, 5, 1, 4, 5, 13]),
+            4 => Sprite::from_runs(&[
                 21, 6, 4, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 6, 13,
             ]),
-            5 => Sprite::from_shorthand(vec![21, 7, 3, 1, 9, 1, 9, 6, 4, 1, 9, 1, 9, 7, 12]),
-            6 => Sprite::from_shorthand(vec![21, 7, 3, 1, 9, 1, 9, 6, 4, 1, 9, 1, 9, 1, 18]),
-            7 => Sprite::from_shorthand(vec![
+            5 => Sprite::from_runs(&[21, 7, 3, 1, 9, 1, 9, 6, 4, 1, 9, 1, 9, 7, 12]),
+            6 => Sprite::from_runs(&[21, 7, 3, 1, 9, 1, 9, 6, 4, 1, 9, 1, 9, 1, 18]),
+            7 => Sprite::from_runs(&[
                 22, 5, 4, 1, 5, 1, 3, 1, 9, 1, 3, 3, 3, 1, 5, 1, 3, 1, 5, 1, 4, 5, 13,
             ]),
-            8 => Sprite::from_shorthand(vec![
+            8 => Sprite::from_runs(&[
                 21, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 7, 3, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 12,
             ]),
-            9 => Sprite::from_shorthand(vec![23, 3, 8, 1, 9, 1, 9, 1, 9, 1, 9, 1, 8, 3, 14]),
-            10 => Sprite::from_shorthand(vec![27, 1, 9, 1, 9, 1, 9, 1, 9, 1, 3, 1, 5, 1, 4, 5, 13]),
-            11 => Sprite::from_shorthand(vec![
+            9 => Sprite::from_runs(&[23, 3, 8, 1, 9, 1, 9, 1, 9, 1, 9, 1, 8, 3, 14]),
+            10 => Sprite::from_runs(&[27, 1, 9, 1, 9, 1, 9, 1, 9, 1, 3, 1, 5, 1, 4, 5, 13]),
+            11 => Sprite::from_runs(&[
                 21, 1, 4, 2, 3, 1, 2, 2, 5, 1, 1, 2, 6, 3, 7, 1, 1, 2, 6, 1, 2, 2, 5, 1, 4, 2, 12,
             ]),
-            12 => Sprite::from_shorthand(vec![21, 1, 9, 1, 9, 1, 9, 1, 9, 1, 9, 1, 9, 7, 12]),
-            13 => Sprite::from_shorthand(vec![
+            12 => Sprite::from_runs(&[21, 1, 9, 1, 9, 1, 9, 1, 9, 1, 9, 1, 9, 7, 12]),
+            13 => Sprite::from_runs(&[
                 21, 1, 5, 1, 3, 2, 3, 2, 3, 1, 1, 1, 1, 1, 1, 1, 3, 1, 2, 1, 2, 1, 3, 1, 5, 1, 3,
                 1, 5, 1, 3, 1, 5, 1, 12,
             ]),
-            14 => Sprite::from_shorthand(vec![
+            14 => Sprite::from_runs(&[
                 21, 1, 5, 1, 3, 2, 4, 1, 3, 1, 1, 1, 3, 1, 3, 1, 2, 1, 2, 1, 3, 1, 3, 1, 1, 1, 3,
                 1, 4, 2, 3, 1, 5, 1, 12,
             ]),
-            15 => Sprite::from_shorthand(vec![
+            15 => Sprite::from_runs(&[
                 22, 5, 4, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 4, 5, 13,
             ]),
-            16 => Sprite::from_shorthand(vec![
-                21, 6, 4, 1, 5, 1, 3, 1, 5, 1, 3, 6, 4, 1, 9, 1, 9, 1, 18,
-            ]),
-            17 => Sprite::from_shorthand(vec![
+            16 => Sprite::from_runs(&[21, 6, 4, 1, 5, 1, 3, 1, 5, 1, 3, 6, 4, 1, 9, 1, 9, 1, 18]),
+            17 => Sprite::from_runs(&[
                 22, 5, 4, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 1, 3, 1, 1, 1, 3, 1, 4, 2, 4, 6, 12,
             ]),
-            18 => Sprite::from_shorthand(vec![
+            18 => Sprite::from_runs(&[
                 21, 6, 4, 1, 5, 1, 3, 1, 5, 1, 3, 6, 4, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 12,
             ]),
-            19 => Sprite::from_shorthand(vec![
-                22, 5, 4, 1, 5, 1, 3, 1, 10, 5, 10, 1, 3, 1, 5, 1, 4, 5, 13,
-            ]),
-            20 => Sprite::from_shorthand(vec![21, 7, 6, 1, 9, 1, 9, 1, 9, 1, 9, 1, 9, 1, 15]),
-            21 => Sprite::from_shorthand(vec![
+            19 => Sprite::from_runs(&[22, 5, 4, 1, 5, 1, 3, 1, 10, 5, 10, 1, 3, 1, 5, 1, 4, 5, 13]),
+            20 => Sprite::from_runs(&[21, 7, 6, 1, 9, 1, 9, 1, 9, 1, 9, 1, 9, 1, 15]),
+            21 => Sprite::from_runs(&[
                 21, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 4, 5, 13,
             ]),
-            22 => Sprite::from_shorthand(vec![
+            22 => Sprite::from_runs(&[
                 21, 1, 5, 1, 3, 1, 5, 1, 4, 1, 3, 1, 5, 1, 3, 1, 6, 1, 1, 1, 7, 1, 1, 1, 8, 1, 15,
             ]),
-            23 => Sprite::from_shorthand(vec![
+            23 => Sprite::from_runs(&[
                 21, 1, 5, 1, 3, 1, 5, 1, 3, 1, 5, 1, 4, 1, 1, 1, 1, 1, 5, 1, 1, 1, 1, 1, 6, 1, 1,
                 1, 7, 1, 1, 1, 14,
             ]),
-            24 => Sprite::from_shorthand(vec![
+            24 => Sprite::from_runs(&[
                 21, 1, 5, 1, 4, 1, 3, 1, 6, 1, 1, 1, 8, 1, 8, 1, 1, 1, 6, 1, 3, 1, 4, 1, 5, 1, 12,
             ]),
-            25 => Sprite::from_shorthand(vec![
+            25 => Sprite::from_runs(&[
                 21, 1, 5, 1, 4, 1, 3, 1, 6, 1, 1, 1, 8, 1, 9, 1, 9, 1, 9, 1, 15,
             ]),
-            26 => Sprite::from_shorthand(vec![21, 7, 8, 1, 8, 1, 8, 1, 8, 1, 8, 1, 8, 7, 12]),
-            27 => Sprite::from_shorthand(vec![
+            26 => Sprite::from_runs(&[21, 7, 8, 1, 8, 1, 8, 1, 8, 1, 8, 1, 8, 7, 12]),
+            27 => Sprite::from_runs(&[
                 22, 5, 4, 1, 4, 2, 3, 1, 3, 1, 1, 1, 3, 1, 2, 1, 2, 1, 3, 1, 1, 1, 3, 1, 3, 2, 4,
                 1, 4, 5, 13,
             ]),
-            28 => Sprite::from_shorthand(vec![23, 2, 7, 1, 1, 1, 9, 1, 9, 1, 9, 1, 9, 1, 9, 1, 15]),
-            29 => Sprite::from_shorthand(vec![22, 5, 4, 1, 5, 1, 9, 1, 4, 5, 4, 1, 9, 1, 9, 7, 12]),
-            30 => Sprite::from_shorthand(vec![
-                22, 5, 4, 1, 5, 1, 9, 1, 6, 3, 10, 1, 3, 1, 5, 1, 4, 5, 13,
-            ]),
-            31 => Sprite::from_shorthand(vec![
+            28 => Sprite::from_runs(&[23, 2, 7, 1, 1, 1, 9, 1, 9, 1, 9, 1, 9, 1, 9, 1, 15]),
+            29 => Sprite::from_runs(&[22, 5, 4, 1, 5, 1, 9, 1, 4, 5, 4, 1, 9, 1, 9, 7, 12]),
+            30 => Sprite::from_runs(&[22, 5, 4, 1, 5, 1, 9, 1, 6, 3, 10, 1, 3, 1, 5, 1, 4, 5, 13]),
+            31 => Sprite::from_runs(&[
                 26, 1, 8, 2, 7, 1, 1, 1, 6, 1, 2, 1, 5, 1, 3, 1, 4, 7, 8, 1, 13,
             ]),
-            32 => {
-                Sprite::from_shorthand(vec![21, 7, 3, 1, 9, 1, 9, 6, 10, 1, 3, 1, 5, 1, 4, 5, 13])
-            }
-            33 => Sprite::from_shorthand(vec![
+            32 => Sprite::from_runs(&[21, 7, 3, 1, 9, 1, 9, 6, 10, 1, 3, 1, 5, 1, 4, 5, 13]),
+            33 => Sprite::from_runs(&[
                 22, 5, 4, 1, 5, 1, 3, 1, 9, 6, 4, 1, 5, 1, 3, 1, 5, 1, 4, 5, 13,
             ]),
-            34 => Sprite::from_shorthand(vec![21, 7, 9, 1, 8, 1, 8, 1, 8, 1, 8, 1, 8, 1, 17]),
-            35 => Sprite::from_shorthand(vec![
+            34 => Sprite::from_runs(&[21, 7, 9, 1, 8, 1, 8, 1, 8, 1, 8, 1, 8, 1, 17]),
+            35 => Sprite::from_runs(&[
                 22, 5, 4, 1, 5, 1, 3, 1, 5, 1, 4, 5, 4, 1, 5, 1, 3, 1, 5, 1, 4, 5, 13,
             ]),
-            36 => Sprite::from_shorthand(vec![
+            36 => Sprite::from_runs(&[
                 22, 5, 4, 1, 5, 1, 3, 1, 5, 1, 4, 6, 9, 1, 3, 1, 5, 1, 4, 5, 13,
             ]),
-            37 => Sprite::from_shorthand(vec![83, 1, 16]),
-            38 => Sprite::from_shorthand(vec![22, 5, 4, 1, 5, 1, 9, 1, 6, 3, 6, 1, 19, 1, 16]),
-            39 => Sprite::from_shorthand(vec![23, 1, 9, 1, 9, 1, 9, 1, 9, 1, 19, 1, 16]),
+            37 => Sprite::from_runs(&[83, 1, 16]),
+            38 => Sprite::from_runs(&[22, 5, 4, 1, 5, 1, 9, 1, 6, 3, 6, 1, 19, 1, 16]),
+            39 => Sprite::from_runs(&[23, 1, 9, 1, 9, 1, 9, 1, 9, 1, 19, 1, 16]),
             _ => Sprite::empty(),
         }
     }
 
     pub fn enable(&mut self, x: u32, y: u32) {
-        let idx = (x + (y * 10)) as usize;
-        self.pixels[idx] = true;
-        self.is_empty = false;
+        self.rows[y as usize] |= 1 << x;
     }
 
     pub fn disable(&mut self, x: u32, y: u32) {
-        let idx = (x + (y * 10)) as usize;
-        self.pixels[idx] = false;
-        self.update_is_empty();
+        self.rows[y as usize] &= !(1 << x);
     }
 
     pub fn toggle(&mut self, x: u32, y: u32) {
-        let idx = (x + (y * 10)) as usize;
-        self.pixels[idx] = !self.pixels[idx];
-        self.update_is_empty();
+        self.rows[y as usize] ^= 1 << x;
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        self.rows[y as usize] & (1 << x) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.iter().all(|r| *r == 0)
     }
 
-    pub fn update_is_empty(&mut self) {
-        for p in self.pixels.iter() {
-            if *p {
-                self.is_empty = false;
-                return;
+    /// Row bitmasks, top row first. Bit n is column n.
+    pub fn rows(&self) -> &[u16; 10] {
+        &self.rows
+    }
+
+    /// Whether any lit pixel is shared with other, when other is drawn
+    /// (dx, dy) pixels right of and below this sprite.
+    pub fn overlaps(&self, other: &Sprite, dx: i32, dy: i32) -> bool {
+        if dx.abs() >= 10 || dy.abs() >= 10 {
+            return false;
+        }
+        (dy.max(0) as usize..(10 + dy).min(10) as usize).any(|row| {
+            let theirs = other.rows[(row as i32 - dy) as usize];
+            let shifted = if dx >= 0 { theirs << dx } else { theirs >> -dx };
+            self.rows[row] & shifted & ROW_MASK != 0
+        })
+    }
+
+    /// Call plot with the (x, y) of every lit pixel that lands on the
+    /// 120x100 screen, with the sprite's top left corner at (x, y). Rows
+    /// are clipped as a whole before any pixel is looked at.
+    pub fn draw<F: FnMut(usize, usize)>(&self, x: i32, y: i32, mut plot: F) {
+        if x <= -10 || x >= 120 || y <= -10 || y >= 100 {
+            return;
+        }
+        // Columns that land on screen
+        let visible = if x < 0 {
+            ROW_MASK & (ROW_MASK << -x)
+        } else {
+            ROW_MASK >> (x - 110).max(0)
+        };
+        for (row, bits) in self.rows.iter().enumerate() {
+            let this_y = y + row as i32;
+            if !(0..100).contains(&this_y) {
+                continue;
+            }
+            let mut bits = bits & visible;
+            while bits != 0 {
+                let col = bits.trailing_zeros() as i32;
+                plot((x + col) as usize, this_y as usize);
+                bits &= bits - 1;
             }
         }
-        self.is_empty = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlaps() {
+        let mut left = Sprite::empty();
+        left.enable(9, 0);
+        let mut right = Sprite::empty();
+        right.enable(0, 9);
+
+        assert!(left.overlaps(&right, 9, -9));
+        assert!(right.overlaps(&left, -9, 9));
+        assert!(!left.overlaps(&right, 9, -8));
+        assert!(!left.overlaps(&right, 10, -9));
+
+        let full = Sprite::from_shorthand(vec![0, 100]);
+        assert!(full.overlaps(&full, -9, 9));
+        assert!(!full.overlaps(&full, 0, 10));
+        assert!(!full.overlaps(&Sprite::empty(), 0, 0));
+    }
+
+    #[test]
+    fn test_draw_clips() {
+        let full = Sprite::from_shorthand(vec![0, 100]);
+        let mut drawn = vec![];
+        full.draw(-8, 98, |x, y| drawn.push((x, y)));
+        assert_eq!(drawn, vec![(0, 98), (1, 98), (0, 99), (1, 99)]);
+
+        drawn.clear();
+        full.draw(118, -9, |x, y| drawn.push((x, y)));
+        assert_eq!(drawn, vec![(118, 0), (119, 0)]);
+
+        drawn.clear();
+        full.draw(120, 0, |x, y| drawn.push((x, y)));
+        assert!(drawn.is_empty());
     }
 }
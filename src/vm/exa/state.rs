@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use super::super::instruction::Span;
+use super::super::program::Program;
+use super::super::state::{
+    read_error, read_file, read_instruction, read_register, write_error, write_file,
+    write_instruction, write_register, StateReader, StateWriter,
+};
+use super::super::VM;
+use super::cycle::CycleResult;
+use super::sprite::Sprite;
+use super::{Exa, Mode, Registers};
+
+impl Registers {
+    fn save_state(&self, w: &mut StateWriter) {
+        for r in [
+            &self.x, &self.t, &self.gx, &self.gy, &self.gz, &self.gp, &self.ci, &self.co,
+        ]
+        .iter()
+        {
+            write_register(w, r);
+        }
+    }
+
+    fn load_state(r: &mut StateReader) -> Result<Registers, Box<dyn Error>> {
+        Ok(Registers {
+            x: read_register(r)?,
+            t: read_register(r)?,
+            gx: read_register(r)?,
+            gy: read_register(r)?,
+            gz: read_register(r)?,
+            gp: read_register(r)?,
+            ci: read_register(r)?,
+            co: read_register(r)?,
+        })
+    }
+}
+
+impl Exa {
+    pub fn save_state(&self, vm: &VM, w: &mut StateWriter) {
+        w.write_string(&self.base_name);
+        w.write_uint(self.spawn_id);
+        w.write_string(&self.name);
+        w.write_uint(self.spawn_counter.load(Ordering::Relaxed));
+
+        self.registers.save_state(w);
+
+        w.write_len(self.pc);
+        let program = &self.program;
+        w.write_len(program.source.len());
+        for inst in program.source.iter() {
+            write_instruction(w, inst);
+        }
+        w.write_len(program.spans.len());
+        for span in program.spans.iter() {
+            w.write_len(span.line);
+            w.write_len(span.column);
+            match span.rep {
+                None => w.write_bool(false),
+                Some(rep) => {
+                    w.write_bool(true);
+                    w.write_len(rep);
+                }
+            }
+        }
+        let mut labels: Vec<_> = program.labels.iter().collect();
+        labels.sort();
+        w.write_len(labels.len());
+        for (label, position) in labels {
+            w.write_string(label);
+            w.write_len(*position);
+        }
+
+        w.write_bool(self.mode == Mode::Local);
+        w.write_string(&vm.hosts[self.host].name);
+        write_error(w, &self.error);
+
+        w.write_int(self.file_pointer as i32);
+        match &self.file {
+            None => w.write_bool(false),
+            Some(f) => {
+                w.write_bool(true);
+                write_file(w, f);
+            }
+        }
+
+        for y in 0..10 {
+            for x in 0..10 {
+                w.write_bool(self.sprite.pixel(x, y));
+            }
+        }
+
+        w.write_bool(self.ran_test_mrd_this_cycle);
+        w.write_bool(self.waiting);
+    }
+
+    /// Restore an Exa written by save_state. Hosts are looked up on the
+    /// (partially restored) VM, and spawn counters are shared between
+    /// every EXA with the same base name, same as with REPL.
+    /// Programs are shared too, as long as they're the same.
+    pub fn load_state(
+        r: &mut StateReader,
+        vm: &VM,
+        spawn_counters: &mut HashMap<String, Arc<AtomicU32>>,
+        programs: &mut HashMap<String, Arc<Program>>,
+    ) -> Result<Exa, Box<dyn Error>> {
+        let base_name = r.read_string()?;
+        let spawn_id = r.read_uint()?;
+        let name = r.read_string()?;
+        let spawn_count = r.read_uint()?;
+        let spawn_counter = spawn_counters
+            .entry(base_name.clone())
+            .or_insert_with(|| Arc::new(AtomicU32::new(spawn_count)))
+            .clone();
+
+        let registers = Registers::load_state(r)?;
+
+        let pc = r.read_len()?;
+        let mut instructions = vec![];
+        for _ in 0..r.read_len()? {
+            instructions.push(read_instruction(r)?);
+        }
+        let mut spans = vec![];
+        for _ in 0..r.read_len()? {
+            spans.push(Span {
+                line: r.read_len()?,
+                column: r.read_len()?,
+                rep: if r.read_bool()? {
+                    Some(r.read_len()?)
+                } else {
+                    None
+                },
+            });
+        }
+        let mut labels = HashMap::new();
+        for _ in 0..r.read_len()? {
+            let label = r.read_string()?;
+            labels.insert(label, r.read_len()?);
+        }
+        if pc > instructions.len() || labels.values().any(|p| *p > instructions.len()) {
+            return Err("invalid exa pc in save state".into());
+        }
+        let program = Program::decode(instructions, spans, labels);
+        let program = match programs.get(&base_name) {
+            Some(shared) if **shared == program => shared.clone(),
+            _ => {
+                let program = Arc::new(program);
+                programs.insert(base_name.clone(), program.clone());
+                program
+            }
+        };
+
+        let mode = if r.read_bool()? {
+            Mode::Local
+        } else {
+            Mode::Global
+        };
+        let host_name = r.read_string()?;
+        let host = match vm.hosts.id(&host_name) {
+            Some(id) => id,
+            None => return Err("exa in unknown host in save state".into()),
+        };
+        let error = read_error(r)?;
+
+        let file_pointer = r.read_int()? as isize;
+        let file = if r.read_bool()? {
+            Some(read_file(r)?)
+        } else {
+            None
+        };
+
+        let mut pixels = [false; 100];
+        for pixel in pixels.iter_mut() {
+            *pixel = r.read_bool()?;
+        }
+
+        let ran_test_mrd_this_cycle = r.read_bool()?;
+        let waiting = r.read_bool()?;
+
+        Ok(Exa {
+            base_name,
+            spawn_id,
+            name: name.into(),
+            registers,
+            result: CycleResult::new(),
+            spawn_counter,
+            pc,
+            program,
+            bound_host: None,
+            hardware: vec![],
+            mode,
+            host,
+            error,
+            file_pointer,
+            file,
+            sprite: Sprite::from_pixels(pixels),
+            ran_test_mrd_this_cycle,
+            waiting,
+        })
+    }
+}
@@ -1,13 +1,17 @@
 use std::fmt;
 
-#[derive(Debug, PartialEq, Eq)]
+use itertools::Itertools;
+
+use super::value::Value;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct File {
     pub id: i32,
-    pub contents: Vec<i32>,
+    pub contents: Vec<Value>,
 }
 
 impl File {
-    pub fn new(id: i32, contents: Vec<i32>) -> File {
+    pub fn new(id: i32, contents: Vec<Value>) -> File {
         File { id, contents }
     }
 }
@@ -16,10 +20,10 @@ impl fmt::Display for File {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "File {} (len: {}) {:?}",
+            "File {} (len: {}) [{}]",
             self.id,
             self.contents.len(),
-            self.contents,
+            self.contents.iter().join(", "),
         )
     }
 }
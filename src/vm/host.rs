@@ -0,0 +1,275 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+use super::bus::MessageBus;
+use super::error::ExaError;
+use super::file::File;
+use super::register::HardwareRegister;
+
+/// Where a host lives in the VM's Hosts. Ids are only meaningful for the
+/// VM that handed them out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HostId(usize);
+
+#[derive(Debug)]
+pub struct Host {
+    pub name: String,
+
+    // capacity is total squares that can be occupied by EXAs or files.
+    // Do NOT include squares that are occupied by registers,
+    // level art, or anything else.
+    pub capacity: usize,
+
+    // occupied is how much of the capacity is currently filled
+    pub occupied: usize,
+
+    // key is the number of the link that needs to be passed to the LINK op
+    pub links: HashMap<i32, HostLink>,
+
+    pub registers: HostRegisters,
+
+    pub bus: MessageBus,
+
+    pub files: Vec<File>,
+}
+
+impl PartialEq for Host {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Host {}
+
+impl Host {
+    pub fn new(name: String, capacity: usize) -> Host {
+        Host {
+            name,
+            capacity,
+            occupied: 0,
+            links: HashMap::new(),
+            registers: HostRegisters::default(),
+            bus: MessageBus::new(),
+            files: vec![],
+        }
+    }
+
+    /// Increments occupied by 1, if there is remaining capacity. Successful
+    /// calls mean you need to free_slot later when you leave the Host.
+    pub fn reserve_slot(&mut self) -> Result<(), ExaError> {
+        if self.capacity <= self.occupied {
+            return Err(ExaError::HostFull);
+        }
+        self.occupied += 1;
+        Ok(())
+    }
+
+    /// Decrements occupied by 1. Call this when you move out of a Host.
+    /// Calling this before reserving a slot from the same object would...be bad, don't do that.
+    pub fn free_slot(&mut self) {
+        self.occupied -= 1;
+    }
+
+    pub fn add_register<R: HardwareRegister + 'static>(&mut self, name: String, register: R) {
+        self.reserve_slot()
+            .expect("cannot add register, host is full");
+        self.registers
+            .insert(name.to_ascii_lowercase(), Box::new(register));
+    }
+}
+
+impl Ord for Host {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+impl PartialOrd for Host {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Host {} (free capacity: {} / {})",
+            self.name,
+            (self.capacity - self.occupied),
+            self.capacity
+        )
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct HostLink {
+    pub to_host: HostId,
+    // links can only support one traversal per cycle
+    pub traversed_this_cycle: bool,
+}
+
+/// A host's hardware registers, by lowercase name. Registers keep the
+/// slot they were added in, so EXAs can remember where to find them.
+#[derive(Debug, Default)]
+pub struct HostRegisters {
+    registers: Vec<(String, Box<dyn HardwareRegister>)>,
+}
+
+impl HostRegisters {
+    /// Add a register, replacing any with the same name.
+    pub fn insert(&mut self, name: String, register: Box<dyn HardwareRegister>) {
+        match self.position(&name) {
+            Some(slot) => self.registers[slot].1 = register,
+            None => self.registers.push((name, register)),
+        }
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.registers.iter().position(|(n, _)| n == name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn HardwareRegister> {
+        let slot = self.position(name)?;
+        Some(self.registers[slot].1.as_ref())
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut dyn HardwareRegister> {
+        let slot = self.position(name)?;
+        Some(self.slot_mut(slot))
+    }
+
+    /// The register at a slot returned by position.
+    pub fn slot_mut(&mut self, slot: usize) -> &mut dyn HardwareRegister {
+        self.registers[slot].1.as_mut()
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.position(name).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.registers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+    }
+
+    /// Take every register out, in the order they were added.
+    pub(crate) fn drain(
+        &mut self,
+    ) -> impl Iterator<Item = (String, Box<dyn HardwareRegister>)> + '_ {
+        self.registers.drain(..)
+    }
+
+    /// Every register with its name, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &dyn HardwareRegister)> {
+        self.registers.iter().map(|(n, r)| (n, r.as_ref()))
+    }
+}
+
+impl Index<&str> for HostRegisters {
+    type Output = dyn HardwareRegister;
+
+    fn index(&self, name: &str) -> &Self::Output {
+        let slot = self.position(name).expect("unknown register");
+        self.registers[slot].1.as_ref()
+    }
+}
+
+/// Every host in a VM. Hosts can be looked up by name or by the HostId
+/// they were given when added, and are never removed.
+#[derive(Debug, Default)]
+pub struct Hosts {
+    hosts: Vec<Host>,
+    ids: HashMap<String, HostId>,
+}
+
+impl Hosts {
+    /// Add a host, replacing any with the same name.
+    pub fn insert(&mut self, host: Host) -> HostId {
+        match self.ids.get(&host.name) {
+            Some(id) => {
+                self.hosts[id.0] = host;
+                *id
+            }
+            None => {
+                let id = HostId(self.hosts.len());
+                self.ids.insert(host.name.clone(), id);
+                self.hosts.push(host);
+                id
+            }
+        }
+    }
+
+    pub fn id(&self, name: &str) -> Option<HostId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Host> {
+        self.id(name).map(|id| &self.hosts[id.0])
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Host> {
+        let id = self.id(name)?;
+        Some(&mut self.hosts[id.0])
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.ids.contains_key(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    /// Every host, in the order they were added.
+    pub fn values(&self) -> slice::Iter<'_, Host> {
+        self.hosts.iter()
+    }
+
+    pub fn values_mut(&mut self) -> slice::IterMut<'_, Host> {
+        self.hosts.iter_mut()
+    }
+
+    /// Every host along with its id, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (HostId, &Host)> {
+        self.hosts.iter().enumerate().map(|(i, h)| (HostId(i), h))
+    }
+}
+
+impl Index<HostId> for Hosts {
+    type Output = Host;
+
+    fn index(&self, id: HostId) -> &Host {
+        &self.hosts[id.0]
+    }
+}
+
+impl IndexMut<HostId> for Hosts {
+    fn index_mut(&mut self, id: HostId) -> &mut Host {
+        &mut self.hosts[id.0]
+    }
+}
+
+impl Index<&str> for Hosts {
+    type Output = Host;
+
+    fn index(&self, name: &str) -> &Host {
+        self.get(name).expect("unknown host")
+    }
+}
+
+impl IndexMut<&str> for Hosts {
+    fn index_mut(&mut self, name: &str) -> &mut Host {
+        self.get_mut(name).expect("unknown host")
+    }
+}
@@ -1,4 +1,12 @@
-#[derive(Clone, Debug, PartialEq, Eq)]
+use std::fmt;
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use super::value::Value;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Instruction {
     Copy(Target, Target),
     Addi(Target, Target, Target),
@@ -31,20 +39,116 @@ pub enum Instruction {
     Noop,
     Rand(Target, Target, Target),
     Wait,
-    Data(Vec<i32>),
+    Data(Vec<Value>),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Target {
     Literal(i32),
+    Keyword(String),
     Register(String),
 }
 
 pub type Label = String;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Comparator {
     Equal,
     GreaterThan,
     LessThan,
 }
+
+/// Instructions print as EXA source in the game's uppercase style, which
+/// parses back to the same instruction.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Copy(src, dest) => write!(f, "COPY {} {}", src, dest),
+            Instruction::Addi(l, r, d) => write!(f, "ADDI {} {} {}", l, r, d),
+            Instruction::Subi(l, r, d) => write!(f, "SUBI {} {} {}", l, r, d),
+            Instruction::Muli(l, r, d) => write!(f, "MULI {} {} {}", l, r, d),
+            Instruction::Divi(l, r, d) => write!(f, "DIVI {} {} {}", l, r, d),
+            Instruction::Modi(l, r, d) => write!(f, "MODI {} {} {}", l, r, d),
+            Instruction::Swiz(i, m, d) => write!(f, "SWIZ {} {} {}", i, m, d),
+            Instruction::Mark(label) => write!(f, "MARK {}", label.to_ascii_uppercase()),
+            Instruction::Jump(label) => write!(f, "JUMP {}", label.to_ascii_uppercase()),
+            Instruction::Tjmp(label) => write!(f, "TJMP {}", label.to_ascii_uppercase()),
+            Instruction::Fjmp(label) => write!(f, "FJMP {}", label.to_ascii_uppercase()),
+            Instruction::Test(l, comp, r) => write!(f, "TEST {} {} {}", l, comp, r),
+            Instruction::Repl(label) => write!(f, "REPL {}", label.to_ascii_uppercase()),
+            Instruction::Halt => write!(f, "HALT"),
+            Instruction::Kill => write!(f, "KILL"),
+            Instruction::Link(dest) => write!(f, "LINK {}", dest),
+            Instruction::Host(dest) => write!(f, "HOST {}", dest),
+            Instruction::Mode => write!(f, "MODE"),
+            Instruction::VoidM => write!(f, "VOID M"),
+            Instruction::TestMrd => write!(f, "TEST MRD"),
+            Instruction::Make => write!(f, "MAKE"),
+            Instruction::Grab(file) => write!(f, "GRAB {}", file),
+            Instruction::File(dest) => write!(f, "FILE {}", dest),
+            Instruction::Seek(amount) => write!(f, "SEEK {}", amount),
+            Instruction::VoidF => write!(f, "VOID F"),
+            Instruction::Drop => write!(f, "DROP"),
+            Instruction::Wipe => write!(f, "WIPE"),
+            Instruction::TestEof => write!(f, "TEST EOF"),
+            Instruction::Noop => write!(f, "NOOP"),
+            Instruction::Rand(lo, hi, d) => write!(f, "RAND {} {} {}", lo, hi, d),
+            Instruction::Wait => write!(f, "WAIT"),
+            Instruction::Data(values) => write!(f, "DATA {}", values.iter().join(" ")),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Literal(n) => write!(f, "{}", n),
+            Target::Keyword(k) => write!(f, "'{}'", k.to_ascii_uppercase()),
+            Target::Register(name) => write!(f, "{}", name.to_ascii_uppercase()),
+        }
+    }
+}
+
+impl fmt::Display for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Comparator::Equal => write!(f, "="),
+            Comparator::GreaterThan => write!(f, ">"),
+            Comparator::LessThan => write!(f, "<"),
+        }
+    }
+}
+
+/// Where an instruction came from in its EXA's source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct Span {
+    /// 1-based line.
+    pub line: usize,
+    /// 1-based column of the instruction's first character.
+    pub column: usize,
+    /// For instructions in an @REP block, which expansion of the block
+    /// they came from, counting from 0.
+    pub rep: Option<usize>,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Span {
+        Span {
+            line,
+            column,
+            rep: None,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)?;
+        if let Some(rep) = self.rep {
+            write!(f, " (@REP expansion {})", rep)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! How a KILL picks its victim. The retail game's rules have never been
+//! fully reverse engineered, so the VM takes a KillPolicy and carts that
+//! care can pick the one that behaves the way they expect.
+//!
+//! Policies are not part of save states.
+
+use std::fmt::Debug;
+
+use super::exa::Exa;
+
+pub trait KillPolicy: Debug + Send {
+    /// Pick who killer kills, as an index into candidates. Candidates are
+    /// indexes into exas of every other EXA in killer's host in the order
+    /// they were spawned, and there's always at least one. Some of them
+    /// may already have been killed by an earlier KILL this cycle. Any
+    /// randomness should come from rng so runs can be replayed from the
+    /// VM's seed. This runs every time an EXA kills, so it shouldn't
+    /// allocate.
+    fn choose(
+        &mut self,
+        killer: &Exa,
+        candidates: &[usize],
+        exas: &[Exa],
+        rng: &fastrand::Rng,
+    ) -> usize;
+}
+
+/// Pick a random candidate out of the ones in_group accepts, as an index
+/// into candidates, or None if it accepts none of them.
+fn choose_in_group(
+    candidates: &[usize],
+    exas: &[Exa],
+    rng: &fastrand::Rng,
+    in_group: impl Fn(&Exa) -> bool,
+) -> Option<usize> {
+    let members = candidates.iter().filter(|i| in_group(&exas[**i])).count();
+    if members == 0 {
+        return None;
+    }
+    let pick = rng.usize(..members);
+    (0..candidates.len())
+        .filter(|i| in_group(&exas[candidates[*i]]))
+        .nth(pick)
+}
+
+/// The VM's default. Targets are prioritized based on:
+/// - whether they are also performing a KILL this turn
+/// - whether they are in our EXA chain, and newer than us
+/// - whether they are in our EXA chain, and older than us
+/// - everyone else
+///
+/// We take the first group that has any members and pick a random member
+/// from it.
+#[derive(Clone, Debug, Default)]
+pub struct Prioritized;
+
+impl KillPolicy for Prioritized {
+    fn choose(
+        &mut self,
+        killer: &Exa,
+        candidates: &[usize],
+        exas: &[Exa],
+        rng: &fastrand::Rng,
+    ) -> usize {
+        let groups: [&dyn Fn(&Exa) -> bool; 4] = [
+            &|e| e.will_kill_this_cycle(),
+            &|e| e.descendant_of(killer),
+            &|e| e.ancestor_of(killer),
+            &|_| true,
+        ];
+        groups
+            .iter()
+            .find_map(|in_group| choose_in_group(candidates, exas, rng, in_group))
+            .expect("there is always a candidate")
+    }
+}
+
+/// Always kill whichever candidate was spawned first. Doesn't touch the
+/// VM's randomness at all, so kills never vary between runs.
+#[derive(Clone, Debug, Default)]
+pub struct OldestFirst;
+
+impl KillPolicy for OldestFirst {
+    fn choose(&mut self, _: &Exa, _: &[usize], _: &[Exa], _: &fastrand::Rng) -> usize {
+        0
+    }
+}
+
+/// A guess at the retail game's rules, which have never been reverse
+/// engineered. It keeps the one part of Prioritized that explains two
+/// killers in a host taking each other out: EXAs also running KILL this
+/// cycle are targeted first. It assumes, without having checked, that the
+/// game doesn't care about REPL lineage, so everyone else is equally
+/// likely. Treat it as a hypothesis until it's compared against the game.
+#[derive(Clone, Debug, Default)]
+pub struct Retail;
+
+impl KillPolicy for Retail {
+    fn choose(
+        &mut self,
+        _: &Exa,
+        candidates: &[usize],
+        exas: &[Exa],
+        rng: &fastrand::Rng,
+    ) -> usize {
+        choose_in_group(candidates, exas, rng, Exa::will_kill_this_cycle)
+            .unwrap_or_else(|| rng.usize(..candidates.len()))
+    }
+}
@@ -1,212 +1,247 @@
 extern crate simple_error;
 
-use std::cell::RefCell;
-use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::error::Error;
+use std::collections::VecDeque;
 use std::fmt;
-use std::rc::Rc;
-use std::sync::atomic::AtomicI32;
 
 use itertools::Itertools;
+use serde::Deserialize;
 
 use self::exa::Exa;
+use audio::Noise;
 use bus::MessageBus;
-use error::ExaError;
-use file::File;
-use redshift::RedshiftEnvironment;
-use register::Register;
+use debug::Debugger;
+use kill::{KillPolicy, Prioritized};
+use redshift::{AnaglyphPixel, RedshiftEnvironment};
+use rewind::Rewind;
+use scheduler::{Scheduler, Shuffle};
+use terminated::History;
+use trace::{Bus, Event, TraceEvent, TraceSink};
 
 pub mod audio;
 pub mod bus;
 pub mod cycle;
+pub mod debug;
 pub mod error;
 pub mod exa;
 pub mod file;
+pub mod host;
 pub mod instruction;
+pub mod kill;
+pub mod program;
 pub mod redshift;
 pub mod register;
+pub mod rewind;
+pub mod scheduler;
+pub mod state;
+pub mod terminated;
+pub mod trace;
+pub mod value;
 
-pub type Shared<T> = Rc<RefCell<T>>;
+pub use host::{Host, HostId, HostLink, HostRegisters, Hosts};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permissions {
+    Denied,
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
 
 #[derive(Debug)]
-pub struct Host<'a> {
-    pub name: String,
+pub struct VM {
+    pub cycle: u32,
 
-    // capacity is total squares that can be occupied by EXAs or files.
-    // Do NOT include squares that are occupied by registers,
-    // level art, or anything else.
-    pub capacity: usize,
+    /// Successful LINKs since the VM started, for activity scoring.
+    pub links_traversed: u32,
 
-    // occupied is how much of the capacity is currently filled
-    pub occupied: usize,
+    /// KILLs executed since the VM started, for activity scoring.
+    pub kills: u32,
 
-    // key is the number of the link that needs to be passed to the LINK op
-    pub links: HashMap<i32, HostLink<'a>>,
+    pub hosts: Hosts,
 
-    pub registers: HashMap<String, Shared<Register>>,
+    pub exas: Vec<Exa>,
 
-    pub bus: MessageBus,
+    // Indexes into exas of the EXAs still to run this cycle, in order.
+    // EXAs are only removed between cycles, so these stay put.
+    run_queue: VecDeque<usize>,
 
-    pub files: Vec<File>,
-}
+    // EXAs REPLed by the EXA that's running, added to exas once it's done
+    spawned: Vec<Exa>,
 
-impl PartialEq for Host<'_> {
-    fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
-    }
-}
+    // (x, y, index into exas) of every EXA that can collide this cycle,
+    // kept around so collision detection doesn't allocate every cycle
+    colliders: Vec<(i32, i32, usize)>,
 
-impl Eq for Host<'_> {}
+    // Everyone the KILL being run could target, as indexes into exas,
+    // kept for the same reason
+    kill_candidates: Vec<usize>,
 
-impl<'a> Host<'_> {
-    pub fn new(name: String, capacity: usize) -> Host<'a> {
-        Host {
-            name,
-            capacity,
-            occupied: 0,
-            links: HashMap::new(),
-            registers: HashMap::new(),
-            bus: MessageBus::new(),
-            files: vec![],
-        }
-    }
-    pub fn new_shared(name: String, capacity: usize) -> Shared<Host<'a>> {
-        Rc::new(RefCell::new(Host::new(name, capacity)))
-    }
+    // Set between begin_cycle and end_cycle, which only matters when a
+    // debugger is stepping through a cycle one EXA at a time
+    mid_cycle: bool,
 
-    /// Increments occupied by 1, if there is remaining capacity. Successful
-    /// calls mean you need to free_slot later when you leave the Host.
-    pub fn reserve_slot(&mut self) -> Result<(), Box<dyn Error>> {
-        if self.capacity <= self.occupied {
-            return Err(ExaError::Blocking("host has no remaining capacity").into());
-        }
-        self.occupied += 1;
-        Ok(())
-    }
+    debugger: Debugger,
 
-    /// Decrements occupied by 1. Call this when you move out of a Host.__rust_force_expr!
-    /// Calling this before reserving a slot from the same object would...be bad, don't do that.
-    pub fn free_slot(&mut self) {
-        self.occupied -= 1;
-    }
+    trace: Option<Box<dyn TraceSink>>,
 
-    pub fn add_register(&mut self, name: String, register: Shared<Register>) {
-        self.reserve_slot()
-            .expect("cannot add register, host is full");
-        self.registers.insert(name.to_ascii_lowercase(), register);
-    }
-}
-
-impl Ord for Host<'_> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.name.cmp(&other.name)
-    }
-}
+    rewind: Option<Rewind>,
 
-impl PartialOrd for Host<'_> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.name.cmp(&other.name))
-    }
-}
+    terminated: History,
 
-impl fmt::Display for Host<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Host {} (free capacity: {} / {})",
-            self.name,
-            (self.capacity - self.occupied),
-            self.capacity
-        )
-    }
-}
-
-#[derive(PartialEq, Eq, Debug)]
-pub struct HostLink<'a> {
-    pub to_host_name: String,
-    pub to_host: Shared<Host<'a>>,
-    // links can only support one traversal per cycle
-    pub traversed_this_cycle: bool,
-}
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub enum Permissions {
-    Denied,
-    ReadOnly,
-    WriteOnly,
-    ReadWrite,
-}
+    pub bus: MessageBus,
 
-#[derive(Debug)]
-pub struct VM<'a> {
-    pub cycle: u32,
+    /// Id of the next file made by an EXA.
+    pub file_counter: i32,
 
-    pub hosts: HashMap<String, Shared<Host<'a>>>,
+    framebuffer: [bool; 120 * 100],
 
-    pub exas: Vec<Shared<Exa<'a>>>,
+    anaglyph_framebuffer: [AnaglyphPixel; 120 * 100],
 
-    exa_stack: Vec<Shared<Exa<'a>>>,
+    audio_buffer: [i16; (44100 / 60) * 2],
 
-    pub bus: Shared<MessageBus>,
+    pub redshift: Option<RedshiftEnvironment>,
 
-    pub file_counter: Rc<AtomicI32>,
+    pub scheduler: Box<dyn Scheduler>,
 
-    framebuffer: [bool; 120 * 100],
+    pub kill_policy: Box<dyn KillPolicy>,
 
-    audio_buffer: [i16; (44100 / 60) * 2],
+    // Every random decision the VM makes (RAND, EXA ordering, KILL
+    // targets, noise samples) comes from this seed so runs can be replayed.
+    seed: u64,
 
-    pub redshift: Option<RedshiftEnvironment>,
+    rng: fastrand::Rng,
+}
 
-    pub randomize_exa_order: bool,
+impl Default for VM {
+    fn default() -> Self {
+        VM::new()
+    }
 }
 
-impl<'a> VM<'a> {
-    pub fn new() -> VM<'a> {
+impl VM {
+    pub fn new() -> VM {
+        VM::with_seed(fastrand::u64(..))
+    }
+
+    /// Create a VM whose randomness is fully determined by seed. Two VMs
+    /// with the same seed running the same programs behave identically.
+    pub fn with_seed(seed: u64) -> VM {
         VM {
             cycle: 0,
-            hosts: HashMap::new(),
+            links_traversed: 0,
+            kills: 0,
+            hosts: Hosts::default(),
             exas: Vec::new(),
-            exa_stack: Vec::new(),
-            bus: Rc::new(RefCell::new(MessageBus::new())),
-            file_counter: Rc::new(AtomicI32::new(400)),
+            run_queue: VecDeque::new(),
+            spawned: Vec::new(),
+            colliders: Vec::new(),
+            kill_candidates: Vec::new(),
+            mid_cycle: false,
+            debugger: Debugger::default(),
+            trace: None,
+            rewind: None,
+            terminated: History::default(),
+            bus: MessageBus::new(),
+            file_counter: 400,
             framebuffer: [false; 120 * 100],
+            anaglyph_framebuffer: [AnaglyphPixel::default(); 120 * 100],
             audio_buffer: [0; (44100 / 60) * 2],
             redshift: None,
-            randomize_exa_order: true,
+            scheduler: Box::new(Shuffle),
+            kill_policy: Box::new(Prioritized),
+            seed,
+            rng: fastrand::Rng::with_seed(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restart the VM's random number generator from seed, including
+    /// the Redshift noise channel's samples.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng.seed(seed);
+        if let Some(redshift) = &mut self.redshift {
+            redshift.nse0_wave = Noise::with_seed(seed);
         }
     }
 
-    pub fn add_host(&mut self, host: Shared<Host<'a>>) {
-        self.hosts
-            .insert(String::from(&host.borrow().name), host.clone());
+    /// Add a host, replacing any with the same name, and return the id
+    /// to link it and spawn EXAs in it with.
+    pub fn add_host(&mut self, mut host: Host) -> HostId {
+        if self.trace.is_some() {
+            let name = host.name.clone();
+            host.bus.set_tracing(Some(Bus::Host(name)));
+        }
+        self.hosts.insert(host)
+    }
+
+    /// Record a TraceEvent to sink for everything EXAs do from now on,
+    /// replacing any sink set before.
+    pub fn set_trace(&mut self, sink: Box<dyn TraceSink>) {
+        self.trace = Some(sink);
+        self.bus.set_tracing(Some(Bus::Global));
+        for host in self.hosts.values_mut() {
+            let name = host.name.clone();
+            host.bus.set_tracing(Some(Bus::Host(name)));
+        }
+    }
+
+    /// Stop tracing, handing back the sink.
+    pub fn take_trace(&mut self) -> Option<Box<dyn TraceSink>> {
+        self.bus.set_tracing(None);
+        for host in self.hosts.values_mut() {
+            host.bus.set_tracing(None);
+        }
+        self.trace.take()
     }
-    pub fn add_link<'b>(
-        &mut self,
-        link_id: i32,
-        from_host: Shared<Host<'b>>,
-        to_host: Shared<Host<'b>>,
-    ) {
-        let name = to_host.borrow().name.to_string();
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    pub(crate) fn trace(&mut self, exa: &str, pc: usize, event: Event) {
+        if let Some(sink) = &mut self.trace {
+            sink.record(TraceEvent {
+                cycle: self.cycle,
+                exa: exa.to_string(),
+                pc,
+                event,
+            });
+        }
+    }
+    pub fn add_link(&mut self, link_id: i32, from_host: HostId, to_host: HostId) {
         let link = HostLink {
-            to_host_name: name,
-            to_host: to_host,
+            to_host,
             traversed_this_cycle: false,
         };
-        from_host.borrow_mut().links.insert(link_id, link);
+        self.hosts[from_host].links.insert(link_id, link);
     }
-    pub fn register_exa(&mut self, exa: Shared<Exa<'a>>) {
+    pub fn register_exa(&mut self, exa: Exa) -> &mut Exa {
         self.exas.push(exa);
+        self.exas.last_mut().unwrap()
     }
-    pub fn get_exa(&self, name: &str) -> Shared<Exa<'a>> {
-        for e in self.exas.iter() {
-            if let Ok(exa) = e.try_borrow() {
-                if exa.name == name {
-                    return e.clone();
-                }
-            }
-        }
-        panic!("unknown exa {}", name)
+    pub fn get_exa(&self, name: &str) -> &Exa {
+        self.exas
+            .iter()
+            .find(|e| *e.name == *name)
+            .unwrap_or_else(|| panic!("unknown exa {}", name))
+    }
+    pub fn get_exa_mut(&mut self, name: &str) -> &mut Exa {
+        self.exas
+            .iter_mut()
+            .find(|e| *e.name == *name)
+            .unwrap_or_else(|| panic!("unknown exa {}", name))
+    }
+
+    /// Id for a new file, the same way MAKE numbers them.
+    pub fn next_file_id(&mut self) -> i32 {
+        let id = self.file_counter;
+        self.file_counter += 1;
+        id
     }
 
     // Update framebuffer based on current sprite info
@@ -214,27 +249,25 @@ impl<'a> VM<'a> {
     pub fn render(&mut self) -> &[bool; 120 * 100] {
         self.framebuffer.iter_mut().for_each(|m| *m = false);
 
+        let framebuffer = &mut self.framebuffer;
         for exa in self.exas.iter() {
-            for (x, y) in exa.borrow().pixels() {
-                self.framebuffer[(x + (y * 120)) as usize] = true;
-            }
+            exa.draw(0, |x, y| framebuffer[x + (y * 120)] = true);
         }
         &self.framebuffer
     }
 }
 
-impl fmt::Display for VM<'_> {
+impl fmt::Display for VM {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "VM (cycle:{})", self.cycle)?;
-        for h in self.hosts.values().sorted() {
-            let host = h.borrow();
+        for (id, host) in self.hosts.iter().sorted_by_key(|(_, h)| *h) {
             write!(f, "\n{}", host)?;
             for file in host.files.iter() {
                 write!(f, " {}", file)?;
             }
             for e in self.exas.iter() {
-                if e.borrow().host == *h {
-                    write!(f, "\n{}", e.borrow())?;
+                if e.host == id {
+                    write!(f, "\n{}", e)?;
                 }
             }
         }
@@ -0,0 +1,422 @@
+//! Parsed instructions compiled into the form EXAs actually run. Register
+//! names are resolved to indices, hardware registers to slots the EXA
+//! binds in whatever host it's in, and labels to the pc they jump to, so
+//! running an instruction never has to look at a string. A Program is
+//! shared between an EXA and everything it REPLs.
+
+use std::collections::HashMap;
+
+use super::instruction::{Comparator, Instruction, Span, Target};
+use super::value::Value;
+
+/// One of the EXA's own registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg {
+    X,
+    T,
+    GX,
+    GY,
+    GZ,
+    GP,
+    CI,
+    CO,
+}
+
+impl Reg {
+    pub fn from_name(name: &str) -> Option<Reg> {
+        match name.to_ascii_lowercase().as_str() {
+            "x" => Some(Reg::X),
+            "t" => Some(Reg::T),
+            "gx" => Some(Reg::GX),
+            "gy" => Some(Reg::GY),
+            "gz" => Some(Reg::GZ),
+            "gp" => Some(Reg::GP),
+            "ci" => Some(Reg::CI),
+            "co" => Some(Reg::CO),
+            _ => None,
+        }
+    }
+
+    /// Registers the VM reads back as numbers are clamped to a range and
+    /// can't hold keywords.
+    pub fn range(self) -> Option<(i32, i32)> {
+        match self {
+            Reg::GX => Some((-10, 120)),
+            Reg::GY => Some((-10, 100)),
+            Reg::GZ => Some((-9, 9)),
+            Reg::CO => Some((-9999, 9999)),
+            _ => None,
+        }
+    }
+}
+
+/// Same as Reg::range, for the Redshift's hardware registers.
+pub fn hardware_range(name: &str) -> Option<(i32, i32)> {
+    match name {
+        "#sqr0" | "#sqr1" | "#tri0" | "#nse0" => Some((0, 99)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Number(i32),
+    Keyword(Value),
+    Register(Reg),
+    M,
+    F,
+    /// Index into Program::hardware.
+    Hardware(usize),
+    /// Not a register EXAs have. Using it is an error, but only once
+    /// the instruction runs.
+    Unknown(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Jump {
+    Pc(usize),
+    Unknown(String),
+}
+
+/// A decoded Instruction. MARK and DATA don't make it this far.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    Copy(Operand, Operand),
+    Addi(Operand, Operand, Operand),
+    Subi(Operand, Operand, Operand),
+    Muli(Operand, Operand, Operand),
+    Divi(Operand, Operand, Operand),
+    Modi(Operand, Operand, Operand),
+    Swiz(Operand, Operand, Operand),
+    Jump(Jump),
+    Tjmp(Jump),
+    Fjmp(Jump),
+    Test(Operand, Comparator, Operand),
+    Repl(Jump),
+    Halt,
+    Kill,
+    Link(Operand),
+    Host(Operand),
+    Mode,
+    VoidM,
+    TestMrd,
+    Make,
+    Grab(Operand),
+    File(Operand),
+    Seek(Operand),
+    VoidF,
+    Drop,
+    Wipe,
+    TestEof,
+    Noop,
+    Rand(Operand, Operand, Operand),
+    Wait,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HardwareSlot {
+    /// Lowercase, as hosts key their registers.
+    pub name: String,
+    pub range: Option<(i32, i32)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    pub ops: Vec<Op>,
+    /// What each op was decoded from, for tracing, debuggers and save
+    /// states.
+    pub source: Vec<Instruction>,
+    /// Where each op came from in the source, if known.
+    pub spans: Vec<Span>,
+    /// Label name to the pc it marks.
+    pub labels: HashMap<String, usize>,
+    /// Every distinct hardware register the program uses.
+    pub hardware: Vec<HardwareSlot>,
+}
+
+impl Program {
+    /// Compile freshly parsed instructions, along with the span of each
+    /// if known. MARKs become labels and DATA is returned separately so
+    /// the EXA can put it in a file.
+    pub fn compile(mut instructions: Vec<Instruction>, spans: Vec<Span>) -> (Program, Vec<Value>) {
+        let spans = instructions
+            .iter()
+            .zip(spans)
+            .filter(|(i, _)| !matches!(i, Instruction::Mark(_) | Instruction::Data(_)))
+            .map(|(_, span)| span)
+            .collect();
+        let data = extract_data(&mut instructions);
+        let labels = extract_labels(&mut instructions);
+        (Program::decode(instructions, spans, labels), data)
+    }
+
+    /// Build a program from instructions with MARK and DATA already taken
+    /// out, such as one read back from a save state.
+    pub fn decode(
+        source: Vec<Instruction>,
+        spans: Vec<Span>,
+        labels: HashMap<String, usize>,
+    ) -> Program {
+        let mut decoder = Decoder {
+            labels: &labels,
+            hardware: vec![],
+        };
+        let ops = source.iter().map(|i| decoder.op(i)).collect();
+        let hardware = decoder.hardware;
+        Program {
+            ops,
+            source,
+            spans,
+            labels,
+            hardware,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Where the op at pc came from in the source, if known.
+    pub fn span(&self, pc: usize) -> Option<Span> {
+        self.spans.get(pc).copied()
+    }
+}
+
+struct Decoder<'l> {
+    labels: &'l HashMap<String, usize>,
+    hardware: Vec<HardwareSlot>,
+}
+
+impl Decoder<'_> {
+    fn op(&mut self, instruction: &Instruction) -> Op {
+        match instruction {
+            Instruction::Copy(src, dest) => Op::Copy(self.operand(src), self.operand(dest)),
+            Instruction::Addi(l, r, d) => {
+                Op::Addi(self.operand(l), self.operand(r), self.operand(d))
+            }
+            Instruction::Subi(l, r, d) => {
+                Op::Subi(self.operand(l), self.operand(r), self.operand(d))
+            }
+            Instruction::Muli(l, r, d) => {
+                Op::Muli(self.operand(l), self.operand(r), self.operand(d))
+            }
+            Instruction::Divi(l, r, d) => {
+                Op::Divi(self.operand(l), self.operand(r), self.operand(d))
+            }
+            Instruction::Modi(l, r, d) => {
+                Op::Modi(self.operand(l), self.operand(r), self.operand(d))
+            }
+            Instruction::Swiz(i, m, d) => {
+                Op::Swiz(self.operand(i), self.operand(m), self.operand(d))
+            }
+            Instruction::Jump(label) => Op::Jump(self.jump(label)),
+            Instruction::Tjmp(label) => Op::Tjmp(self.jump(label)),
+            Instruction::Fjmp(label) => Op::Fjmp(self.jump(label)),
+            Instruction::Test(l, comp, r) => {
+                Op::Test(self.operand(l), comp.clone(), self.operand(r))
+            }
+            Instruction::Repl(label) => Op::Repl(self.jump(label)),
+            Instruction::Halt => Op::Halt,
+            Instruction::Kill => Op::Kill,
+            Instruction::Link(dest) => Op::Link(self.operand(dest)),
+            Instruction::Host(dest) => Op::Host(self.operand(dest)),
+            Instruction::Mode => Op::Mode,
+            Instruction::VoidM => Op::VoidM,
+            Instruction::TestMrd => Op::TestMrd,
+            Instruction::Make => Op::Make,
+            Instruction::Grab(file) => Op::Grab(self.operand(file)),
+            Instruction::File(dest) => Op::File(self.operand(dest)),
+            Instruction::Seek(amount) => Op::Seek(self.operand(amount)),
+            Instruction::VoidF => Op::VoidF,
+            Instruction::Drop => Op::Drop,
+            Instruction::Wipe => Op::Wipe,
+            Instruction::TestEof => Op::TestEof,
+            Instruction::Noop => Op::Noop,
+            Instruction::Rand(lo, hi, d) => {
+                Op::Rand(self.operand(lo), self.operand(hi), self.operand(d))
+            }
+            Instruction::Wait => Op::Wait,
+            Instruction::Mark(_) => panic!("marks should have been preprocessed out"),
+            Instruction::Data(_) => panic!("datas should have been preprocessed out"),
+        }
+    }
+
+    fn operand(&mut self, target: &Target) -> Operand {
+        let name = match target {
+            Target::Literal(n) => return Operand::Number(*n),
+            Target::Keyword(k) => return Operand::Keyword(Value::keyword(k)),
+            // Parsed scripts are already lowercase, but built ones may not be
+            Target::Register(name) => name.to_ascii_lowercase(),
+        };
+        match name.as_str() {
+            "m" => return Operand::M,
+            "f" => return Operand::F,
+            _ => {}
+        }
+        if name.starts_with('#') {
+            let slot = match self.hardware.iter().position(|h| h.name == name) {
+                Some(slot) => slot,
+                None => {
+                    self.hardware.push(HardwareSlot {
+                        range: hardware_range(&name),
+                        name,
+                    });
+                    self.hardware.len() - 1
+                }
+            };
+            return Operand::Hardware(slot);
+        }
+        match Reg::from_name(&name) {
+            Some(reg) => Operand::Register(reg),
+            None => Operand::Unknown(name),
+        }
+    }
+
+    fn jump(&self, label: &str) -> Jump {
+        match self.labels.get(label) {
+            Some(pc) => Jump::Pc(*pc),
+            None => Jump::Unknown(label.to_string()),
+        }
+    }
+}
+
+fn extract_labels(instructions: &mut Vec<Instruction>) -> HashMap<String, usize> {
+    let mut m = HashMap::new();
+
+    let mut idx = 0;
+    while idx < instructions.len() {
+        let inst = &instructions[idx];
+        match inst {
+            Instruction::Mark(label) => {
+                m.insert(label.to_string(), idx);
+                instructions.remove(idx);
+            }
+            _ => idx += 1,
+        }
+    }
+
+    m
+}
+
+fn extract_data(instructions: &mut Vec<Instruction>) -> Vec<Value> {
+    let mut contents: Vec<Value> = vec![];
+
+    let mut idx = 0;
+    while idx < instructions.len() {
+        let inst = &instructions[idx];
+        match inst {
+            Instruction::Data(data) => {
+                contents.extend(data.iter().cloned());
+                instructions.remove(idx);
+            }
+            _ => idx += 1,
+        }
+    }
+
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_labels() {
+        let mut insts = vec![
+            Instruction::Mark("first".into()),
+            Instruction::Noop,
+            Instruction::Mark("second".into()),
+            Instruction::Mark("third".into()),
+            Instruction::Noop,
+        ];
+        let extracted = extract_labels(&mut insts);
+        assert_eq!(insts, vec![Instruction::Noop, Instruction::Noop]);
+        assert_eq!(*extracted.get("first").expect("not found"), 0);
+        assert_eq!(*extracted.get("second").expect("not found"), 1);
+        assert_eq!(*extracted.get("third").expect("not found"), 1);
+    }
+
+    #[test]
+    fn test_extract_labels_at_end() {
+        let mut insts = vec![
+            Instruction::Mark("first".into()),
+            Instruction::Noop,
+            Instruction::Mark("second".into()),
+            Instruction::Mark("third".into()),
+        ];
+        let extracted = extract_labels(&mut insts);
+        assert_eq!(insts, vec![Instruction::Noop]);
+        assert_eq!(*extracted.get("first").expect("not found"), 0);
+        assert_eq!(*extracted.get("second").expect("not found"), 1);
+        assert_eq!(*extracted.get("third").expect("not found"), 1);
+    }
+
+    #[test]
+    fn test_compile() {
+        let insts = vec![
+            Instruction::Mark("top".into()),
+            Instruction::Copy(
+                Target::Register("#nse0".into()),
+                Target::Register("m".into()),
+            ),
+            Instruction::Data(vec![Value::Number(1)]),
+            Instruction::Addi(
+                Target::Register("x".into()),
+                Target::Keyword("kw".into()),
+                Target::Register("#NSE0".into()),
+            ),
+            Instruction::Jump("top".into()),
+            Instruction::Repl("nowhere".into()),
+            Instruction::Copy(Target::Literal(1), Target::Register("#out".into())),
+        ];
+        let spans = (1..=7).map(|line| Span::new(line, 1)).collect();
+        let (program, data) = Program::compile(insts, spans);
+        assert_eq!(data, vec![Value::Number(1)]);
+        let lines: Vec<usize> = program.spans.iter().map(|s| s.line).collect();
+        assert_eq!(lines, vec![2, 4, 5, 6, 7]);
+        assert_eq!(program.span(2), Some(Span::new(5, 1)));
+        assert_eq!(program.span(5), None);
+        assert_eq!(
+            program.ops,
+            vec![
+                Op::Copy(Operand::Hardware(0), Operand::M),
+                Op::Addi(
+                    Operand::Register(Reg::X),
+                    Operand::Keyword(Value::Keyword("KW".into())),
+                    Operand::Hardware(0),
+                ),
+                Op::Jump(Jump::Pc(0)),
+                Op::Repl(Jump::Unknown("nowhere".into())),
+                Op::Copy(Operand::Number(1), Operand::Hardware(1)),
+            ]
+        );
+        assert_eq!(program.hardware[0].range, Some((0, 99)));
+        assert_eq!(program.hardware[1].name, "#out");
+        assert_eq!(program.hardware[1].range, None);
+    }
+
+    #[test]
+    fn test_compile_uppercase() {
+        let insts = vec![
+            Instruction::Copy(
+                Target::Register("F".into()),
+                Target::Register("#SQR0".into()),
+            ),
+            Instruction::Copy(Target::Register("M".into()), Target::Register("X".into())),
+        ];
+        let (program, _) = Program::compile(insts, vec![]);
+        assert_eq!(
+            program.ops,
+            vec![
+                Op::Copy(Operand::F, Operand::Hardware(0)),
+                Op::Copy(Operand::M, Operand::Register(Reg::X)),
+            ]
+        );
+        assert_eq!(program.hardware[0].name, "#sqr0");
+        assert_eq!(program.hardware[0].range, Some((0, 99)));
+    }
+}
@@ -1,28 +1,51 @@
-use std::cell::RefCell;
-
 use super::audio::{Noise, SquareWave, TriangleWave};
 use super::register::Register;
-use super::{Host, Permissions, Shared, VM};
-
+use super::rewind::Input;
+use super::value::Value;
+use super::{Host, Permissions, VM};
+
+/// The host and name of every register Redshift hardware provides.
+pub(crate) const REDSHIFT_REGISTERS: [(&str, &str); 8] = [
+    ("input", "#padx"),
+    ("input", "#pady"),
+    ("input", "#padb"),
+    ("input", "#en3d"),
+    ("sound", "#sqr0"),
+    ("sound", "#sqr1"),
+    ("sound", "#tri0"),
+    ("sound", "#nse0"),
+];
+
+/// Redshift state that lives outside the network. The registers games
+/// talk to the hardware through are in the input and sound hosts.
 #[derive(Debug)]
 pub struct RedshiftEnvironment {
     pub game_name: String,
-    pub pady: Shared<Register>,
-    pub padx: Shared<Register>,
-    pub padb: Shared<Register>,
-    pub en3d: Shared<Register>,
-    pub sqr0: Shared<Register>,
-    pub sqr1: Shared<Register>,
-    pub tri0: Shared<Register>,
-    pub nse0: Shared<Register>,
-
-    pub sqr0_wave: RefCell<SquareWave>,
-    pub sqr1_wave: RefCell<SquareWave>,
-    pub tri0_wave: RefCell<TriangleWave>,
-    pub nse0_wave: RefCell<Noise>,
+
+    pub sqr0_wave: SquareWave,
+    pub sqr1_wave: SquareWave,
+    pub tri0_wave: TriangleWave,
+    pub nse0_wave: Noise,
 }
 
-#[derive(Debug)]
+/// A single pixel of the anaglyph 3D framebuffer. Each eye gets its
+/// own copy of every sprite, so a pixel can be lit for the red (left)
+/// eye, the cyan (right) eye, both, or neither.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AnaglyphPixel {
+    pub red: bool,
+    pub cyan: bool,
+}
+
+/// Horizontal offsets of the red and cyan copies of a sprite at the
+/// given GZ depth. Positive depths pop out of the screen, so the red
+/// copy moves right and the cyan copy moves left. The two copies end
+/// up GZ pixels apart.
+pub fn parallax(depth: i32) -> (i32, i32) {
+    (depth - depth / 2, -(depth / 2))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum RedshiftButton {
     Up,
     Down,
@@ -34,105 +57,105 @@ pub enum RedshiftButton {
     Z,
 }
 
-impl<'a> VM<'a> {
+impl VM {
     // Instantiate a VM matching the Redshift spec
-    pub fn new_redshift() -> VM<'a> {
+    pub fn new_redshift() -> VM {
         let mut vm = VM::new();
 
-        let core = Host::new_shared("core".to_string(), 18);
-        let input = Host::new_shared("input".to_string(), 24);
-        let sound = Host::new_shared("sound".to_string(), 24);
-        let aux1 = Host::new_shared("aux1".to_string(), 3);
-        let aux2 = Host::new_shared("aux2".to_string(), 3);
-
-        let padx = Register::new_shared(Permissions::ReadWrite, 0);
-        let pady = Register::new_shared(Permissions::ReadWrite, 0);
-        let padb = Register::new_shared(Permissions::ReadWrite, 0);
-        let en3d = Register::new_shared(Permissions::ReadWrite, 0);
-        let sqr0 = Register::new_shared(Permissions::ReadWrite, 0);
-        let sqr1 = Register::new_shared(Permissions::ReadWrite, 0);
-        let tri0 = Register::new_shared(Permissions::ReadWrite, 0);
-        let nse0 = Register::new_shared(Permissions::ReadWrite, 0);
-
-        input
-            .borrow_mut()
-            .add_register(String::from("#PADX"), padx.clone());
-        input
-            .borrow_mut()
-            .add_register(String::from("#PADY"), pady.clone());
-        input
-            .borrow_mut()
-            .add_register(String::from("#PADB"), padb.clone());
-        input
-            .borrow_mut()
-            .add_register(String::from("#EN3D"), en3d.clone());
-
-        sound
-            .borrow_mut()
-            .add_register(String::from("#SQR0"), sqr0.clone());
-        sound
-            .borrow_mut()
-            .add_register(String::from("#SQR1"), sqr1.clone());
-        sound
-            .borrow_mut()
-            .add_register(String::from("#TRI0"), tri0.clone());
-        sound
-            .borrow_mut()
-            .add_register(String::from("#NSE0"), nse0.clone());
-
-        vm.add_host(core.clone());
-        vm.add_host(input.clone());
-        vm.add_host(sound.clone());
-        vm.add_host(aux1.clone());
-        vm.add_host(aux2.clone());
-
-        vm.add_link(800, core.clone(), input.clone());
-        vm.add_link(-1, input.clone(), core.clone());
-        vm.add_link(801, core.clone(), sound.clone());
-        vm.add_link(-1, sound.clone(), core.clone());
-        vm.add_link(802, core.clone(), aux1.clone());
-        vm.add_link(-1, aux1.clone(), core.clone());
-        vm.add_link(803, core.clone(), aux2.clone());
-        vm.add_link(-1, aux2.clone(), core.clone());
+        let core = vm.add_host(Host::new("core".to_string(), 18));
+        let mut input = Host::new("input".to_string(), 24);
+        let mut sound = Host::new("sound".to_string(), 24);
+        for (host, name) in REDSHIFT_REGISTERS.iter() {
+            let host = match *host {
+                "input" => &mut input,
+                _ => &mut sound,
+            };
+            host.add_register(name.to_string(), Register::new(Permissions::ReadWrite, 0));
+        }
+        let input = vm.add_host(input);
+        let sound = vm.add_host(sound);
+        let aux1 = vm.add_host(Host::new("aux1".to_string(), 3));
+        let aux2 = vm.add_host(Host::new("aux2".to_string(), 3));
+
+        vm.add_link(800, core, input);
+        vm.add_link(-1, input, core);
+        vm.add_link(801, core, sound);
+        vm.add_link(-1, sound, core);
+        vm.add_link(802, core, aux1);
+        vm.add_link(-1, aux1, core);
+        vm.add_link(803, core, aux2);
+        vm.add_link(-1, aux2, core);
 
         vm.redshift = Some(RedshiftEnvironment {
             game_name: "".to_string(),
-            padx: padx.clone(),
-            pady: pady.clone(),
-            padb: padb.clone(),
-            en3d: en3d.clone(),
-            sqr0: sqr0.clone(),
-            sqr1: sqr1.clone(),
-            tri0: tri0.clone(),
-            nse0: nse0.clone(),
-
-            sqr0_wave: RefCell::new(SquareWave::default()),
-            sqr1_wave: RefCell::new(SquareWave::default()),
-            tri0_wave: RefCell::new(TriangleWave::default()),
-            nse0_wave: RefCell::new(Noise::default()),
+
+            sqr0_wave: SquareWave::default(),
+            sqr1_wave: SquareWave::default(),
+            tri0_wave: TriangleWave::default(),
+            nse0_wave: Noise::with_seed(vm.seed),
         });
 
         vm
     }
 
+    /// Numeric value of one of the Redshift hardware registers.
+    pub(crate) fn redshift_register(&self, host: &str, name: &str) -> i32 {
+        match self.hosts[host].registers[name].peek() {
+            Value::Number(n) => n,
+            Value::Keyword(_) => 0,
+        }
+    }
+
+    fn set_redshift_register(&mut self, host: &str, name: &str, value: i32) {
+        let register = self.hosts[host].registers.get_mut(name);
+        register
+            .expect("unknown register")
+            .on_write(Value::Number(value))
+            .expect("redshift registers are read/write");
+    }
+
+    /// Games switch on anaglyph 3D by writing anything other
+    /// than 0 to #EN3D.
+    pub fn anaglyph_enabled(&self) -> bool {
+        self.redshift.is_some() && self.redshift_register("input", "#en3d") != 0
+    }
+
+    // Update the anaglyph framebuffer based on current sprite info
+    // of running EXAs, then return ref to it. Each sprite is drawn
+    // once per eye, offset horizontally based on the EXA's GZ.
+    pub fn render_anaglyph(&mut self) -> &[AnaglyphPixel; 120 * 100] {
+        self.anaglyph_framebuffer
+            .iter_mut()
+            .for_each(|m| *m = AnaglyphPixel::default());
+
+        let framebuffer = &mut self.anaglyph_framebuffer;
+        for e in self.exas.iter() {
+            let (red_offset, cyan_offset) = parallax(e.depth());
+            e.draw(red_offset, |x, y| framebuffer[x + (y * 120)].red = true);
+            e.draw(cyan_offset, |x, y| framebuffer[x + (y * 120)].cyan = true);
+        }
+        &self.anaglyph_framebuffer
+    }
+
     pub fn reset_inputs(&mut self) {
-        let r = &mut self.redshift.as_mut().unwrap();
-        r.padx.borrow_mut().value = 0;
-        r.pady.borrow_mut().value = 0;
-        r.padb.borrow_mut().value = 0;
+        self.record_input(Input::ResetInputs);
+        self.set_redshift_register("input", "#padx", 0);
+        self.set_redshift_register("input", "#pady", 0);
+        self.set_redshift_register("input", "#padb", 0);
     }
 
     pub fn input_pressed(&mut self, for_input: RedshiftButton) {
-        let r = &self.redshift.as_ref().unwrap();
+        self.record_input(Input::Button(for_input.clone()));
+        let padb = self.redshift_register("input", "#padb");
         match for_input {
-            RedshiftButton::Up => r.pady.borrow_mut().value = -1,
-            RedshiftButton::Down => r.pady.borrow_mut().value = 1,
-            RedshiftButton::Left => r.padx.borrow_mut().value = -1,
-            RedshiftButton::Right => r.padx.borrow_mut().value = 1,
-            RedshiftButton::Start => r.padb.borrow_mut().value += 1000,
-            RedshiftButton::Z => r.padb.borrow_mut().value += 100,
-            RedshiftButton::Y => r.padb.borrow_mut().value += 10,
-            RedshiftButton::X => r.padb.borrow_mut().value += 1,
+            RedshiftButton::Up => self.set_redshift_register("input", "#pady", -1),
+            RedshiftButton::Down => self.set_redshift_register("input", "#pady", 1),
+            RedshiftButton::Left => self.set_redshift_register("input", "#padx", -1),
+            RedshiftButton::Right => self.set_redshift_register("input", "#padx", 1),
+            RedshiftButton::Start => self.set_redshift_register("input", "#padb", padb + 1000),
+            RedshiftButton::Z => self.set_redshift_register("input", "#padb", padb + 100),
+            RedshiftButton::Y => self.set_redshift_register("input", "#padb", padb + 10),
+            RedshiftButton::X => self.set_redshift_register("input", "#padb", padb + 1),
         }
     }
 }
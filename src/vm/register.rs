@@ -1,19 +1,120 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::error::Error;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
 
-use super::{Permissions, Shared};
+use super::error::ExaError;
+use super::value::Value;
+use super::Permissions;
 
-#[derive(Debug, PartialEq, Eq)]
+/// Behavior behind a hardware (#) register. EXAs reading or writing the
+/// register call into these hooks, so embedders can hang input streams,
+/// output sinks, clocks or other devices off a host.
+///
+/// Hooks report problems with ExaErrors: blocking ones leave the EXA stuck
+/// on the instruction until a later cycle, fatal ones kill it. Any other
+/// error kills it too.
+///
+/// Hosts own their registers, so they need to be Send for the VM to be.
+/// Wrap one in an Arc<Mutex<_>> to keep a handle on it from outside.
+pub trait HardwareRegister: Debug + Send {
+    /// Called when an EXA reads the register. Numbers outside
+    /// -9999..=9999 are clamped, like EXAs' own arithmetic.
+    fn on_read(&mut self) -> Result<Value, Box<dyn Error>>;
+
+    /// Called when an EXA writes to the register.
+    fn on_write(&mut self, value: Value) -> Result<(), Box<dyn Error>>;
+
+    /// The value an EXA would currently see, without side effects. Used
+    /// by save states and anything else inspecting the network.
+    fn peek(&self) -> Value;
+
+    /// Put the register back to a value peek returned earlier. Called
+    /// when the VM is rewound, before the cycles since are replayed
+    /// against it. Registers with nothing to wind back can leave this
+    /// alone.
+    fn restore(&mut self, _value: Value) {}
+
+    fn permissions(&self) -> Permissions {
+        Permissions::ReadWrite
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Register {
     pub permissions: Permissions,
-    pub value: i32,
+    pub value: Value,
 }
 
 impl Register {
-    pub fn new(permissions: Permissions, value: i32) -> Register {
-        Register { permissions, value }
+    pub fn new<V: Into<Value>>(permissions: Permissions, value: V) -> Register {
+        Register {
+            permissions,
+            value: value.into(),
+        }
+    }
+    /// Numeric value of the register. Keywords are refused by the
+    /// registers the VM itself reads from, so anything else reads as 0.
+    pub fn number(&self) -> i32 {
+        match self.value {
+            Value::Number(n) => n,
+            Value::Keyword(_) => 0,
+        }
+    }
+}
+
+/// A plain storage cell, which is what every register is unless the
+/// embedder says otherwise.
+impl HardwareRegister for Register {
+    fn on_read(&mut self) -> Result<Value, Box<dyn Error>> {
+        match self.permissions {
+            Permissions::Denied => Err(ExaError::DeactivatedRegister.into()),
+            Permissions::WriteOnly => Err(ExaError::WriteOnlyRegister.into()),
+            _ => Ok(self.value.clone()),
+        }
+    }
+
+    fn on_write(&mut self, value: Value) -> Result<(), Box<dyn Error>> {
+        match self.permissions {
+            Permissions::Denied => Err(ExaError::DeactivatedRegister.into()),
+            Permissions::ReadOnly => Err(ExaError::ReadOnlyRegister.into()),
+            _ => {
+                self.value = value;
+                Ok(())
+            }
+        }
+    }
+
+    fn peek(&self) -> Value {
+        self.value.clone()
     }
-    pub fn new_shared(permissions: Permissions, value: i32) -> Shared<Register> {
-        Rc::new(RefCell::new(Register::new(permissions, value)))
+
+    fn restore(&mut self, value: Value) {
+        self.value = value;
+    }
+
+    fn permissions(&self) -> Permissions {
+        self.permissions.clone()
+    }
+}
+
+impl<T: HardwareRegister + ?Sized> HardwareRegister for Arc<Mutex<T>> {
+    fn on_read(&mut self) -> Result<Value, Box<dyn Error>> {
+        self.lock().unwrap().on_read()
+    }
+
+    fn on_write(&mut self, value: Value) -> Result<(), Box<dyn Error>> {
+        self.lock().unwrap().on_write(value)
+    }
+
+    fn peek(&self) -> Value {
+        self.lock().unwrap().peek()
+    }
+
+    fn restore(&mut self, value: Value) {
+        self.lock().unwrap().restore(value)
+    }
+
+    fn permissions(&self) -> Permissions {
+        self.lock().unwrap().permissions()
     }
 }
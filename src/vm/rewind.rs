@@ -0,0 +1,196 @@
+//! Time travel for debugging. With rewinding enabled, the VM keeps a save
+//! state every few cycles along with every input fed to it from outside.
+//! Any cycle since the oldest checkpoint can then be rebuilt by loading
+//! the checkpoint before it and running forward again, which lands on the
+//! same VM as the first time around since run_cycle is deterministic
+//! given the seed and inputs.
+//!
+//! History is kept at cycle granularity: inputs are replayed before the
+//! cycle they were given in, even if a debugger was partway through it.
+//! Custom hardware registers don't survive save states, so the live ones
+//! are carried over and restored to their checkpointed values instead.
+//! They see the replayed reads and writes again.
+
+use std::collections::VecDeque;
+use std::error::Error;
+
+use super::kill::Prioritized;
+use super::redshift::RedshiftButton;
+use super::scheduler::Shuffle;
+use super::VM;
+
+/// Anything done to the VM from outside run_cycle that changes what
+/// happens next.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Button(RedshiftButton),
+    ResetInputs,
+    UnfreezeWaiters,
+}
+
+#[derive(Debug)]
+pub struct Rewind {
+    interval: u32,
+    capacity: usize,
+    // Save states taken at the start of a cycle, before any of that
+    // cycle's inputs, oldest first
+    checkpoints: VecDeque<(u32, Vec<u8>)>,
+    // Every input since the oldest checkpoint, with the cycle it came
+    // before, in the order they were given
+    inputs: Vec<(u32, Input)>,
+}
+
+impl Rewind {
+    fn new(interval: u32, capacity: usize) -> Rewind {
+        Rewind {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            checkpoints: VecDeque::new(),
+            inputs: vec![],
+        }
+    }
+
+    fn due(&self, cycle: u32) -> bool {
+        match self.checkpoints.back() {
+            Some((last, _)) => cycle >= last.saturating_add(self.interval),
+            None => true,
+        }
+    }
+
+    fn checkpoint(&mut self, cycle: u32, state: Vec<u8>) {
+        self.checkpoints.push_back((cycle, state));
+        if self.checkpoints.len() > self.capacity {
+            self.checkpoints.pop_front();
+            let oldest = self.checkpoints[0].0;
+            self.inputs.retain(|(c, _)| *c >= oldest);
+        }
+    }
+}
+
+impl VM {
+    /// Start keeping history so the VM can be rewound. A checkpoint is
+    /// saved every interval cycles and the last capacity of them are
+    /// kept, so roughly interval * capacity cycles can be revisited.
+    /// Smaller intervals make rewinds faster at the cost of memory.
+    pub fn enable_rewind(&mut self, interval: u32, capacity: usize) {
+        let mut rewind = Rewind::new(interval, capacity);
+        if !self.mid_cycle {
+            rewind.checkpoint(self.cycle, self.save_state());
+        }
+        self.rewind = Some(rewind);
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    pub fn rewind_enabled(&self) -> bool {
+        self.rewind.is_some()
+    }
+
+    /// The earliest cycle rewind_to can go back to, if any.
+    pub fn earliest_cycle(&self) -> Option<u32> {
+        self.rewind.as_ref()?.checkpoints.front().map(|(c, _)| *c)
+    }
+
+    /// Go back to the start of the previous cycle, or to the start of
+    /// this one if a debugger is partway through it.
+    pub fn step_back(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.mid_cycle {
+            return self.rewind_to(self.cycle);
+        }
+        if self.cycle == 0 {
+            return Err("already at the first cycle".into());
+        }
+        self.rewind_to(self.cycle - 1)
+    }
+
+    /// Put the VM back the way it was at the start of cycle, before any
+    /// input given for that cycle. Hardware registers, breakpoints,
+    /// watchpoints, the kill policy, the scheduler, the trace sink and
+    /// EXAs terminated before cycle carry over, but nothing is traced
+    /// while re-simulating. History after cycle is forgotten, since new
+    /// inputs will take it elsewhere.
+    pub fn rewind_to(&mut self, cycle: u32) -> Result<(), Box<dyn Error>> {
+        let rewind = match &self.rewind {
+            Some(rewind) => rewind,
+            None => return Err("rewinding is not enabled".into()),
+        };
+        if cycle > self.cycle {
+            return Err(format!("cannot rewind forward to cycle {}", cycle).into());
+        }
+        let (start, state) = match rewind.checkpoints.iter().rev().find(|(c, _)| *c <= cycle) {
+            Some(checkpoint) => checkpoint,
+            None => return Err(format!("cycle {} is no longer in history", cycle).into()),
+        };
+
+        let mut vm = VM::load_state(state)?;
+        vm.kill_policy = std::mem::replace(&mut self.kill_policy, Box::new(Prioritized));
+        vm.scheduler = std::mem::replace(&mut self.scheduler, Box::new(Shuffle));
+        // Keep the slots the checkpoint's EXAs were compiled against
+        for host in self.hosts.values_mut() {
+            let id = match vm.hosts.id(&host.name) {
+                Some(id) => id,
+                None => continue,
+            };
+            let registers = &mut vm.hosts[id].registers;
+            for (name, mut register) in host.registers.drain() {
+                if let Some(saved) = registers.get(&name) {
+                    register.restore(saved.peek());
+                }
+                registers.insert(name, register);
+            }
+        }
+        let inputs = rewind.inputs.iter();
+        let mut inputs = inputs.filter(|(c, _)| c >= start && *c < cycle).peekable();
+        for c in *start..cycle {
+            while let Some((_, input)) = inputs.next_if(|(i, _)| *i == c) {
+                vm.apply_input(input);
+            }
+            vm.run_cycle();
+        }
+
+        let mut rewind = self.rewind.take().unwrap();
+        rewind.checkpoints.retain(|(c, _)| *c <= cycle);
+        rewind.inputs.retain(|(c, _)| *c < cycle);
+        vm.rewind = Some(rewind);
+
+        vm.terminated = std::mem::take(&mut self.terminated);
+        vm.terminated.truncate(cycle);
+        vm.debugger = std::mem::take(&mut self.debugger);
+        vm.reset_debugger_position();
+        if let Some(sink) = self.trace.take() {
+            vm.set_trace(sink);
+        }
+
+        *self = vm;
+        Ok(())
+    }
+
+    /// Note an input for rewinding. Called by everything that changes the
+    /// VM between cycles.
+    pub(crate) fn record_input(&mut self, input: Input) {
+        if let Some(rewind) = &mut self.rewind {
+            rewind.inputs.push((self.cycle, input));
+        }
+    }
+
+    /// Save a checkpoint if one is due. Only called between cycles.
+    pub(crate) fn checkpoint_if_due(&mut self) {
+        if !self.rewind.as_ref().is_some_and(|r| r.due(self.cycle)) {
+            return;
+        }
+        let state = self.save_state();
+        if let Some(rewind) = &mut self.rewind {
+            rewind.checkpoint(self.cycle, state);
+        }
+    }
+
+    pub(crate) fn apply_input(&mut self, input: &Input) {
+        match input {
+            Input::Button(button) => self.input_pressed(button.clone()),
+            Input::ResetInputs => self.reset_inputs(),
+            Input::UnfreezeWaiters => self.unfreeze_waiters(),
+        }
+    }
+}
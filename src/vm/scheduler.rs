@@ -0,0 +1,90 @@
+//! The order EXAs run in each cycle. Which EXA gets a message off an M
+//! bus first depends entirely on this order, so races between EXAs often
+//! only show up under particular orderings. The VM takes a Scheduler so
+//! those orderings can be reproduced on demand.
+//!
+//! Schedulers are not part of save states. For rewinding to land back on
+//! the same VM, a scheduler's order should only depend on the cycle, the
+//! EXAs it's given and the VM's rng.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use super::exa::Exa;
+
+pub trait Scheduler: Debug + Send {
+    /// Put queue, which holds indexes into exas, into the order they'll
+    /// run in this cycle. It comes in the order they were spawned, minus
+    /// any that are frozen or dead. Any randomness should come from rng
+    /// so runs can be replayed from the VM's seed.
+    fn order(&mut self, cycle: u32, queue: &mut [usize], exas: &[Exa], rng: &fastrand::Rng);
+}
+
+/// The VM's default, and what the game does. Plenty of solutions rely on
+/// bus reads being random, so this is the only scheduler that behaves
+/// like the real thing.
+#[derive(Clone, Debug, Default)]
+pub struct Shuffle;
+
+impl Scheduler for Shuffle {
+    fn order(&mut self, _: u32, queue: &mut [usize], _: &[Exa], rng: &fastrand::Rng) {
+        rng.shuffle(queue);
+    }
+}
+
+/// Oldest EXA first, every cycle.
+#[derive(Clone, Debug, Default)]
+pub struct SpawnOrder;
+
+impl Scheduler for SpawnOrder {
+    fn order(&mut self, _: u32, _: &mut [usize], _: &[Exa], _: &fastrand::Rng) {}
+}
+
+/// Newest EXA first, every cycle.
+#[derive(Clone, Debug, Default)]
+pub struct Reverse;
+
+impl Scheduler for Reverse {
+    fn order(&mut self, _: u32, queue: &mut [usize], _: &[Exa], _: &fastrand::Rng) {
+        queue.reverse();
+    }
+}
+
+/// A hand written order, by EXA name. Named EXAs run first in the order
+/// given and everyone else follows in spawn order. Names that aren't
+/// running that cycle are skipped.
+#[derive(Clone, Debug, Default)]
+pub struct Scripted {
+    default: Vec<String>,
+    cycles: HashMap<u32, Vec<String>>,
+}
+
+impl Scripted {
+    /// Use order on every cycle without one of its own.
+    pub fn new(order: &[&str]) -> Scripted {
+        Scripted {
+            default: order.iter().map(|n| n.to_string()).collect(),
+            cycles: HashMap::new(),
+        }
+    }
+
+    /// Use order on cycle only.
+    pub fn on_cycle(mut self, cycle: u32, order: &[&str]) -> Scripted {
+        let order = order.iter().map(|n| n.to_string()).collect();
+        self.cycles.insert(cycle, order);
+        self
+    }
+}
+
+impl Scheduler for Scripted {
+    fn order(&mut self, cycle: u32, queue: &mut [usize], exas: &[Exa], _: &fastrand::Rng) {
+        let order = self.cycles.get(&cycle).unwrap_or(&self.default);
+        // Stable, so unnamed EXAs keep their spawn order at the end
+        queue.sort_by_key(|i| {
+            order
+                .iter()
+                .position(|n| **n == *exas[*i].name)
+                .unwrap_or(order.len())
+        });
+    }
+}
@@ -0,0 +1,815 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::error::Error;
+use std::sync::atomic::AtomicU32;
+use std::sync::Arc;
+
+use itertools::Itertools;
+
+use super::audio::{Noise, SquareWave, TriangleWave};
+use super::bus::MessageBus;
+use super::error::{ExaError, Fault};
+use super::exa::Exa;
+use super::file::File;
+use super::instruction::{Comparator, Instruction, Target};
+use super::redshift::{RedshiftEnvironment, REDSHIFT_REGISTERS};
+use super::register::Register;
+use super::value::Value;
+use super::{Host, HostLink, Permissions, VM};
+
+/// Every save state starts with these bytes, followed by STATE_VERSION.
+const STATE_MAGIC: &[u8; 4] = b"EXAS";
+
+/// Bump this whenever the layout of the save state changes. States
+/// written by a different version are refused rather than misread.
+pub const STATE_VERSION: u32 = 9;
+
+/// Little-endian writer for save state blobs.
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: vec![] }
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_byte(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_int(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_uint(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_long(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_float(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_len(&mut self, value: usize) {
+        self.write_uint(value as u32);
+    }
+
+    pub fn write_string(&mut self, value: &str) {
+        self.write_len(value.len());
+        self.data.extend_from_slice(value.as_bytes());
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reader for blobs produced by StateWriter. Unlike the ROM reader, every
+/// read is bounds checked since save states come from the frontend and
+/// may be truncated or garbage.
+pub struct StateReader<'d> {
+    pos: usize,
+    data: &'d [u8],
+}
+
+impl<'d> StateReader<'d> {
+    pub fn new(data: &'d [u8]) -> StateReader<'d> {
+        StateReader { pos: 0, data }
+    }
+
+    /// Everything that has not been read yet.
+    pub fn remaining(&self) -> &'d [u8] {
+        &self.data[self.pos..]
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'d [u8], Box<dyn Error>> {
+        if self.pos + length > self.data.len() {
+            return Err("save state is truncated".into());
+        }
+        let value = &self.data[self.pos..self.pos + length];
+        self.pos += length;
+        Ok(value)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.read_byte()? == 1)
+    }
+
+    pub fn read_byte(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_int(&mut self) -> Result<i32, Box<dyn Error>> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub fn read_uint(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub fn read_long(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub fn read_float(&mut self) -> Result<f32, Box<dyn Error>> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub fn read_len(&mut self) -> Result<usize, Box<dyn Error>> {
+        Ok(self.read_uint()? as usize)
+    }
+
+    pub fn read_string(&mut self) -> Result<String, Box<dyn Error>> {
+        let length = self.read_len()?;
+        Ok(String::from_utf8(self.take(length)?.to_vec())?)
+    }
+}
+
+pub fn write_permissions(w: &mut StateWriter, permissions: &Permissions) {
+    w.write_byte(match permissions {
+        Permissions::Denied => 0,
+        Permissions::ReadOnly => 1,
+        Permissions::WriteOnly => 2,
+        Permissions::ReadWrite => 3,
+    });
+}
+
+pub fn read_permissions(r: &mut StateReader) -> Result<Permissions, Box<dyn Error>> {
+    match r.read_byte()? {
+        0 => Ok(Permissions::Denied),
+        1 => Ok(Permissions::ReadOnly),
+        2 => Ok(Permissions::WriteOnly),
+        3 => Ok(Permissions::ReadWrite),
+        _ => Err("invalid register permissions in save state".into()),
+    }
+}
+
+pub fn write_value(w: &mut StateWriter, value: &Value) {
+    match value {
+        Value::Number(n) => {
+            w.write_byte(0);
+            w.write_int(*n);
+        }
+        Value::Keyword(k) => {
+            w.write_byte(1);
+            w.write_string(k);
+        }
+    }
+}
+
+pub fn read_value(r: &mut StateReader) -> Result<Value, Box<dyn Error>> {
+    match r.read_byte()? {
+        0 => Ok(Value::Number(r.read_int()?)),
+        1 => Ok(Value::Keyword(r.read_string()?)),
+        _ => Err("invalid value in save state".into()),
+    }
+}
+
+pub fn write_register(w: &mut StateWriter, register: &Register) {
+    write_permissions(w, &register.permissions);
+    write_value(w, &register.value);
+}
+
+pub fn read_register(r: &mut StateReader) -> Result<Register, Box<dyn Error>> {
+    let permissions = read_permissions(r)?;
+    Ok(Register::new(permissions, read_value(r)?))
+}
+
+pub fn write_file(w: &mut StateWriter, file: &File) {
+    w.write_int(file.id);
+    w.write_len(file.contents.len());
+    for value in file.contents.iter() {
+        write_value(w, value);
+    }
+}
+
+pub fn read_file(r: &mut StateReader) -> Result<File, Box<dyn Error>> {
+    let id = r.read_int()?;
+    let length = r.read_len()?;
+    let mut contents = Vec::with_capacity(length.min(r.data.len()));
+    for _ in 0..length {
+        contents.push(read_value(r)?);
+    }
+    Ok(File::new(id, contents))
+}
+
+/// Errors are stored as a tag byte followed by whatever the variant
+/// carries, then where the fault happened.
+pub fn write_error(w: &mut StateWriter, error: &Option<Fault>) {
+    let fault = match error {
+        None => {
+            w.write_byte(0);
+            return;
+        }
+        Some(fault) => fault,
+    };
+
+    match &fault.error {
+        ExaError::Blocking(m) => {
+            w.write_byte(1);
+            w.write_string(m);
+        }
+        ExaError::Freezing(m) => {
+            w.write_byte(2);
+            w.write_string(m);
+        }
+        ExaError::Fatal(m) => {
+            w.write_byte(3);
+            w.write_string(m);
+        }
+        ExaError::NoMessages => w.write_byte(4),
+        ExaError::BusBandwidthExceeded => w.write_byte(5),
+        ExaError::LinkBandwidthExceeded => w.write_byte(6),
+        ExaError::HostFull => w.write_byte(7),
+        ExaError::AwaitingReader => w.write_byte(8),
+        ExaError::Waiting => w.write_byte(9),
+        ExaError::OutOfInstructions => w.write_byte(10),
+        ExaError::Halted => w.write_byte(11),
+        ExaError::Killed(by) => {
+            w.write_byte(12);
+            w.write_string(by);
+        }
+        ExaError::UnknownLabel(label) => {
+            w.write_byte(13);
+            w.write_string(label);
+        }
+        ExaError::InvalidLinkId(id) => {
+            w.write_byte(14);
+            w.write_int(*id);
+        }
+        ExaError::DivideByZero => w.write_byte(15),
+        ExaError::NumberRequired => w.write_byte(16),
+        ExaError::WriteToLiteral => w.write_byte(17),
+        ExaError::ReadOnlyRegister => w.write_byte(18),
+        ExaError::WriteOnlyRegister => w.write_byte(19),
+        ExaError::DeactivatedRegister => w.write_byte(20),
+        ExaError::UnknownRegister(register) => {
+            w.write_byte(21);
+            w.write_string(register);
+        }
+        ExaError::NoFileHeld => w.write_byte(22),
+        ExaError::FileAlreadyHeld => w.write_byte(23),
+        ExaError::FileNotFound(id) => {
+            w.write_byte(24);
+            w.write_int(*id);
+        }
+        ExaError::EndOfFile => w.write_byte(25),
+        ExaError::InvalidRandRange => w.write_byte(26),
+        ExaError::Hardware(m) => {
+            w.write_byte(27);
+            w.write_string(m);
+        }
+    }
+
+    w.write_string(&fault.exa);
+    w.write_len(fault.pc);
+    match fault.line {
+        None => w.write_bool(false),
+        Some(line) => {
+            w.write_bool(true);
+            w.write_len(line);
+        }
+    }
+    w.write_uint(fault.cycle);
+}
+
+pub fn read_error(r: &mut StateReader) -> Result<Option<Fault>, Box<dyn Error>> {
+    let error = match r.read_byte()? {
+        0 => return Ok(None),
+        1 => ExaError::Blocking(r.read_string()?.into()),
+        2 => ExaError::Freezing(r.read_string()?.into()),
+        3 => ExaError::Fatal(r.read_string()?.into()),
+        4 => ExaError::NoMessages,
+        5 => ExaError::BusBandwidthExceeded,
+        6 => ExaError::LinkBandwidthExceeded,
+        7 => ExaError::HostFull,
+        8 => ExaError::AwaitingReader,
+        9 => ExaError::Waiting,
+        10 => ExaError::OutOfInstructions,
+        11 => ExaError::Halted,
+        12 => ExaError::Killed(r.read_string()?.into()),
+        13 => ExaError::UnknownLabel(r.read_string()?),
+        14 => ExaError::InvalidLinkId(r.read_int()?),
+        15 => ExaError::DivideByZero,
+        16 => ExaError::NumberRequired,
+        17 => ExaError::WriteToLiteral,
+        18 => ExaError::ReadOnlyRegister,
+        19 => ExaError::WriteOnlyRegister,
+        20 => ExaError::DeactivatedRegister,
+        21 => ExaError::UnknownRegister(r.read_string()?),
+        22 => ExaError::NoFileHeld,
+        23 => ExaError::FileAlreadyHeld,
+        24 => ExaError::FileNotFound(r.read_int()?),
+        25 => ExaError::EndOfFile,
+        26 => ExaError::InvalidRandRange,
+        27 => ExaError::Hardware(r.read_string()?),
+        _ => return Err("invalid error kind in save state".into()),
+    };
+
+    Ok(Some(Fault {
+        error,
+        exa: r.read_string()?.into(),
+        pc: r.read_len()?,
+        line: if r.read_bool()? {
+            Some(r.read_len()?)
+        } else {
+            None
+        },
+        cycle: r.read_uint()?,
+    }))
+}
+
+fn write_target(w: &mut StateWriter, target: &Target) {
+    match target {
+        Target::Literal(value) => {
+            w.write_byte(0);
+            w.write_int(*value);
+        }
+        Target::Register(specifier) => {
+            w.write_byte(1);
+            w.write_string(specifier);
+        }
+        Target::Keyword(keyword) => {
+            w.write_byte(2);
+            w.write_string(keyword);
+        }
+    }
+}
+
+fn read_target(r: &mut StateReader) -> Result<Target, Box<dyn Error>> {
+    match r.read_byte()? {
+        0 => Ok(Target::Literal(r.read_int()?)),
+        1 => Ok(Target::Register(r.read_string()?)),
+        2 => Ok(Target::Keyword(r.read_string()?)),
+        _ => Err("invalid target in save state".into()),
+    }
+}
+
+fn write_targets(w: &mut StateWriter, targets: &[&Target]) {
+    for t in targets.iter() {
+        write_target(w, t);
+    }
+}
+
+fn write_comparator(w: &mut StateWriter, comp: &Comparator) {
+    w.write_byte(match comp {
+        Comparator::Equal => 0,
+        Comparator::GreaterThan => 1,
+        Comparator::LessThan => 2,
+    });
+}
+
+fn read_comparator(r: &mut StateReader) -> Result<Comparator, Box<dyn Error>> {
+    match r.read_byte()? {
+        0 => Ok(Comparator::Equal),
+        1 => Ok(Comparator::GreaterThan),
+        2 => Ok(Comparator::LessThan),
+        _ => Err("invalid comparator in save state".into()),
+    }
+}
+
+pub fn write_instruction(w: &mut StateWriter, inst: &Instruction) {
+    match inst {
+        Instruction::Copy(a, b) => {
+            w.write_byte(0);
+            write_targets(w, &[a, b]);
+        }
+        Instruction::Addi(a, b, c) => {
+            w.write_byte(1);
+            write_targets(w, &[a, b, c]);
+        }
+        Instruction::Subi(a, b, c) => {
+            w.write_byte(2);
+            write_targets(w, &[a, b, c]);
+        }
+        Instruction::Muli(a, b, c) => {
+            w.write_byte(3);
+            write_targets(w, &[a, b, c]);
+        }
+        Instruction::Divi(a, b, c) => {
+            w.write_byte(4);
+            write_targets(w, &[a, b, c]);
+        }
+        Instruction::Modi(a, b, c) => {
+            w.write_byte(5);
+            write_targets(w, &[a, b, c]);
+        }
+        Instruction::Swiz(a, b, c) => {
+            w.write_byte(6);
+            write_targets(w, &[a, b, c]);
+        }
+        Instruction::Mark(label) => {
+            w.write_byte(7);
+            w.write_string(label);
+        }
+        Instruction::Jump(label) => {
+            w.write_byte(8);
+            w.write_string(label);
+        }
+        Instruction::Tjmp(label) => {
+            w.write_byte(9);
+            w.write_string(label);
+        }
+        Instruction::Fjmp(label) => {
+            w.write_byte(10);
+            w.write_string(label);
+        }
+        Instruction::Test(a, comp, b) => {
+            w.write_byte(11);
+            write_target(w, a);
+            write_comparator(w, comp);
+            write_target(w, b);
+        }
+        Instruction::Repl(label) => {
+            w.write_byte(12);
+            w.write_string(label);
+        }
+        Instruction::Halt => w.write_byte(13),
+        Instruction::Kill => w.write_byte(14),
+        Instruction::Link(a) => {
+            w.write_byte(15);
+            write_target(w, a);
+        }
+        Instruction::Host(a) => {
+            w.write_byte(16);
+            write_target(w, a);
+        }
+        Instruction::Mode => w.write_byte(17),
+        Instruction::VoidM => w.write_byte(18),
+        Instruction::TestMrd => w.write_byte(19),
+        Instruction::Make => w.write_byte(20),
+        Instruction::Grab(a) => {
+            w.write_byte(21);
+            write_target(w, a);
+        }
+        Instruction::File(a) => {
+            w.write_byte(22);
+            write_target(w, a);
+        }
+        Instruction::Seek(a) => {
+            w.write_byte(23);
+            write_target(w, a);
+        }
+        Instruction::VoidF => w.write_byte(24),
+        Instruction::Drop => w.write_byte(25),
+        Instruction::Wipe => w.write_byte(26),
+        Instruction::TestEof => w.write_byte(27),
+        Instruction::Noop => w.write_byte(28),
+        Instruction::Rand(a, b, c) => {
+            w.write_byte(29);
+            write_targets(w, &[a, b, c]);
+        }
+        Instruction::Wait => w.write_byte(30),
+        Instruction::Data(values) => {
+            w.write_byte(31);
+            w.write_len(values.len());
+            for v in values.iter() {
+                write_value(w, v);
+            }
+        }
+    }
+}
+
+pub fn read_instruction(r: &mut StateReader) -> Result<Instruction, Box<dyn Error>> {
+    let inst = match r.read_byte()? {
+        0 => Instruction::Copy(read_target(r)?, read_target(r)?),
+        1 => Instruction::Addi(read_target(r)?, read_target(r)?, read_target(r)?),
+        2 => Instruction::Subi(read_target(r)?, read_target(r)?, read_target(r)?),
+        3 => Instruction::Muli(read_target(r)?, read_target(r)?, read_target(r)?),
+        4 => Instruction::Divi(read_target(r)?, read_target(r)?, read_target(r)?),
+        5 => Instruction::Modi(read_target(r)?, read_target(r)?, read_target(r)?),
+        6 => Instruction::Swiz(read_target(r)?, read_target(r)?, read_target(r)?),
+        7 => Instruction::Mark(r.read_string()?),
+        8 => Instruction::Jump(r.read_string()?),
+        9 => Instruction::Tjmp(r.read_string()?),
+        10 => Instruction::Fjmp(r.read_string()?),
+        11 => Instruction::Test(read_target(r)?, read_comparator(r)?, read_target(r)?),
+        12 => Instruction::Repl(r.read_string()?),
+        13 => Instruction::Halt,
+        14 => Instruction::Kill,
+        15 => Instruction::Link(read_target(r)?),
+        16 => Instruction::Host(read_target(r)?),
+        17 => Instruction::Mode,
+        18 => Instruction::VoidM,
+        19 => Instruction::TestMrd,
+        20 => Instruction::Make,
+        21 => Instruction::Grab(read_target(r)?),
+        22 => Instruction::File(read_target(r)?),
+        23 => Instruction::Seek(read_target(r)?),
+        24 => Instruction::VoidF,
+        25 => Instruction::Drop,
+        26 => Instruction::Wipe,
+        27 => Instruction::TestEof,
+        28 => Instruction::Noop,
+        29 => Instruction::Rand(read_target(r)?, read_target(r)?, read_target(r)?),
+        30 => Instruction::Wait,
+        31 => {
+            let length = r.read_len()?;
+            let mut values = vec![];
+            for _ in 0..length {
+                values.push(read_value(r)?);
+            }
+            Instruction::Data(values)
+        }
+        _ => return Err("invalid instruction in save state".into()),
+    };
+    Ok(inst)
+}
+
+impl VM {
+    /// Serialize the entire VM into a versioned binary blob that can
+    /// be handed back to load_state later. Derived state, such as the
+    /// framebuffer and audio buffer, is not included since it is
+    /// regenerated every frame.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        for b in STATE_MAGIC.iter() {
+            w.write_byte(*b);
+        }
+        w.write_uint(STATE_VERSION);
+
+        w.write_uint(self.cycle);
+        w.write_uint(self.links_traversed);
+        w.write_uint(self.kills);
+        w.write_int(self.file_counter);
+        w.write_long(self.seed);
+        w.write_long(self.rng.get_seed());
+        self.bus.save_state(&mut w);
+
+        // Hosts are written in two passes, since links can't be
+        // restored until every host they might point to exists.
+        let hosts: Vec<_> = self.hosts.values().sorted().collect();
+        w.write_len(hosts.len());
+        for host in hosts.iter() {
+            w.write_string(&host.name);
+            w.write_len(host.capacity);
+            w.write_len(host.occupied);
+
+            w.write_len(host.registers.len());
+            for (name, r) in host.registers.iter().sorted_by_key(|(n, _)| *n) {
+                w.write_string(name);
+                write_register(&mut w, &Register::new(r.permissions(), r.peek()));
+            }
+
+            host.bus.save_state(&mut w);
+
+            w.write_len(host.files.len());
+            for file in host.files.iter() {
+                write_file(&mut w, file);
+            }
+        }
+
+        for host in hosts.iter() {
+            w.write_len(host.links.len());
+            for (link_id, link) in host.links.iter().sorted_by_key(|(id, _)| *id) {
+                w.write_int(*link_id);
+                w.write_string(&self.hosts[link.to_host].name);
+                w.write_bool(link.traversed_this_cycle);
+            }
+        }
+
+        w.write_len(self.exas.len());
+        for exa in self.exas.iter() {
+            exa.save_state(self, &mut w);
+        }
+
+        // A debugger may have stopped partway through a cycle
+        w.write_bool(self.mid_cycle);
+        w.write_len(self.run_queue.len());
+        for i in self.run_queue.iter() {
+            w.write_string(&self.exas[*i].name);
+        }
+
+        match &self.redshift {
+            None => w.write_bool(false),
+            Some(r) => {
+                w.write_bool(true);
+                w.write_string(&r.game_name);
+                r.sqr0_wave.save_state(&mut w);
+                r.sqr1_wave.save_state(&mut w);
+                r.tri0_wave.save_state(&mut w);
+                r.nse0_wave.save_state(&mut w);
+            }
+        }
+
+        w.into_inner()
+    }
+
+    /// Rebuild a VM from a blob produced by save_state. Trailing bytes
+    /// are ignored, so frontends are free to pad the buffer.
+    pub fn load_state(data: &[u8]) -> Result<VM, Box<dyn Error>> {
+        let mut r = StateReader::new(data);
+
+        let mut magic = [0; 4];
+        for m in magic.iter_mut() {
+            *m = r.read_byte()?;
+        }
+        if &magic != STATE_MAGIC {
+            return Err("not an exa save state".into());
+        }
+        let version = r.read_uint()?;
+        if version != STATE_VERSION {
+            return Err(format!("unsupported save state version {}", version).into());
+        }
+
+        let cycle = r.read_uint()?;
+        let links_traversed = r.read_uint()?;
+        let kills = r.read_uint()?;
+        let file_counter = r.read_int()?;
+        let mut vm = VM::with_seed(r.read_long()?);
+        vm.rng.seed(r.read_long()?);
+        vm.cycle = cycle;
+        vm.links_traversed = links_traversed;
+        vm.kills = kills;
+        vm.file_counter = file_counter;
+        vm.bus = MessageBus::load_state(&mut r)?;
+
+        // Custom hardware registers can't be rebuilt from a save state,
+        // so every register comes back as a plain Register.
+        let mut hosts = vec![];
+        for _ in 0..r.read_len()? {
+            let name = r.read_string()?;
+            let mut host = Host::new(name, r.read_len()?);
+            host.occupied = r.read_len()?;
+
+            for _ in 0..r.read_len()? {
+                let name = r.read_string()?;
+                let register = read_register(&mut r)?;
+                host.registers.insert(name, Box::new(register));
+            }
+
+            host.bus = MessageBus::load_state(&mut r)?;
+
+            for _ in 0..r.read_len()? {
+                host.files.push(read_file(&mut r)?);
+            }
+
+            hosts.push(vm.add_host(host));
+        }
+
+        for h in hosts.iter() {
+            for _ in 0..r.read_len()? {
+                let link_id = r.read_int()?;
+                let to_host = match vm.hosts.id(&r.read_string()?) {
+                    Some(to_host) => to_host,
+                    None => return Err("link to unknown host in save state".into()),
+                };
+                let traversed_this_cycle = r.read_bool()?;
+                vm.hosts[*h].links.insert(
+                    link_id,
+                    HostLink {
+                        to_host,
+                        traversed_this_cycle,
+                    },
+                );
+            }
+        }
+
+        // EXAs in the same REPL lineage share one spawn counter
+        let mut spawn_counters: HashMap<String, Arc<AtomicU32>> = HashMap::new();
+        let mut programs = HashMap::new();
+        for _ in 0..r.read_len()? {
+            let exa = Exa::load_state(&mut r, &vm, &mut spawn_counters, &mut programs)?;
+            vm.register_exa(exa);
+        }
+
+        vm.mid_cycle = r.read_bool()?;
+        for _ in 0..r.read_len()? {
+            let name = r.read_string()?;
+            match vm.exas.iter().position(|e| *e.name == *name) {
+                Some(i) => vm.run_queue.push_back(i),
+                None => return Err("unknown exa in save state".into()),
+            }
+        }
+
+        if r.read_bool()? {
+            let game_name = r.read_string()?;
+            let mut registers = REDSHIFT_REGISTERS.iter();
+            if !registers.all(|(h, n)| vm.hosts.get(h).is_some_and(|h| h.registers.contains_key(n)))
+            {
+                return Err("missing redshift register in save state".into());
+            }
+
+            let mut sqr0_wave = SquareWave::default();
+            sqr0_wave.load_state(&mut r)?;
+            let mut sqr1_wave = SquareWave::default();
+            sqr1_wave.load_state(&mut r)?;
+            let mut tri0_wave = TriangleWave::default();
+            tri0_wave.load_state(&mut r)?;
+            let mut nse0_wave = Noise::with_seed(vm.seed);
+            nse0_wave.load_state(&mut r)?;
+
+            vm.redshift = Some(RedshiftEnvironment {
+                game_name,
+                sqr0_wave,
+                sqr1_wave,
+                tri0_wave,
+                nse0_wave,
+            });
+        }
+
+        Ok(vm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primitives() {
+        let mut w = StateWriter::new();
+        w.write_bool(true);
+        w.write_byte(7);
+        w.write_int(-9999);
+        w.write_uint(123456);
+        w.write_long(u64::MAX - 1);
+        w.write_float(261.63);
+        w.write_string("#PADX");
+
+        let data = w.into_inner();
+        let mut r = StateReader::new(&data);
+        assert!(r.read_bool().unwrap());
+        assert_eq!(r.read_byte().unwrap(), 7);
+        assert_eq!(r.read_int().unwrap(), -9999);
+        assert_eq!(r.read_uint().unwrap(), 123456);
+        assert_eq!(r.read_long().unwrap(), u64::MAX - 1);
+        assert_eq!(r.read_float().unwrap(), 261.63);
+        assert_eq!(r.read_string().unwrap(), "#PADX");
+        assert!(r.read_byte().is_err());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut w = StateWriter::new();
+        w.write_string("truncated");
+        let data = w.into_inner();
+
+        let mut r = StateReader::new(&data[..6]);
+        assert!(r.read_string().is_err());
+    }
+
+    #[test]
+    fn test_instructions() {
+        let insts = [
+            Instruction::Copy(Target::Literal(1), Target::Register("x".into())),
+            Instruction::Test(
+                Target::Register("#padx".into()),
+                Comparator::LessThan,
+                Target::Literal(-1),
+            ),
+            Instruction::Jump("loop".into()),
+            Instruction::Rand(
+                Target::Literal(0),
+                Target::Literal(15),
+                Target::Register("gx".into()),
+            ),
+            Instruction::Data(vec![
+                Value::Number(1),
+                Value::Keyword("EOF".into()),
+                Value::Number(3),
+            ]),
+            Instruction::Host(Target::Register("x".into())),
+            Instruction::Copy(Target::Keyword("NEXT".into()), Target::Register("m".into())),
+            Instruction::TestMrd,
+            Instruction::Wait,
+        ];
+
+        let mut w = StateWriter::new();
+        for inst in insts.iter() {
+            write_instruction(&mut w, inst);
+        }
+        let data = w.into_inner();
+        let mut r = StateReader::new(&data);
+        for inst in insts.iter() {
+            assert_eq!(read_instruction(&mut r).unwrap(), *inst);
+        }
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        assert!(VM::load_state(b"NOPE\x01\x00\x00\x00").is_err());
+        assert!(VM::load_state(&[]).is_err());
+    }
+
+    #[test]
+    fn test_bad_version() {
+        let mut data = VM::new().save_state();
+        data[4] = 99;
+        assert!(VM::load_state(&data).is_err());
+    }
+}
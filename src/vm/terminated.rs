@@ -0,0 +1,145 @@
+//! A bounded history of EXAs that have died, so a cart or debugger can
+//! find out what happened to an EXA after it's gone from the VM.
+//!
+//! The history is not part of save states.
+
+use std::collections::VecDeque;
+
+use super::error::ExaError;
+use super::exa::Exa;
+use super::value::Value;
+use super::{Hosts, VM};
+
+/// How many terminated EXAs are remembered unless told otherwise.
+pub const DEFAULT_TERMINATED_CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cause {
+    Halted,
+    OutOfInstructions,
+    /// Killed by the named EXA.
+    Killed {
+        by: String,
+    },
+    /// Any other fatal error.
+    Fault(ExaError),
+}
+
+impl Cause {
+    fn from_error(error: &ExaError) -> Cause {
+        match error {
+            ExaError::Halted => Cause::Halted,
+            ExaError::OutOfInstructions => Cause::OutOfInstructions,
+            ExaError::Killed(by) => Cause::Killed { by: by.to_string() },
+            e => Cause::Fault(e.clone()),
+        }
+    }
+}
+
+/// What an EXA looked like when it died.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Terminated {
+    pub name: String,
+    /// Name of the EXA this one was REPLed from, or its own name if it
+    /// wasn't.
+    pub base_name: String,
+    /// 0 for the original EXA, then 1, 2... for each REPL in its lineage.
+    pub spawn_id: u32,
+    /// Host the EXA died in.
+    pub host: String,
+    /// Cycle the EXA died on.
+    pub cycle: u32,
+    pub pc: usize,
+    /// Source line of the instruction at pc, if known.
+    pub line: Option<usize>,
+    pub cause: Cause,
+    /// Every register the EXA could access, in X, T, GX, GY, GZ, GP, CI,
+    /// CO order.
+    pub registers: Vec<(String, Value)>,
+}
+
+impl Terminated {
+    /// Build the entry for an EXA with a fatal error. None if it's
+    /// still alive.
+    pub(crate) fn from_exa(exa: &Exa, hosts: &Hosts) -> Option<Terminated> {
+        let fault = exa.error.as_ref().filter(|_| exa.is_fatal())?;
+        Some(Terminated {
+            name: exa.name.to_string(),
+            base_name: exa.lineage().to_string(),
+            spawn_id: exa.spawn_id(),
+            host: hosts[exa.host].name.clone(),
+            cycle: fault.cycle,
+            pc: fault.pc,
+            line: fault.line,
+            cause: Cause::from_error(&fault.error),
+            registers: exa.register_values(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct History {
+    capacity: usize,
+    // Oldest first
+    entries: VecDeque<Terminated>,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History {
+            capacity: DEFAULT_TERMINATED_CAPACITY,
+            entries: VecDeque::new(),
+        }
+    }
+}
+
+impl History {
+    fn trim(&mut self) {
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub(crate) fn push(&mut self, entry: Terminated) {
+        self.entries.push_back(entry);
+        self.trim();
+    }
+
+    /// Forget everything that died on or after cycle.
+    pub(crate) fn truncate(&mut self, cycle: u32) {
+        self.entries.retain(|t| t.cycle < cycle);
+    }
+}
+
+impl VM {
+    /// Every remembered EXA that has died, oldest death first.
+    pub fn terminated(&self) -> impl Iterator<Item = &Terminated> {
+        self.terminated.entries.iter()
+    }
+
+    /// The most recent death of an EXA with this name, if it's still
+    /// remembered. Names get reused, so there may be older ones too.
+    pub fn find_terminated(&self, name: &str) -> Option<&Terminated> {
+        self.terminated
+            .entries
+            .iter()
+            .rev()
+            .find(|t| t.name == name)
+    }
+
+    /// Remember at most capacity terminated EXAs, forgetting the oldest
+    /// first. A capacity of 0 turns the history off, so EXAs can die
+    /// without the VM allocating a record of it.
+    pub fn set_terminated_capacity(&mut self, capacity: usize) {
+        self.terminated.capacity = capacity;
+        self.terminated.trim();
+    }
+
+    pub fn clear_terminated(&mut self) {
+        self.terminated.entries.clear();
+    }
+}
@@ -0,0 +1,202 @@
+//! Opt-in execution tracing. Once a TraceSink is set on the VM, every
+//! meaningful action an EXA takes is recorded as a TraceEvent, which can
+//! be kept in memory or written out as JSON Lines for diffing runs.
+//!
+//! Sinks are not part of save states.
+
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::Serialize;
+
+use super::instruction::Instruction;
+use super::value::Value;
+
+/// Which M bus a message went over.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Bus {
+    Global,
+    /// The local bus of the named host.
+    Host(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The EXA is running the instruction at pc. Emitted every time it
+    /// tries, so a blocked instruction shows up once per cycle.
+    Instruction {
+        instruction: Instruction,
+    },
+    Blocked {
+        reason: String,
+    },
+    Frozen {
+        reason: String,
+    },
+    Fatal {
+        reason: String,
+    },
+    Send {
+        bus: Bus,
+        value: Value,
+    },
+    Receive {
+        bus: Bus,
+        sender: String,
+        value: Value,
+    },
+    Link {
+        link: i32,
+        from: String,
+        to: String,
+    },
+    Repl {
+        child: String,
+    },
+    /// None if there was nobody else in the host to kill.
+    Kill {
+        target: Option<String>,
+    },
+    Grab {
+        file: i32,
+    },
+    Drop {
+        file: i32,
+    },
+    Wipe {
+        file: i32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TraceEvent {
+    pub cycle: u32,
+    pub exa: String,
+    pub pc: usize,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl TraceEvent {
+    /// One line of JSON, without the trailing newline.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("trace events always serialize")
+    }
+}
+
+pub trait TraceSink: Debug + Send {
+    fn record(&mut self, event: TraceEvent);
+}
+
+/// Keeps every event in memory. Clones share the same events, so hand
+/// one to the VM and keep the other to look at them.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTrace {
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+impl MemoryTrace {
+    pub fn new() -> MemoryTrace {
+        MemoryTrace::default()
+    }
+
+    pub fn events(&self) -> MutexGuard<'_, Vec<TraceEvent>> {
+        self.events.lock().unwrap()
+    }
+
+    pub fn take(&self) -> Vec<TraceEvent> {
+        std::mem::take(&mut *self.events())
+    }
+}
+
+impl TraceSink for MemoryTrace {
+    fn record(&mut self, event: TraceEvent) {
+        self.events().push(event);
+    }
+}
+
+/// Writes each event as a line of JSON. The VM has nowhere to report I/O
+/// errors mid-cycle, so the first one is kept for the caller to check
+/// and later events are dropped.
+#[derive(Debug)]
+pub struct JsonLines<W: Write + Debug> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write + Debug> JsonLines<W> {
+    pub fn new(writer: W) -> JsonLines<W> {
+        JsonLines {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Debug + Send> TraceSink for JsonLines<W> {
+    fn record(&mut self, event: TraceEvent) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.writer, "{}", event.to_json()) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+/// Write already collected events as JSON Lines.
+pub fn write_json_lines<W: Write>(events: &[TraceEvent], mut writer: W) -> io::Result<()> {
+    for event in events {
+        writeln!(writer, "{}", event.to_json())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::instruction::Target;
+
+    #[test]
+    fn json_line_format() {
+        let event = TraceEvent {
+            cycle: 3,
+            exa: String::from("XA"),
+            pc: 1,
+            event: Event::Instruction {
+                instruction: Instruction::Copy(
+                    Target::Literal(5),
+                    Target::Register(String::from("x")),
+                ),
+            },
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"cycle":3,"exa":"XA","pc":1,"event":"instruction","instruction":{"copy":[{"literal":5},{"register":"x"}]}}"#
+        );
+
+        let event = TraceEvent {
+            cycle: 0,
+            exa: String::from("XB"),
+            pc: 0,
+            event: Event::Send {
+                bus: Bus::Host(String::from("start")),
+                value: Value::Keyword(String::from("HI")),
+            },
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"cycle":0,"exa":"XB","pc":0,"event":"send","bus":{"host":"start"},"value":"HI"}"#
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Anything an EXA can hold in a register, write to a file or send
+/// over M. Keywords are written as 'KEYWORD' in scripts and are always
+/// stored uppercase.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Value {
+    Number(i32),
+    Keyword(String),
+}
+
+impl Value {
+    pub fn keyword(keyword: &str) -> Value {
+        Value::Keyword(keyword.to_ascii_uppercase())
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        matches!(self, Value::Keyword(_))
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Number(value)
+    }
+}
+
+/// Numbers compare numerically and keywords compare alphabetically.
+/// A number and a keyword can't be compared, so every TEST between
+/// them (including =) comes out false.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(l), Value::Number(r)) => l.partial_cmp(r),
+            (Value::Keyword(l), Value::Keyword(r)) => l.partial_cmp(r),
+            _ => None,
+        }
+    }
+}
+
+/// Network descriptions write numbers as integers and keywords as strings.
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(i32),
+            Keyword(String),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Number(n) => Value::Number(n),
+            Raw::Keyword(k) => Value::keyword(&k),
+        })
+    }
+}
+
+/// The reverse of Deserialize, so values round trip.
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Number(n) => serializer.serialize_i32(*n),
+            Value::Keyword(k) => serializer.serialize_str(k),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Keyword(k) => write!(f, "'{}'", k),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let (one, two) = (Value::Number(1), Value::Number(2));
+        let (apple, pear) = (Value::keyword("apple"), Value::keyword("PEAR"));
+
+        assert!(one < two);
+        assert!(apple < pear);
+        assert_eq!(apple, Value::Keyword("APPLE".into()));
+
+        assert!(one != apple);
+        assert_eq!(one.partial_cmp(&apple), None);
+        assert_eq!(apple.partial_cmp(&one), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Value::Number(-12).to_string(), "-12");
+        assert_eq!(Value::keyword("core").to_string(), "'CORE'");
+    }
+}
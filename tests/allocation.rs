@@ -0,0 +1,132 @@
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use common::*;
+use exa::vm::exa::Mode;
+use exa::vm::Host;
+
+// Counts allocations made by the current thread, so tests running in
+// parallel don't count each other's.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Warm up so every buffer has grown to size, then count what the next
+// cycles allocate. Skips the bench's run_cycle, which prints.
+fn assert_steady_state_allocation_free(bench: &mut TestBench) {
+    let mut vm = bench.vm();
+    for _ in 0..20 {
+        vm.run_cycle();
+    }
+
+    let before = ALLOCATIONS.with(|a| a.get());
+    for _ in 0..100 {
+        vm.run_cycle();
+        if vm.redshift.is_some() {
+            vm.render();
+        }
+    }
+    let allocations = ALLOCATIONS.with(|a| a.get()) - before;
+    assert_eq!(allocations, 0, "allocated during steady state cycles");
+
+    // Nobody died along the way, which would make this a lot easier
+    assert!(vm.exas.iter().all(|e| !e.is_fatal()));
+}
+
+#[test]
+fn allocation_free_arithmetic() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa(
+        "mark a\n copy 5 x\n addi x 1 x\n muli x 3 t\n swiz t 4321 x\n test x > 100\n tjmp a\n modi x 7 x\n subi x 1 x\n divi 10 2 t\n rand 1 10 t\n copy x #reg\n jump a\n",
+    );
+    assert_steady_state_allocation_free(&mut bench);
+}
+
+#[test]
+fn allocation_free_messages() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("mark a\n copy x m\n addi x 1 x\n jump a\n");
+    let _ = bench.exa("mark a\n copy m t\n test mrd\n jump a\n");
+    let _ = bench.exa("mode\n mark a\n copy 1 m\n jump a\n");
+    let _ = bench.exa("mode\n mark a\n void m\n jump a\n");
+    assert_steady_state_allocation_free(&mut bench);
+}
+
+#[test]
+fn allocation_free_links_and_files() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("mark a\n link 800\n link -1\n jump a\n");
+    let _ = bench.exa("make\n mark a\n copy 1 f\n seek -9999\n copy f x\n seek -9999\n jump a\n");
+    assert_steady_state_allocation_free(&mut bench);
+}
+
+#[test]
+fn allocation_free_kill_without_target() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("link 800\n mark a\n kill\n noop\n jump a\n");
+    let _ = bench.exa("noop\n mark a\n kill\n jump a\n");
+    assert_steady_state_allocation_free(&mut bench);
+}
+
+#[test]
+fn allocation_free_kill() {
+    let mut bench = TestBench::basic_vm();
+    bench.vm().add_host(Host::new(String::from("arena"), 100));
+    // Remembering the dead isn't free
+    bench.vm().set_terminated_capacity(0);
+    // One kill every other cycle, with enough victims to go around
+    let _ = bench.exa_custom("mark a\n kill\n jump a\n", "arena", Mode::Global);
+    for _ in 0..64 {
+        let _ = bench.exa_custom("mark a\n noop\n jump a\n", "arena", Mode::Global);
+    }
+    assert_steady_state_allocation_free(&mut bench);
+    bench.assert_activity(0, 60);
+}
+
+#[test]
+fn allocation_free_sprites() {
+    let mut bench = TestBench::redshift_vm();
+    for i in 0..8 {
+        let script = format!(
+            "copy {} co\n mark a\n copy 3{:02} gp\n rand 0 20 gx\n rand 0 20 gy\n copy 1{}{} gp\n addi ci 0 x\n jump a\n",
+            i,
+            i + 1,
+            i,
+            i
+        );
+        let _ = bench.exa(&script);
+    }
+    assert_steady_state_allocation_free(&mut bench);
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use exa::batch::{Batch, Run, Source, Stop};
+use exa::vm::redshift::RedshiftButton;
+use exa::vm::rewind::Input;
+use exa::vm::terminated::Cause;
+use exa::vm::value::Value;
+use exa::vm::VM;
+
+fn network_batch() -> Batch {
+    Batch::new(Source::from_path("./tests/network.toml").unwrap()).expect("failed to load batch")
+}
+
+fn image_batch() -> Batch {
+    Batch::new(Source::from_path("./tests/golden.png").unwrap()).expect("failed to load batch")
+}
+
+#[test]
+fn batch_repeatable() {
+    let runs: Vec<Run> = (0..8)
+        .map(|seed| Run::new(seed, Stop::Cycles(20)))
+        .collect();
+    let mut batch = image_batch();
+    batch.threads = 1;
+    let one = batch.run(&runs).unwrap();
+    batch.threads = 4;
+    let four = batch.run(&runs).unwrap();
+
+    assert_eq!(one, four);
+    for (i, result) in one.iter().enumerate() {
+        assert_eq!(
+            (result.index, result.seed, result.cycles),
+            (i, i as u64, 20)
+        );
+        assert!(result.finished);
+        assert!(result.framebuffer_hash.is_some());
+    }
+}
+
+#[test]
+fn batch_until_dead() {
+    let runs = vec![Run::new(0, Stop::AllDead { max_cycles: 100 })];
+    let result = &network_batch().run(&runs).unwrap()[0];
+
+    assert!(result.finished);
+    assert!(result.cycles < 100);
+    assert_eq!(result.framebuffer_hash, None);
+    let deaths: Vec<(&str, &Cause)> = result
+        .terminated
+        .iter()
+        .map(|t| (t.name.as_str(), &t.cause))
+        .collect();
+    assert_eq!(
+        deaths,
+        vec![
+            ("XB", &Cause::OutOfInstructions),
+            ("XA", &Cause::OutOfInstructions)
+        ]
+    );
+}
+
+#[test]
+fn batch_until_predicate() {
+    let until = Arc::new(|vm: &VM| vm.hosts["outbox"].registers["#out"].peek() != Value::Number(0));
+    let runs = vec![
+        Run::new(
+            0,
+            Stop::Predicate {
+                max_cycles: 100,
+                until: until.clone(),
+            },
+        ),
+        Run::new(
+            0,
+            Stop::Predicate {
+                max_cycles: 2,
+                until,
+            },
+        ),
+    ];
+    let results = network_batch().run(&runs).unwrap();
+
+    assert!(results[0].finished);
+    let vm = VM::load_state(&results[0].state).unwrap();
+    assert_eq!(
+        vm.hosts["outbox"].registers["#out"].peek(),
+        Value::Number(1)
+    );
+    assert!(!results[1].finished);
+    assert_eq!(results[1].cycles, 2);
+}
+
+#[test]
+fn batch_inputs() {
+    let mut run = Run::new(0, Stop::Cycles(10));
+    run.inputs = vec![
+        (5, Input::Button(RedshiftButton::X)),
+        (0, Input::Button(RedshiftButton::Right)),
+        (5, Input::Button(RedshiftButton::Z)),
+    ];
+    let results = image_batch().run(&[run]).unwrap();
+
+    let vm = VM::load_state(&results[0].state).unwrap();
+    assert_eq!(
+        vm.hosts["input"].registers["#padx"].peek(),
+        Value::Number(1)
+    );
+    assert_eq!(
+        vm.hosts["input"].registers["#padb"].peek(),
+        Value::Number(101)
+    );
+    assert_eq!(results[0].cycles, 10);
+}
+
+#[test]
+fn batch_errors() {
+    let mut run = Run::new(0, Stop::Cycles(10));
+    run.inputs = vec![(0, Input::Button(RedshiftButton::Start))];
+    let err = network_batch().run(&[run]).unwrap_err();
+    assert_eq!(err.to_string(), "button inputs need a Redshift image");
+
+    assert!(Source::from_path("./tests/missing.toml").is_err());
+    assert!(Batch::new(Source::Image("./tests/missing.png".into())).is_err());
+}
@@ -38,6 +38,16 @@ fn test_collision_and_reset() {
     bench.assert_exa_register(&e2, "ci", -9999);
 }
 
+#[test]
+fn test_collision_read_by_modi() {
+    let mut bench = TestBench::redshift_vm();
+    let _ = bench.exa("copy 1 co\n copy 100 gp\n noop\n noop\n");
+    let e2 = bench.exa("copy 2 co\n copy 100 gp\n modi ci 7 x\n noop\n");
+
+    bench.run_cycles(3);
+    bench.assert_exa_register(&e2, "x", 1);
+}
+
 #[test]
 fn test_out_of_bounds_collision() {
     let mut bench = TestBench::redshift_vm();
@@ -61,3 +71,17 @@ fn test_out_of_bounds_collision() {
     bench.assert_exa_register(&e1, "ci", -9999);
     bench.assert_exa_register(&e2, "ci", -9999);
 }
+
+#[test]
+fn test_corner_collision() {
+    let mut bench = TestBench::redshift_vm();
+    let e1 = bench.exa("copy 1 co\n noop\n noop\n copy 199 gp\n copy ci x\n noop\n");
+    let e2 = bench.exa("copy 9 gx\n copy 9 gy\n copy 2 co\n copy 100 gp\n copy ci x\n noop\n");
+    let e3 = bench.exa("copy 10 gx\n copy 9 gy\n copy 3 co\n copy 100 gp\n copy ci x\n noop\n");
+
+    bench.run_cycles(5);
+    // only the bottom right pixel of e1 and top left of e2 overlap
+    bench.assert_exa_register(&e1, "x", 2);
+    bench.assert_exa_register(&e2, "x", 1);
+    bench.assert_exa_register(&e3, "x", -9999);
+}
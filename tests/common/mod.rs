@@ -1,118 +1,196 @@
 extern crate exa;
 
-use std::cell::RefCell;
+use std::cell::{Ref, RefCell, RefMut};
+use std::error::Error;
 use std::fmt;
 use std::rc::Rc;
 
 use exa::image::load_image;
-use exa::vm::error::ExaError;
+use exa::network::load_network;
+use exa::vm::error::{ExaError, Severity};
 use exa::vm::exa::sprite::Sprite;
 use exa::vm::exa::{Exa, Mode};
-use exa::vm::register::Register;
-use exa::vm::{Host, Permissions, Shared, VM};
-
-pub struct TestBench<'a> {
-    vm: Shared<VM<'a>>,
+use exa::vm::redshift::RedshiftButton;
+use exa::vm::register::{HardwareRegister, Register};
+use exa::vm::scheduler::{Shuffle, SpawnOrder};
+use exa::vm::value::Value;
+use exa::vm::{Host, Permissions, VM};
+
+pub struct TestBench {
+    vm: Rc<RefCell<VM>>,
     spawned: usize,
     redshift: bool,
+    // Schedulers aren't saved, so clones need to be told
+    spawn_order: bool,
 }
 
-impl fmt::Display for TestBench<'_> {
+impl fmt::Display for TestBench {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.vm.borrow())
     }
 }
 
+/// A handle on an EXA in a TestBench's VM. The VM owns its EXAs, so the
+/// handle looks its EXA up by name each time it's borrowed.
+pub struct ExaRef {
+    vm: Rc<RefCell<VM>>,
+    name: String,
+}
+
+#[allow(dead_code)]
+impl ExaRef {
+    pub fn is_alive(&self) -> bool {
+        self.vm.borrow().exas.iter().any(|e| *e.name == self.name)
+    }
+
+    pub fn borrow(&self) -> Ref<'_, Exa> {
+        Ref::map(self.vm.borrow(), |vm| vm.get_exa(&self.name))
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, Exa> {
+        RefMut::map(self.vm.borrow_mut(), |vm| vm.get_exa_mut(&self.name))
+    }
+}
+
 #[allow(dead_code)]
-impl<'a> TestBench<'a> {
+impl TestBench {
     /// basic_vm provides a VM with two hosts, each with capacity 6.
     /// Host "start" is linked to host "end" via a 800<->-1 link. A
     /// ReadWrite register #REG exists in "one" and is initialized to 100.
-    pub fn basic_vm() -> TestBench<'a> {
-        let h1 = Host::new_shared(String::from("start"), 6);
+    pub fn basic_vm() -> TestBench {
+        let mut h1 = Host::new(String::from("start"), 6);
         let r = Register::new(Permissions::ReadWrite, 100);
-        h1.borrow_mut()
-            .add_register(String::from("#REG"), Rc::new(RefCell::new(r)));
+        h1.add_register(String::from("#REG"), r);
 
-        let h2 = Host::new_shared(String::from("end"), 4);
+        let h2 = Host::new(String::from("end"), 4);
 
         let mut vm = VM::new();
 
-        vm.add_host(h1.clone());
-        vm.add_host(h2.clone());
+        let h1 = vm.add_host(h1);
+        let h2 = vm.add_host(h2);
 
-        vm.add_link(800, h1.clone(), h2.clone());
-        vm.add_link(-1, h2.clone(), h1.clone());
+        vm.add_link(800, h1, h2);
+        vm.add_link(-1, h2, h1);
 
-        vm.randomize_exa_order = false;
+        vm.scheduler = Box::new(SpawnOrder);
 
         TestBench {
             vm: Rc::new(RefCell::new(vm)),
             spawned: 0,
             redshift: false,
+            spawn_order: true,
         }
     }
 
-    pub fn redshift_vm() -> TestBench<'a> {
+    pub fn redshift_vm() -> TestBench {
         let mut vm = VM::new_redshift();
-        vm.randomize_exa_order = false;
+        vm.scheduler = Box::new(SpawnOrder);
 
         TestBench {
             vm: Rc::new(RefCell::new(vm)),
             spawned: 0,
             redshift: true,
+            spawn_order: true,
         }
     }
 
-    pub fn redshift_vm_from_image(path: String) -> TestBench<'a> {
+    pub fn redshift_vm_from_image(path: String) -> TestBench {
         let vm = load_image(path).expect("failed to load image");
 
         TestBench {
             vm: Rc::new(RefCell::new(vm)),
             spawned: 0,
             redshift: true,
+            spawn_order: false,
+        }
+    }
+
+    pub fn network_vm(path: String) -> TestBench {
+        let vm = load_network(path).expect("failed to load network");
+
+        TestBench {
+            vm: Rc::new(RefCell::new(vm)),
+            spawned: 0,
+            redshift: false,
+            spawn_order: false,
+        }
+    }
+
+    /// Build a second bench from a save state of this one.
+    pub fn clone_via_state(&self) -> TestBench {
+        let data = self.vm.borrow().save_state();
+        let mut vm = VM::load_state(&data).expect("failed to load state");
+        if self.spawn_order {
+            vm.scheduler = Box::new(SpawnOrder);
+        }
+
+        TestBench {
+            vm: Rc::new(RefCell::new(vm)),
+            spawned: self.spawned,
+            redshift: self.redshift,
+            spawn_order: self.spawn_order,
         }
     }
 
+    /// Direct access to the VM, for APIs the bench doesn't wrap.
+    pub fn vm(&self) -> RefMut<'_, VM> {
+        self.vm.borrow_mut()
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.vm.borrow().save_state()
+    }
+
+    /// Restart the VM's randomness from seed.
+    pub fn reseed(&mut self, seed: u64) {
+        self.vm.borrow_mut().reseed(seed);
+    }
+
+    pub fn randomize_exa_order(&mut self) {
+        self.vm.borrow_mut().scheduler = Box::new(Shuffle);
+        self.spawn_order = false;
+    }
+
+    pub fn add_register<R: HardwareRegister + 'static>(
+        &mut self,
+        hostname: &str,
+        name: &str,
+        register: R,
+    ) {
+        let mut vm = self.vm.borrow_mut();
+        let host = vm.hosts.get_mut(hostname).expect("unknown host");
+        host.add_register(name.into(), register);
+    }
+
+    pub fn input_pressed(&mut self, button: RedshiftButton) {
+        self.vm.borrow_mut().input_pressed(button);
+    }
+
     /// Spawn an Exa in the first host.
-    pub fn exa(&mut self, script: &str) -> Shared<Exa<'a>> {
-        let name = if self.redshift { "core" } else { "start" };
-        let host = self.vm.borrow().hosts.get(name).unwrap().clone();
-        let mut name = String::from("x");
-        name.push_str(&self.spawned.to_string());
-        self.spawned += 1;
-        Exa::spawn(
-            &mut self.vm.clone().borrow_mut(),
-            host,
-            name,
-            self.redshift,
-            script,
-        )
-        .unwrap()
+    pub fn exa(&mut self, script: &str) -> ExaRef {
+        let host = if self.redshift { "core" } else { "start" };
+        self.exa_custom(script, host, Mode::Global)
     }
 
     /// Spawn an Exa, with all available options.
-    pub fn exa_custom(&mut self, script: &str, host: &str, mode: Mode) -> Shared<Exa<'a>> {
-        let host = self.vm.borrow().hosts.get(host).unwrap().clone();
+    pub fn exa_custom(&mut self, script: &str, host: &str, mode: Mode) -> ExaRef {
+        let mut vm = self.vm.borrow_mut();
+        let host = vm.hosts.id(host).unwrap();
         let mut name = String::from("x");
         name.push_str(&self.spawned.to_string());
         self.spawned += 1;
-        let e = Exa::spawn(
-            &mut self.vm.clone().borrow_mut(),
-            host,
-            name,
-            self.redshift,
-            script,
-        )
-        .unwrap();
-        e.borrow_mut().mode = mode;
-        e
+        let e = Exa::spawn(&mut vm, host, name.clone(), self.redshift, script).unwrap();
+        e.mode = mode;
+        self.get_exa(&name)
     }
 
     /// Get an exa by its name. Useful for grabbing new EXAs that have been
     /// spawned via REPL commands.
-    pub fn get_exa(&mut self, name: &str) -> Shared<Exa<'a>> {
-        self.vm.borrow_mut().get_exa(name)
+    pub fn get_exa(&self, name: &str) -> ExaRef {
+        ExaRef {
+            vm: self.vm.clone(),
+            name: name.to_string(),
+        }
     }
 
     pub fn run_cycle(&mut self) {
@@ -120,49 +198,81 @@ impl<'a> TestBench<'a> {
         println!("{}", self);
     }
 
-    pub fn assert_position(&self, exa: &Shared<Exa<'a>>, hostname: &str) {
-        assert_eq!(exa.borrow().host.borrow().name, hostname);
+    pub fn run_cycles(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.run_cycle();
+        }
+    }
+
+    pub fn assert_same_state(&self, other: &TestBench) {
+        assert_eq!(format!("{}", self), format!("{}", other));
+        assert!(
+            self.save_state() == other.save_state(),
+            "save states differ"
+        );
+    }
+
+    pub fn assert_pixel(&self, x: usize, y: usize, lit: bool) {
+        let mut vm = self.vm.borrow_mut();
+        assert_eq!(vm.render()[x + y * 120], lit, "pixel ({}, {})", x, y);
+    }
+
+    pub fn assert_anaglyph_pixel(&self, x: usize, y: usize, red: bool, cyan: bool) {
+        let mut vm = self.vm.borrow_mut();
+        let pixel = vm.render_anaglyph()[x + y * 120];
+        assert_eq!((pixel.red, pixel.cyan), (red, cyan), "pixel ({}, {})", x, y);
+    }
+
+    pub fn assert_anaglyph_enabled(&self, enabled: bool) {
+        assert_eq!(self.vm.borrow().anaglyph_enabled(), enabled);
     }
 
-    pub fn assert_exa_register(&self, exa: &Shared<Exa<'a>>, specifier: &str, value: i32) {
+    pub fn assert_position(&self, exa: &ExaRef, hostname: &str) {
+        let host = exa.borrow().host;
+        assert_eq!(self.vm.borrow().hosts[host].name, hostname);
+    }
+
+    pub fn assert_exa_register<V: Into<Value>>(&self, exa: &ExaRef, specifier: &str, value: V) {
+        let value = value.into();
         let v = exa.borrow_mut().read_register(specifier).unwrap();
         assert_eq!(v, value, "wanted {} got {}", value, v);
     }
 
-    pub fn assert_exa_no_file(&self, exa: &Shared<Exa<'a>>) {
+    pub fn assert_exa_no_file(&self, exa: &ExaRef) {
         assert!(exa.borrow().file.is_none());
     }
 
-    pub fn assert_exa_file(&self, exa: &Shared<Exa<'a>>, file_id: i32) {
+    pub fn assert_exa_file(&self, exa: &ExaRef, file_id: i32) {
         assert_eq!(
             exa.borrow().file.as_ref().expect("no file held").id,
             file_id
         );
     }
 
-    pub fn assert_exa_file_contents(&self, exa: &Shared<Exa<'a>>, contents: Vec<i32>) {
+    pub fn assert_exa_file_contents<V: Into<Value>>(&self, exa: &ExaRef, contents: Vec<V>) {
         let e = exa.borrow();
         let f = e.file.as_ref().expect("no file held");
+        let contents: Vec<Value> = contents.into_iter().map(|v| v.into()).collect();
         assert_eq!(f.contents, contents);
     }
 
-    pub fn assert_exa_sprite(&self, exa: &Shared<Exa<'a>>, shorthand: Vec<u32>) {
+    pub fn assert_exa_sprite(&self, exa: &ExaRef, shorthand: Vec<u32>) {
         let test_sprite = Sprite::from_shorthand(shorthand);
         assert_eq!(exa.borrow().sprite, test_sprite);
     }
 
-    pub fn assert_exa_global_mode(&self, exa: &Shared<Exa<'a>>) {
+    pub fn assert_exa_global_mode(&self, exa: &ExaRef) {
         assert_eq!(exa.borrow().mode, Mode::Global);
     }
 
-    pub fn assert_exa_local_mode(&self, exa: &Shared<Exa<'a>>) {
+    pub fn assert_exa_local_mode(&self, exa: &ExaRef) {
         assert_eq!(exa.borrow().mode, Mode::Local);
     }
 
     pub fn assert_host_file(&self, hostname: &str, file_id: i32) {
         let vm = self.vm.borrow();
         let host = vm.hosts.get(hostname).expect("unknown host");
-        for f in host.borrow().files.iter() {
+        for f in host.files.iter() {
             if f.id == file_id {
                 return;
             }
@@ -173,56 +283,64 @@ impl<'a> TestBench<'a> {
     pub fn assert_host_occupied_slots(&self, hostname: &str, occupied: usize) {
         let vm = self.vm.borrow();
         let host = vm.hosts.get(hostname).expect("unknown host");
-        assert_eq!(host.borrow().occupied, occupied);
+        assert_eq!(host.occupied, occupied);
+    }
+
+    pub fn assert_host_register<V: Into<Value>>(&self, hostname: &str, name: &str, value: V) {
+        let vm = self.vm.borrow();
+        let host = vm.hosts.get(hostname).expect("unknown host");
+        let register = host.registers.get(name).expect("unknown register");
+        assert_eq!(register.peek(), value.into());
     }
 
     pub fn assert_host_no_file(&self, hostname: &str, file_id: i32) {
         let vm = self.vm.borrow();
         let host = vm.hosts.get(hostname).expect("unknown host");
-        for f in host.borrow().files.iter() {
+        for f in host.files.iter() {
             if f.id == file_id {
                 panic!("file found");
             }
         }
     }
 
-    pub fn assert_fatal_error(&self, exa: &Shared<Exa<'a>>) {
-        let e = exa.borrow();
-        let error = e.error.as_ref().unwrap();
-        match error.downcast_ref::<ExaError>() {
-            Some(e) => match *e {
-                ExaError::Fatal(_) => (),
-                _ => panic!("expected fatal error, got {}", e),
-            },
-            _ => panic!("expected fatal error, got {}", e),
-        }
+    pub fn assert_activity(&self, links: u32, kills: u32) {
+        let vm = self.vm.borrow();
+        assert_eq!((vm.links_traversed, vm.kills), (links, kills));
     }
 
-    pub fn assert_blocking_error(&self, exa: &Shared<Exa<'a>>) {
-        let e = exa.borrow();
-        let error = e.error.as_ref().unwrap();
-        match error.downcast_ref::<ExaError>() {
-            Some(e) => match *e {
-                ExaError::Blocking(_) => (),
-                _ => panic!("expected blocking error, got {}", e),
-            },
-            _ => panic!("expected blocking error, got {}", e),
+    pub fn assert_fatal_error(&self, exa: &ExaRef) {
+        self.assert_severity(exa, Severity::Fatal);
+    }
+
+    pub fn assert_blocking_error(&self, exa: &ExaRef) {
+        self.assert_severity(exa, Severity::Blocking);
+    }
+
+    pub fn assert_freezing_error(&self, exa: &ExaRef) {
+        self.assert_severity(exa, Severity::Freezing);
+    }
+
+    fn assert_severity(&self, exa: &ExaRef, severity: Severity) {
+        // EXAs are cleaned up the cycle after a fatal error, so a dead one
+        // can only be remembered by the VM's terminated history
+        if !exa.is_alive() {
+            let vm = self.vm.borrow();
+            let t = vm.find_terminated(&exa.name).expect("exa is gone");
+            assert_eq!(severity, Severity::Fatal, "exa died: {:?}", t.cause);
+            return;
         }
+        let e = exa.borrow();
+        let fault = e.error.as_ref().expect("expected an error, got None");
+        assert_eq!(fault.severity(), severity, "got {}", fault);
     }
 
-    pub fn assert_freezing_error(&self, exa: &Shared<Exa<'a>>) {
+    pub fn assert_error(&self, exa: &ExaRef, error: ExaError) {
         let e = exa.borrow();
-        let error = e.error.as_ref().unwrap();
-        match error.downcast_ref::<ExaError>() {
-            Some(e) => match *e {
-                ExaError::Freezing(_) => (),
-                _ => panic!("expected freezing error, got {}", e),
-            },
-            _ => panic!("expected freezing error, got {}", e),
-        }
+        let fault = e.error.as_ref().expect("expected an error, got None");
+        assert_eq!(fault.error, error, "got {}", fault);
     }
 
-    pub fn assert_no_error(&self, exa: &Shared<Exa<'a>>) {
+    pub fn assert_no_error(&self, exa: &ExaRef) {
         let e = exa.borrow();
         assert!(
             e.error.is_none(),
@@ -231,17 +349,45 @@ impl<'a> TestBench<'a> {
         );
     }
 
-    pub fn assert_alive(&self, exa: &Shared<Exa<'a>>) {
-        for test_exa in self.vm.borrow().exas.iter() {
-            if test_exa == exa {
-                return;
+    pub fn assert_alive(&self, exa: &ExaRef) {
+        assert!(exa.is_alive(), "exa is not alive");
+    }
+    pub fn assert_dead(&self, exa: &ExaRef) {
+        assert!(!exa.is_alive(), "exa is alive");
+    }
+}
+
+/// Hardware register that counts up every time it's read. Writing a
+/// number sets the count.
+#[allow(dead_code)]
+#[derive(Debug, Default)]
+pub struct Clock {
+    pub ticks: i32,
+}
+
+impl HardwareRegister for Clock {
+    fn on_read(&mut self) -> Result<Value, Box<dyn Error>> {
+        self.ticks += 1;
+        Ok(Value::Number(self.ticks))
+    }
+
+    fn on_write(&mut self, value: Value) -> Result<(), Box<dyn Error>> {
+        match value {
+            Value::Number(n) => self.ticks = n,
+            Value::Keyword(_) => {
+                return Err(ExaError::Fatal("numeric value required".into()).into())
             }
         }
-        panic!("exa is not alive")
+        Ok(())
     }
-    pub fn assert_dead(&self, exa: &Shared<Exa<'a>>) {
-        for test_exa in self.vm.borrow().exas.iter() {
-            assert_ne!(test_exa, exa);
+
+    fn peek(&self) -> Value {
+        Value::Number(self.ticks)
+    }
+
+    fn restore(&mut self, value: Value) {
+        if let Value::Number(n) = value {
+            self.ticks = n;
         }
     }
 }
@@ -0,0 +1,185 @@
+mod common;
+
+use common::*;
+use exa::vm::debug::{Break, Breakpoint, ExaStatus, Location, Watch};
+use exa::vm::error::ExaError;
+use exa::vm::value::Value;
+
+#[test]
+fn debug_step_instruction() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("copy 1 x\n copy 2 x\n");
+    let e2 = bench.exa("copy 3 x\n noop\n");
+
+    assert_eq!(bench.vm().step_instruction(), Some("x0".into()));
+    bench.assert_exa_register(&e1, "x", 1);
+    bench.assert_exa_register(&e2, "x", 0);
+    assert_eq!(bench.vm().cycle, 0);
+
+    assert_eq!(bench.vm().step_instruction(), Some("x1".into()));
+    bench.assert_exa_register(&e2, "x", 3);
+    assert_eq!(bench.vm().cycle, 1);
+
+    bench.vm().step_instruction();
+    bench.assert_exa_register(&e1, "x", 2);
+}
+
+#[test]
+fn debug_run_cycle_finishes_step() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("copy 1 x\n copy 2 x\n noop\n");
+    let e2 = bench.exa("copy 3 x\n copy 4 x\n noop\n");
+
+    bench.vm().step_instruction();
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "x", 1);
+    bench.assert_exa_register(&e2, "x", 3);
+    assert_eq!(bench.vm().cycle, 1);
+}
+
+#[test]
+fn debug_breakpoint_line() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("noop\n mark loop\n addi x 1 x\n\n ; comment\n addi t 1 t\n jump loop\n");
+    bench.vm().add_breakpoint("x0", Location::Line(6));
+
+    let expected = Break::Breakpoint {
+        exa: "x0".into(),
+        breakpoint: Breakpoint {
+            exa: "x0".into(),
+            location: Location::Line(6),
+        },
+    };
+    assert_eq!(bench.vm().run_until_break(100), expected);
+    bench.assert_exa_register(&e1, "x", 1);
+    bench.assert_exa_register(&e1, "t", 0);
+
+    assert_eq!(bench.vm().run_until_break(100), expected);
+    bench.assert_exa_register(&e1, "x", 2);
+    bench.assert_exa_register(&e1, "t", 1);
+
+    bench.vm().remove_breakpoint("x0", &Location::Line(6));
+    assert_eq!(bench.vm().run_until_break(10), Break::CycleLimit);
+}
+
+#[test]
+fn debug_breakpoint_macro() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("@rep 3\n addi x 1 x\n @end\n noop\n");
+    bench.vm().add_breakpoint("x0", Location::Line(2));
+
+    for x in 0..3 {
+        assert!(matches!(
+            bench.vm().run_until_break(100),
+            Break::Breakpoint { .. }
+        ));
+        bench.assert_exa_register(&e1, "x", x);
+    }
+    assert_eq!(bench.vm().run_until_break(100), Break::Halted);
+}
+
+#[test]
+fn debug_breakpoint_label_lineage() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("noop\n repl child\n halt\n mark child\n copy 1 x\n noop\n");
+    bench
+        .vm()
+        .add_breakpoint("x0", Location::Label("CHILD".into()));
+
+    match bench.vm().run_until_break(100) {
+        Break::Breakpoint { exa, .. } => assert_eq!(exa, "x0:1"),
+        b => panic!("unexpected break {:?}", b),
+    }
+    let info = bench.vm().exa_info();
+    let child = info.iter().find(|e| e.name == "x0:1").unwrap();
+    assert_eq!(child.line, Some(5));
+    assert_eq!(child.registers[0], ("X".into(), Value::Number(0)));
+}
+
+#[test]
+fn debug_watch_exa_register() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("noop\n noop\n copy 5 x\n noop\n");
+    bench.vm().add_watchpoint(Watch::Exa {
+        exa: "x0".into(),
+        register: "x".into(),
+    });
+
+    assert_eq!(
+        bench.vm().run_until_break(100),
+        Break::Watchpoint {
+            watch: Watch::Exa {
+                exa: "x0".into(),
+                register: "x".into(),
+            },
+            old: Some(Value::Number(0)),
+            new: Some(Value::Number(5)),
+        }
+    );
+    assert_eq!(bench.vm().cycle, 3);
+}
+
+#[test]
+fn debug_watch_host_register() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("noop\n copy 'HI' #reg\n noop\n");
+    let watch = Watch::Host {
+        host: "start".into(),
+        register: "#REG".into(),
+    };
+    bench.vm().add_watchpoint(watch.clone());
+
+    assert_eq!(
+        bench.vm().run_until_break(100),
+        Break::Watchpoint {
+            watch,
+            old: Some(Value::Number(100)),
+            new: Some(Value::keyword("HI")),
+        }
+    );
+}
+
+#[test]
+fn debug_exa_info() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("copy m x\n noop\n");
+    let _ = bench.exa("noop\n noop\n noop\n noop\n");
+    let _ = bench.exa("make\n copy 7 f\n link 999\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    let info = bench.vm().exa_info();
+    assert_eq!(info[0].status, ExaStatus::Blocked(ExaError::NoMessages));
+    assert_eq!(info[0].pc, 0);
+    assert_eq!(info[0].line, Some(1));
+    assert_eq!(info[1].status, ExaStatus::Running);
+    assert_eq!(
+        info[2].file.as_ref().unwrap().contents,
+        vec![Value::Number(7)]
+    );
+    assert_eq!(info[2].file_pointer, 1);
+    assert_eq!(info[2].host, "start");
+
+    bench.run_cycle();
+    let info = bench.vm().exa_info();
+    assert_eq!(
+        info[2].status,
+        ExaStatus::Dead(ExaError::InvalidLinkId(999))
+    );
+}
+
+#[test]
+fn debug_state_mid_cycle() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("copy 1 x\n copy 2 x\n noop\n");
+    let _ = bench.exa("copy 3 x\n copy 4 x\n noop\n");
+
+    bench.vm().step_instruction();
+    let mut restored = bench.clone_via_state();
+    bench.assert_same_state(&restored);
+
+    bench.run_cycle();
+    restored.run_cycle();
+    bench.assert_same_state(&restored);
+    assert_eq!(restored.vm().cycle, 1);
+}
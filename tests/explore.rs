@@ -0,0 +1,92 @@
+mod common;
+
+use common::*;
+use exa::explore::Explorer;
+use exa::vm::error::ExaError;
+use exa::vm::terminated::Cause;
+
+// x2 and x3 both read on the global bus once x0 and x1 have written
+fn racing_bench() -> TestBench {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("copy 1 m\n noop\n noop\n noop\n noop\n");
+    let _ = bench.exa("copy 0 m\n noop\n noop\n noop\n noop\n");
+    let _ = bench.exa("noop\n copy m x\n divi 10 x x\n noop\n noop\n");
+    let _ = bench.exa("noop\n copy m x\n noop\n noop\n noop\n");
+    bench
+}
+
+#[test]
+fn explore_no_race() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("copy 1 m\n noop\n noop\n");
+    let _ = bench.exa("noop\n copy m x\n noop\n noop\n");
+
+    let report = Explorer::new(4).explore(&bench.vm()).unwrap();
+    assert!(!report.divergent(), "{}", report);
+    assert_eq!(report.schedules, 1);
+    assert!(!report.truncated);
+}
+
+#[test]
+fn explore_divergent() {
+    let bench = racing_bench();
+    let report = Explorer::new(4).explore(&bench.vm()).unwrap();
+    assert!(report.divergent(), "{}", report);
+    assert_eq!(report.outcomes.len(), 2);
+    assert!(!report.truncated);
+
+    // Whichever reader gets the 0 dies dividing by it
+    let deaths: Vec<_> = report.outcomes.iter().map(|(o, _)| &o.deaths).collect();
+    assert!(deaths.iter().any(|d| d.is_empty()));
+    assert!(deaths.iter().any(|d| d.len() == 1
+        && d[0].exa == "x2"
+        && d[0].cause == Cause::Fault(ExaError::DivideByZero)));
+}
+
+#[test]
+fn explore_schedule_reproduces() {
+    let bench = racing_bench();
+    let report = Explorer::new(4).explore(&bench.vm()).unwrap();
+
+    for (outcome, schedule) in report.outcomes.iter() {
+        let mut replay = racing_bench();
+        replay.vm().scheduler = Box::new(schedule.scheduler());
+        replay.run_cycles(4);
+        let dead = replay
+            .vm()
+            .exas
+            .iter()
+            .filter(|e| e.is_fatal())
+            .map(|e| e.name.to_string())
+            .collect::<Vec<_>>();
+        let expected = outcome
+            .deaths
+            .iter()
+            .map(|d| d.exa.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(dead, expected, "schedule {}", schedule);
+    }
+}
+
+#[test]
+fn explore_max_schedules() {
+    let bench = racing_bench();
+    let mut explorer = Explorer::new(4);
+    explorer.max_schedules = 1;
+    let report = explorer.explore(&bench.vm()).unwrap();
+    assert_eq!(report.schedules, 1);
+    assert_eq!(report.outcomes.len(), 1);
+    assert!(report.truncated);
+}
+
+#[test]
+fn explore_mid_cycle() {
+    let bench = racing_bench();
+    bench.vm().run_cycle();
+    bench.vm().step_instruction();
+    assert!(Explorer::new(4).explore(&bench.vm()).is_err());
+
+    // Finishing the cycle makes it explorable again
+    bench.vm().run_cycle();
+    assert!(Explorer::new(4).explore(&bench.vm()).is_ok());
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::*;
+use exa::vm::error::{ExaError, Fault};
+use exa::vm::exa::Mode;
+use exa::vm::register::Register;
+use exa::vm::Permissions;
+
+#[test]
+fn fault_context() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("noop\n copy 0 x\n\n divi 5 x x\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_no_error(&e1);
+    bench.run_cycle();
+
+    let fault = e1.borrow().error.clone().unwrap();
+    assert_eq!(
+        fault,
+        Fault {
+            error: ExaError::DivideByZero,
+            exa: "x0".into(),
+            pc: 2,
+            line: Some(4),
+            cycle: 2,
+        }
+    );
+    assert_eq!(
+        fault.to_string(),
+        "x0 died at line 4: divide by zero on cycle 2"
+    );
+}
+
+#[test]
+fn fault_out_of_instructions() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("noop\n copy 1 x\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_error(&e1, ExaError::OutOfInstructions);
+    assert_eq!(e1.borrow().error.as_ref().unwrap().line, Some(2));
+}
+
+#[test]
+fn fault_kinds() {
+    let mut bench = TestBench::basic_vm();
+    let r = Register::new(Permissions::ReadOnly, 5);
+    bench.add_register("start", "#ro", r);
+    let e1 = bench.exa("jump nowhere\n noop\n");
+    let e2 = bench.exa("copy 1 #ro\n noop\n");
+    let e3 = bench.exa("copy f x\n noop\n");
+    let e4 = bench.exa("link 5\n noop\n");
+    let e5 = bench.exa_custom("copy #nope x\n noop\n", "end", Mode::Global);
+    let e6 = bench.exa_custom("grab 123\n noop\n", "end", Mode::Global);
+    let e7 = bench.exa_custom("copy 'KW' gx\n noop\n", "end", Mode::Global);
+
+    bench.run_cycle();
+    bench.assert_error(&e1, ExaError::UnknownLabel("nowhere".into()));
+    bench.assert_error(&e2, ExaError::ReadOnlyRegister);
+    bench.assert_error(&e3, ExaError::NoFileHeld);
+    bench.assert_error(&e4, ExaError::InvalidLinkId(5));
+    bench.assert_error(&e5, ExaError::UnknownRegister("#nope".into()));
+    bench.assert_error(&e6, ExaError::FileNotFound(123));
+    bench.assert_error(&e7, ExaError::NumberRequired);
+}
+
+#[test]
+fn fault_host_full() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa_custom("noop\n noop\n noop\n", "end", Mode::Global);
+    let _ = bench.exa_custom("noop\n noop\n noop\n", "end", Mode::Global);
+    let _ = bench.exa_custom("noop\n noop\n noop\n", "end", Mode::Global);
+    let _ = bench.exa_custom("noop\n noop\n noop\n", "end", Mode::Global);
+    let e1 = bench.exa("link 800\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_blocking_error(&e1);
+    bench.assert_error(&e1, ExaError::HostFull);
+}
+
+#[test]
+fn fault_save_state() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("noop\n copy m x\n noop\n");
+    let _ = bench.exa("jump nowhere\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    let other = bench.clone_via_state();
+    bench.assert_same_state(&other);
+
+    let e1 = other.get_exa("x0");
+    other.assert_error(&e1, ExaError::NoMessages);
+    assert_eq!(e1.borrow().error.as_ref().unwrap().cycle, 1);
+}
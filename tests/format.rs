@@ -0,0 +1,208 @@
+use exa::parse::{format_text, parse_text, print_text};
+use exa::vm::instruction::{Comparator, Instruction, Target};
+use exa::vm::value::Value;
+
+const REGISTERS: [&str; 10] = [
+    "x", "t", "f", "gx", "gy", "gz", "co", "ci", "#padx", "#sqr0",
+];
+const LABELS: [&str; 5] = ["a", "loop", "denote", "note_1", "x2"];
+
+fn pick<'a>(rng: &fastrand::Rng, options: &[&'a str]) -> &'a str {
+    options[rng.usize(..options.len())]
+}
+
+fn random_case(rng: &fastrand::Rng, s: &str) -> String {
+    s.chars()
+        .map(|c| match rng.bool() {
+            true => c.to_ascii_uppercase(),
+            false => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+fn space(rng: &fastrand::Rng) -> &'static str {
+    pick(rng, &[" ", "  ", "\t", " \t "])
+}
+
+fn keyword(rng: &fastrand::Rng) -> String {
+    let chars = "abcXYZ019 ;@{}#";
+    let len = rng.usize(1..6);
+    let word: String = (0..len)
+        .map(|_| chars.chars().nth(rng.usize(..chars.len())).unwrap())
+        .collect();
+    format!("'{}'", word)
+}
+
+/// A number, or an @{...} that stays in range, if in an @REP block.
+fn number(rng: &fastrand::Rng, in_rep: bool) -> String {
+    match in_rep && rng.bool() {
+        true => format!("@{{{},{}}}", rng.i32(-50..50), rng.i32(-10..10)),
+        false => rng.i32(-9999..=9999).to_string(),
+    }
+}
+
+fn target(rng: &fastrand::Rng, in_rep: bool) -> String {
+    match rng.u8(..3) {
+        0 => number(rng, in_rep),
+        1 => keyword(rng),
+        _ => random_case(rng, pick(rng, &REGISTERS)),
+    }
+}
+
+fn instruction(rng: &fastrand::Rng, in_rep: bool) -> String {
+    let s = space(rng);
+    let reg = |rng: &fastrand::Rng| random_case(rng, pick(rng, &REGISTERS));
+    let label = |rng: &fastrand::Rng| random_case(rng, pick(rng, &LABELS));
+    let op = |name: &str| random_case(rng, name);
+    match rng.u8(..14) {
+        0 => format!(
+            "{}{}{}{}{}",
+            op("copy"),
+            s,
+            target(rng, in_rep),
+            s,
+            reg(rng)
+        ),
+        1 => format!("{}{}m{}{}", op("copy"), s, s, reg(rng)),
+        2 => {
+            let name = pick(
+                rng,
+                &["addi", "subi", "muli", "divi", "modi", "swiz", "rand"],
+            );
+            let (l, r) = (target(rng, in_rep), target(rng, in_rep));
+            format!("{}{}{}{}{}{}{}", op(name), s, l, s, r, s, reg(rng))
+        }
+        3 => {
+            let name = pick(rng, &["mark", "jump", "tjmp", "fjmp", "repl"]);
+            format!("{}{}{}", op(name), s, label(rng))
+        }
+        4 => {
+            let comp = pick(rng, &["=", "<", ">"]);
+            let (l, r) = (target(rng, in_rep), target(rng, in_rep));
+            format!("{}{}{}{}{}{}{}", op("test"), s, l, s, comp, s, r)
+        }
+        5 => op(pick(rng, &["test mrd", "test eof", "void m", "void f"])),
+        6 => op(pick(
+            rng,
+            &[
+                "halt", "kill", "mode", "make", "drop", "wipe", "noop", "wait",
+            ],
+        )),
+        7 => {
+            let name = pick(rng, &["link", "grab", "seek"]);
+            format!("{}{}{}", op(name), s, target(rng, in_rep))
+        }
+        8 => format!("{}{}{}", op(pick(rng, &["host", "file"])), s, reg(rng)),
+        9 => {
+            let values: Vec<String> = (0..rng.usize(1..4))
+                .map(|_| match rng.bool() {
+                    true => number(rng, in_rep),
+                    false => keyword(rng),
+                })
+                .collect();
+            format!("{}{}{}", op("data"), s, values.join(s))
+        }
+        10 => op("note don't 'mind' me ; at all"),
+        11 => String::new(),
+        _ => format!("{}{}", op(pick(rng, &["noop", "halt"])), s),
+    }
+}
+
+/// A random script that parses, with comments, odd spacing and casing,
+/// and @REP blocks nested up to depth deep.
+fn script(rng: &fastrand::Rng, depth: usize) -> String {
+    let mut out = String::new();
+    for _ in 0..rng.usize(1..8) {
+        let indent = pick(rng, &["", " ", "\t"]);
+        if depth > 0 && rng.u8(..5) == 0 {
+            let body = script(rng, depth - 1);
+            let rep = random_case(rng, "@rep");
+            let end = random_case(rng, "@end");
+            out.push_str(&format!(
+                "{}{} {}\n{}{}\n",
+                indent,
+                rep,
+                rng.usize(..4),
+                body,
+                end
+            ));
+            continue;
+        }
+        out.push_str(indent);
+        out.push_str(&instruction(rng, depth < 2));
+        if rng.u8(..4) == 0 {
+            out.push_str(pick(rng, &[" ; comment", ";", ";;note 'x"]));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn print_parse_identity() {
+    for seed in 0..500 {
+        let rng = fastrand::Rng::with_seed(seed);
+        let source = script(&rng, 2);
+        let insts =
+            parse_text(&source).unwrap_or_else(|e| panic!("seed {}: {}\n{}", seed, e, source));
+        let printed = print_text(&insts);
+        assert_eq!(
+            parse_text(&printed).as_ref(),
+            Ok(&insts),
+            "seed {}:\n{}",
+            seed,
+            printed
+        );
+        assert_eq!(print_text(&parse_text(&printed).unwrap()), printed);
+    }
+}
+
+#[test]
+fn format_keeps_semantics() {
+    for seed in 0..500 {
+        let rng = fastrand::Rng::with_seed(seed);
+        let source = script(&rng, 2);
+        let formatted = format_text(&source).unwrap();
+        assert_eq!(
+            parse_text(&formatted),
+            parse_text(&source),
+            "seed {}:\n{}\nformatted:\n{}",
+            seed,
+            source,
+            formatted
+        );
+        assert_eq!(format_text(&formatted).unwrap(), formatted, "seed {}", seed);
+    }
+}
+
+#[test]
+fn print_instructions() {
+    let insts = vec![
+        Instruction::Copy(
+            Target::Keyword("a;b".into()),
+            Target::Register("#padx".into()),
+        ),
+        Instruction::Test(
+            Target::Literal(-9999),
+            Comparator::GreaterThan,
+            Target::Register("ci".into()),
+        ),
+        Instruction::Mark("note".into()),
+        Instruction::Data(vec![Value::Number(3), Value::keyword("x y")]),
+    ];
+    let printed = print_text(&insts);
+    assert_eq!(
+        printed,
+        "COPY 'A;B' #PADX\nTEST -9999 > CI\nMARK NOTE\nDATA 3 'X Y'\n"
+    );
+    // Keywords are always uppercase once parsed
+    let parsed = parse_text(&printed).unwrap();
+    assert_eq!(
+        parsed[0],
+        Instruction::Copy(
+            Target::Keyword("A;B".into()),
+            Target::Register("#padx".into())
+        )
+    );
+    assert_eq!(parsed[1..], insts[1..]);
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use exa::vm::error::ExaError;
+use exa::vm::register::{HardwareRegister, Register};
+use exa::vm::value::Value;
+use exa::vm::Permissions;
+
+/// Hands out queued values one read at a time, and blocks readers once
+/// it runs dry.
+#[derive(Debug, Default)]
+struct Input {
+    queue: Vec<i32>,
+}
+
+impl HardwareRegister for Input {
+    fn on_read(&mut self) -> Result<Value, Box<dyn Error>> {
+        if self.queue.is_empty() {
+            return Err(ExaError::Blocking("input is empty".into()).into());
+        }
+        Ok(Value::Number(self.queue.remove(0)))
+    }
+
+    fn on_write(&mut self, _: Value) -> Result<(), Box<dyn Error>> {
+        Err(ExaError::Fatal("cannot write to input".into()).into())
+    }
+
+    fn peek(&self) -> Value {
+        Value::Number(*self.queue.first().unwrap_or(&0))
+    }
+}
+
+/// Remembers everything written to it.
+#[derive(Debug, Default)]
+struct Output {
+    written: Vec<Value>,
+}
+
+impl HardwareRegister for Output {
+    fn on_read(&mut self) -> Result<Value, Box<dyn Error>> {
+        Err(ExaError::Fatal("cannot read from output".into()).into())
+    }
+
+    fn on_write(&mut self, value: Value) -> Result<(), Box<dyn Error>> {
+        self.written.push(value);
+        Ok(())
+    }
+
+    fn peek(&self) -> Value {
+        self.written.last().cloned().unwrap_or(Value::Number(0))
+    }
+}
+
+#[test]
+fn hardware_input() {
+    let mut bench = TestBench::basic_vm();
+    let input = Arc::new(Mutex::new(Input { queue: vec![3, 4] }));
+    bench.add_register("start", "#IN", input.clone());
+    let e1 = bench.exa("copy #in x\n addi x #in x\n copy #in t\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "x", 3);
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "x", 7);
+    bench.run_cycle();
+    bench.assert_blocking_error(&e1);
+
+    input.lock().unwrap().queue.push(9);
+    bench.run_cycle();
+    bench.assert_no_error(&e1);
+    bench.assert_exa_register(&e1, "t", 9);
+}
+
+#[test]
+fn hardware_output() {
+    let mut bench = TestBench::basic_vm();
+    let output = Arc::new(Mutex::new(Output::default()));
+    bench.add_register("start", "#OUT", output.clone());
+    let e1 = bench.exa("copy 1 #out\n copy 'DONE' #out\n copy #out x\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_host_register("start", "#out", Value::keyword("DONE"));
+    bench.run_cycle();
+    bench.assert_fatal_error(&e1);
+    assert_eq!(
+        output.lock().unwrap().written,
+        vec![Value::Number(1), Value::keyword("DONE")]
+    );
+}
+
+#[test]
+fn hardware_clock() {
+    let mut bench = TestBench::basic_vm();
+    bench.add_register("start", "#CLK", Clock::default());
+    let e1 = bench.exa("copy #clk x\n copy 100 #clk\n copy #clk t\n copy 'A' #clk\n");
+
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "x", 1);
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "t", 101);
+    bench.run_cycle();
+    bench.assert_fatal_error(&e1);
+}
+
+#[test]
+fn hardware_state_snapshot() {
+    let mut bench = TestBench::basic_vm();
+    bench.add_register("start", "#CLK", Clock { ticks: 41 });
+    let e1 = bench.exa("copy #clk x\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "x", 42);
+    let restored = bench.clone_via_state();
+    restored.assert_host_register("start", "#clk", 42);
+}
+
+#[test]
+fn hardware_out_of_range() {
+    let mut bench = TestBench::basic_vm();
+    let big = Register::new(Permissions::ReadWrite, i32::MAX);
+    let small = Register::new(Permissions::ReadWrite, i32::MIN);
+    bench.add_register("start", "#BIG", big);
+    bench.add_register("start", "#SMAL", small);
+    let e1 = bench.exa("addi #big 1 x\n subi #smal 1 t\n copy #big #big\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "x", 9999);
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "t", -9999);
+    bench.run_cycle();
+    bench.assert_no_error(&e1);
+    bench.assert_host_register("start", "#big", 9999);
+}
@@ -54,3 +54,13 @@ fn test_fjmp() {
     bench.run_cycle();
     bench.assert_dead(&e);
 }
+
+#[test]
+fn test_jump_to_first_instruction() {
+    let mut bench = TestBench::basic_vm();
+    let e = bench.exa("mark top\n addi x 1 x\n jump top\n");
+
+    bench.run_cycles(6);
+    bench.assert_no_error(&e);
+    bench.assert_exa_register(&e, "x", 3);
+}
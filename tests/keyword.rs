@@ -0,0 +1,136 @@
+mod common;
+
+use common::*;
+use exa::vm::value::Value;
+
+#[test]
+fn keyword_copy() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("copy 'hello' x\n copy x t\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "x", Value::keyword("HELLO"));
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "t", Value::keyword("HELLO"));
+}
+
+#[test]
+fn keyword_file() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("make\n copy 'ONE' f\n copy 2 f\n seek -9999\n copy f x\n noop\n");
+
+    for _ in 0..5 {
+        bench.run_cycle();
+    }
+    bench.assert_exa_file_contents(&e1, vec![Value::keyword("ONE"), Value::Number(2)]);
+    bench.assert_exa_register(&e1, "x", Value::keyword("ONE"));
+}
+
+#[test]
+fn keyword_data() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("data 'eof' 1\n noop\n");
+
+    bench.assert_exa_file_contents(&e1, vec![Value::keyword("EOF"), Value::Number(1)]);
+}
+
+#[test]
+fn keyword_bus() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("copy 'PING' m\n noop\n");
+    let e2 = bench.exa("copy m x\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_exa_register(&e2, "x", Value::keyword("PING"));
+}
+
+#[test]
+fn keyword_test() {
+    let cases = [
+        ("test 'A' = 'A'", 1),
+        ("test 'A' = 'B'", 0),
+        ("test 'A' < 'B'", 1),
+        ("test 'B' > 'A'", 1),
+        ("test 'B' < 'A'", 0),
+        ("test 'A' = 0", 0),
+        ("test 'A' > 0", 0),
+        ("test 'A' < 0", 0),
+        ("test 0 < 'A'", 0),
+    ];
+    for (test, expected) in cases.iter() {
+        let mut bench = TestBench::basic_vm();
+        let e1 = bench.exa(&format!("copy 5 t\n {}\n noop\n", test));
+
+        bench.run_cycle();
+        bench.run_cycle();
+        bench.assert_no_error(&e1);
+        bench.assert_exa_register(&e1, "t", *expected);
+    }
+}
+
+#[test]
+fn keyword_tjmp() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("copy 'TRUE' t\n tjmp end\n copy 1 x\n mark end\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "x", 0);
+}
+
+#[test]
+fn keyword_not_numeric() {
+    for script in [
+        "addi 'A' 1 x\n noop\n",
+        "link 'A'\n noop\n",
+        "rand 'A' 1 x\n noop\n",
+        "seek 'A'\n noop\n",
+    ]
+    .iter()
+    {
+        let mut bench = TestBench::basic_vm();
+        let e1 = bench.exa(script);
+
+        bench.run_cycle();
+        bench.assert_fatal_error(&e1);
+    }
+}
+
+#[test]
+fn keyword_redshift_registers() {
+    for script in ["copy 'A' gx\n noop\n", "copy 'A' gp\n noop\n"].iter() {
+        let mut bench = TestBench::redshift_vm();
+        let e1 = bench.exa(script);
+
+        bench.run_cycle();
+        bench.assert_fatal_error(&e1);
+    }
+}
+
+#[test]
+fn host() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("host x\n link 800\n host t\n test x = 'start'\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "x", Value::keyword("START"));
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "t", Value::keyword("END"));
+    bench.run_cycle();
+    bench.assert_exa_register(&e1, "t", 1);
+}
+
+#[test]
+fn keyword_state_round_trip() {
+    let mut bench = TestBench::basic_vm();
+    bench.exa("make\n host f\n copy 'KW' x\n copy x m\n noop\n");
+
+    for _ in 0..4 {
+        bench.run_cycle();
+    }
+    let restored = bench.clone_via_state();
+    bench.assert_same_state(&restored);
+}
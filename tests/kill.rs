@@ -1,6 +1,7 @@
 mod common;
 
 use common::*;
+use exa::vm::kill::{OldestFirst, Retail};
 
 #[test]
 fn kill_noop() {
@@ -56,3 +57,79 @@ fn kill_prioritize_ancestors() {
     bench.assert_fatal_error(&e1);
     bench.assert_no_error(&e2);
 }
+
+#[test]
+fn kill_activity() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("noop\n noop\n");
+    let _ = bench.exa("kill\n kill\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_activity(0, 1);
+    bench.run_cycle();
+    bench.assert_activity(0, 2);
+}
+
+#[test]
+fn kill_oldest_first() {
+    for seed in 0..10 {
+        let mut bench = TestBench::basic_vm();
+        bench.randomize_exa_order();
+        bench.reseed(seed);
+        bench.vm().kill_policy = Box::new(OldestFirst);
+        let e1 = bench.exa("noop\n noop\n noop\n");
+        let e2 = bench.exa("repl end\n kill\n mark end\n noop\n noop\n");
+        let e3 = bench.exa("kill\n noop\n noop\n");
+
+        bench.run_cycle();
+        bench.assert_fatal_error(&e1);
+        bench.assert_no_error(&e3);
+        bench.run_cycle();
+        bench.assert_dead(&e1);
+
+        // With e1 gone, e3 is the oldest EXA left for e2 to kill
+        let e4 = bench.get_exa("x1:1");
+        bench.assert_fatal_error(&e3);
+        bench.assert_no_error(&e2);
+        bench.assert_no_error(&e4);
+    }
+}
+
+#[test]
+fn kill_retail_killers() {
+    let mut bench = TestBench::basic_vm();
+    bench.vm().kill_policy = Box::new(Retail);
+    let e3 = bench.exa("noop\n noop\n");
+    let e1 = bench.exa("kill\n noop\n");
+    let e2 = bench.exa("kill\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_fatal_error(&e1);
+    bench.assert_fatal_error(&e2);
+    bench.assert_no_error(&e3);
+}
+
+// Checks the policy does what its doc says, not that the game does
+#[test]
+fn kill_retail_ignores_lineage() {
+    let mut descendants = 0;
+    let mut strangers = 0;
+    for seed in 0..50 {
+        let mut bench = TestBench::basic_vm();
+        bench.reseed(seed);
+        bench.vm().kill_policy = Box::new(Retail);
+        let _ = bench.exa("repl end\n kill\n mark end\n noop\n noop\n");
+        let e2 = bench.exa("noop\n noop\n noop\n");
+
+        bench.run_cycle();
+        bench.run_cycle();
+        if e2.borrow().is_fatal() {
+            strangers += 1;
+        } else {
+            let e3 = bench.get_exa("x0:1");
+            bench.assert_fatal_error(&e3);
+            descendants += 1;
+        }
+    }
+    assert!(descendants > 0 && strangers > 0);
+}
@@ -92,3 +92,17 @@ fn test_redshift_links() {
         bench.assert_no_error(&e1);
     }
 }
+
+#[test]
+fn link_activity() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("link 800\n link -1\n link 999\n");
+    let _ = bench.exa("link 800\n");
+
+    bench.run_cycle();
+    bench.assert_activity(1, 0);
+    bench.run_cycle();
+    bench.assert_activity(2, 0);
+    bench.run_cycle();
+    bench.assert_activity(3, 0);
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::*;
+use exa::vm::value::Value;
+
+#[test]
+fn network_initial_state() {
+    let bench = TestBench::network_vm("./tests/network.toml".into());
+    let xa = bench.get_exa("XA");
+    let xb = bench.get_exa("XB");
+
+    bench.assert_position(&xa, "inbox");
+    bench.assert_position(&xb, "outbox");
+    bench.assert_exa_global_mode(&xa);
+    bench.assert_exa_local_mode(&xb);
+    bench.assert_host_file("inbox", 200);
+    bench.assert_host_register("inbox", "#in", Value::keyword("HELLO"));
+    bench.assert_host_register("outbox", "#out", 0);
+    bench.assert_host_occupied_slots("inbox", 3);
+    bench.assert_host_occupied_slots("outbox", 2);
+}
+
+#[test]
+fn network_run() {
+    let mut bench = TestBench::network_vm("./tests/network.toml".into());
+    let xa = bench.get_exa("XA");
+    let xb = bench.get_exa("XB");
+
+    bench.run_cycle();
+    bench.assert_exa_file_contents(
+        &xa,
+        vec![Value::Number(1), Value::Number(2), Value::keyword("THREE")],
+    );
+    bench.assert_position(&xb, "inbox");
+
+    bench.run_cycle();
+    bench.assert_exa_register(&xa, "x", Value::keyword("HELLO"));
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_position(&xa, "outbox");
+    bench.assert_exa_register(&xa, "t", Value::keyword("OUTBOX"));
+
+    bench.run_cycle();
+    bench.assert_no_error(&xa);
+    bench.assert_host_register("outbox", "#out", 1);
+}
+
+#[test]
+fn network_missing_file() {
+    assert!(exa::network::load_network("./tests/missing.toml".into()).is_err());
+}
@@ -0,0 +1,169 @@
+use exa::network::{ExaSpec, FileSpec, Network};
+use exa::puzzle::{Failure, Goal, Runner, TestCase};
+use exa::vm::value::Value;
+
+const NETWORK: &str = r#"
+[[host]]
+name = "inbox"
+capacity = 4
+
+[[host]]
+name = "outbox"
+capacity = 4
+
+[[link]]
+from = "inbox"
+to = "outbox"
+id = 800
+return_id = -1
+"#;
+
+/// File 200 in the inbox holds two numbers. Leave their sum in a file
+/// in the outbox.
+fn sum_puzzle(rng: &fastrand::Rng) -> TestCase {
+    let a = rng.i32(0..100);
+    let b = rng.i32(0..100);
+
+    let mut network = Network::from_toml(NETWORK).unwrap();
+    network.hosts[0].files.push(file_spec(200, &[a, b]));
+    TestCase {
+        network,
+        start_host: "inbox".into(),
+        goals: vec![
+            Goal::File {
+                host: "outbox".into(),
+                id: None,
+                contents: vec![Value::Number(a + b)],
+            },
+            Goal::NoFilesLeftBehind,
+            Goal::AllExasHalted,
+        ],
+    }
+}
+
+fn file_spec(id: i32, contents: &[i32]) -> FileSpec {
+    FileSpec {
+        id: Some(id),
+        contents: contents.iter().map(|v| Value::Number(*v)).collect(),
+    }
+}
+
+fn solution(script: &str) -> Vec<ExaSpec> {
+    vec![ExaSpec {
+        name: "XA".into(),
+        script: script.into(),
+        local: false,
+    }]
+}
+
+fn runner() -> Runner {
+    let mut runner = Runner::new(1234);
+    runner.runs = 20;
+    runner.max_cycles = 100;
+    runner
+}
+
+#[test]
+fn puzzle_pass() {
+    let report = runner().run(
+        &sum_puzzle,
+        &solution("grab 200\n copy f x\n addi x f x\n wipe\n link 800\n make\n copy x f\n drop\n"),
+    );
+
+    assert!(report.passed(), "{}", report);
+    assert_eq!(report.cases.len(), 20);
+    assert!(report.cases.iter().all(|c| c.cycles == 8));
+}
+
+#[test]
+fn puzzle_repeatable() {
+    let script = "grab 200\n copy f x\n wipe\n link 800\n make\n copy x f\n drop\n";
+    let first = runner().run(&sum_puzzle, &solution(script));
+    let second = runner().run(&sum_puzzle, &solution(script));
+
+    assert_eq!(first, second);
+}
+
+#[test]
+fn puzzle_wrong_answer() {
+    let report = runner().run(
+        &sum_puzzle,
+        &solution("grab 200\n copy f x\n wipe\n link 800\n make\n copy x f\n drop\n"),
+    );
+
+    assert!(!report.passed());
+    let case = report.first_failure().unwrap();
+    match case.failure.as_ref().unwrap() {
+        Failure::Goal {
+            goal: Goal::File { .. },
+            reason,
+            timed_out: false,
+        } => assert_eq!(reason, "no matching file in outbox"),
+        f => panic!("unexpected failure {}", f),
+    }
+}
+
+#[test]
+fn puzzle_file_left_behind() {
+    let report = runner().run(
+        &sum_puzzle,
+        &solution(
+            "grab 200\n copy f x\n addi x f x\n wipe\n make\n drop\n link 800\n make\n copy x f\n drop\n",
+        ),
+    );
+
+    let case = report.first_failure().unwrap();
+    match case.failure.as_ref().unwrap() {
+        Failure::Goal {
+            goal: Goal::NoFilesLeftBehind,
+            reason,
+            ..
+        } => assert_eq!(reason, "file 400 left in inbox"),
+        f => panic!("unexpected failure {}", f),
+    }
+}
+
+#[test]
+fn puzzle_timeout() {
+    let report = runner().run(&sum_puzzle, &solution("noop\n mark loop\n jump loop\n"));
+
+    let case = report.first_failure().unwrap();
+    assert_eq!(case.cycles, 100);
+    match case.failure.as_ref().unwrap() {
+        Failure::Goal { timed_out, .. } => assert!(timed_out),
+        f => panic!("unexpected failure {}", f),
+    }
+}
+
+#[test]
+fn puzzle_setup_error() {
+    let report = runner().run(&sum_puzzle, &solution("copy m m\n"));
+    assert_eq!(report.size, None);
+    assert!(report.score().is_none());
+
+    match report.first_failure().unwrap().failure.as_ref().unwrap() {
+        Failure::Setup(e) => assert!(e.starts_with("exa XA:")),
+        f => panic!("unexpected failure {}", f),
+    }
+}
+
+#[test]
+fn puzzle_score() {
+    let report = runner().run(
+        &sum_puzzle,
+        &solution("grab 200\n copy f x\n addi x f x\n wipe\n link 800\n make\n copy x f\n drop\n"),
+    );
+
+    let score = report.score().unwrap();
+    assert_eq!(score.size, 8);
+    assert_eq!(score.cycles.counts().collect::<Vec<_>>(), vec![(8, 20)]);
+    assert_eq!(score.activity.counts().collect::<Vec<_>>(), vec![(1, 20)]);
+    assert_eq!(score.to_string(), "cycles 8, size 8, activity 1");
+}
+
+#[test]
+fn puzzle_no_score_on_failure() {
+    let report = runner().run(&sum_puzzle, &solution("noop\n mark loop\n jump loop\n"));
+
+    assert_eq!(report.score(), None);
+}
@@ -31,3 +31,82 @@ fn rand_invalid() {
     bench.run_cycle();
     bench.assert_fatal_error(&e1);
 }
+
+const RAND_LOOP: &str = "noop\n mark loop\n rand 0 9999 x\n jump loop\n";
+
+fn seeded_bench(seed: u64) -> TestBench {
+    let mut bench = TestBench::basic_vm();
+    bench.reseed(seed);
+    bench.randomize_exa_order();
+    bench
+}
+
+#[test]
+fn rand_seed_repeatable() {
+    let mut b1 = seeded_bench(42);
+    let mut b2 = seeded_bench(42);
+    for bench in [&mut b1, &mut b2].iter_mut() {
+        bench.exa(RAND_LOOP);
+        bench.exa(RAND_LOOP);
+        bench.exa("noop\n kill\n kill\n");
+    }
+
+    for _ in 0..30 {
+        b1.run_cycle();
+        b2.run_cycle();
+        b1.assert_same_state(&b2);
+    }
+}
+
+#[test]
+fn rand_seed_differs() {
+    let mut b1 = seeded_bench(1);
+    let mut b2 = seeded_bench(2);
+    b1.exa(RAND_LOOP);
+    b2.exa(RAND_LOOP);
+
+    let mut rolls = (vec![], vec![]);
+    for _ in 0..10 {
+        b1.run_cycle();
+        b2.run_cycle();
+        let (e1, e2) = (b1.get_exa("x0"), b2.get_exa("x0"));
+        rolls.0.push(e1.borrow_mut().read_register("x").unwrap());
+        rolls.1.push(e2.borrow_mut().read_register("x").unwrap());
+    }
+    assert_ne!(rolls.0, rolls.1);
+}
+
+#[test]
+fn rand_reseed_restarts() {
+    let mut bench = seeded_bench(7);
+    let e1 = bench.exa("noop\n rand 0 9999 x\n noop\n");
+    bench.run_cycle();
+    bench.run_cycle();
+    let first = e1.borrow_mut().read_register("x").unwrap();
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_dead(&e1);
+
+    bench.reseed(7);
+    let e2 = bench.exa("noop\n rand 0 9999 x\n noop\n");
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_exa_register(&e2, "x", first);
+}
+
+#[test]
+fn rand_seed_survives_state() {
+    let mut bench = seeded_bench(99);
+    bench.exa(RAND_LOOP);
+    bench.exa(RAND_LOOP);
+    for _ in 0..5 {
+        bench.run_cycle();
+    }
+
+    let mut restored = bench.clone_via_state();
+    for _ in 0..10 {
+        bench.run_cycle();
+        restored.run_cycle();
+        bench.assert_same_state(&restored);
+    }
+}
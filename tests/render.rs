@@ -0,0 +1,81 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn render_sprite() {
+    let mut bench = TestBench::redshift_vm();
+    let _ = bench.exa("copy 10 gx\n copy 20 gy\n copy 100 gp\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.assert_pixel(10, 20, true);
+    bench.assert_pixel(11, 20, false);
+}
+
+#[test]
+fn anaglyph_disabled_by_default() {
+    let mut bench = TestBench::redshift_vm();
+    let _ = bench.exa("noop\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_anaglyph_enabled(false);
+}
+
+#[test]
+fn anaglyph_enabled_by_en3d() {
+    let mut bench = TestBench::redshift_vm();
+    let _ = bench.exa("link 800\n copy 1 #en3d\n noop\n");
+
+    bench.run_cycle();
+    bench.assert_anaglyph_enabled(false);
+    bench.run_cycle();
+    bench.assert_anaglyph_enabled(true);
+}
+
+#[test]
+fn anaglyph_flat_sprite() {
+    let mut bench = TestBench::redshift_vm();
+    let _ = bench.exa("copy 10 gx\n copy 20 gy\n copy 100 gp\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.run_cycle();
+    // GZ of 0 draws both eyes in the same place
+    bench.assert_anaglyph_pixel(10, 20, true, true);
+    bench.assert_anaglyph_pixel(11, 20, false, false);
+    bench.assert_anaglyph_pixel(9, 20, false, false);
+}
+
+#[test]
+fn anaglyph_parallax() {
+    let mut bench = TestBench::redshift_vm();
+    let _ = bench.exa("copy 10 gx\n copy 4 gz\n copy 100 gp\n noop\n");
+    let _ = bench.exa("copy 50 gx\n copy -3 gz\n copy 100 gp\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.run_cycle();
+    // Positive GZ pops out: red moves right, cyan moves left
+    bench.assert_anaglyph_pixel(12, 0, true, false);
+    bench.assert_anaglyph_pixel(8, 0, false, true);
+    bench.assert_anaglyph_pixel(10, 0, false, false);
+    // Negative GZ recedes, and odd depths put the extra pixel on red
+    bench.assert_anaglyph_pixel(48, 0, true, false);
+    bench.assert_anaglyph_pixel(51, 0, false, true);
+    bench.assert_anaglyph_pixel(50, 0, false, false);
+}
+
+#[test]
+fn anaglyph_clips_to_screen() {
+    let mut bench = TestBench::redshift_vm();
+    let _ = bench.exa("copy 0 gx\n copy 9 gz\n copy 100 gp\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+    bench.run_cycle();
+    // Cyan copy is pushed off the left edge entirely
+    bench.assert_anaglyph_pixel(0, 0, false, false);
+    bench.assert_anaglyph_pixel(5, 0, true, false);
+}
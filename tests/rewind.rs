@@ -0,0 +1,145 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use exa::vm::debug::{Break, Location};
+use exa::vm::redshift::RedshiftButton;
+
+/// Run cycles, keeping a save state from the start of each one.
+fn record(bench: &mut TestBench, states: &mut Vec<Vec<u8>>, cycles: usize) {
+    for _ in 0..cycles {
+        states.push(bench.save_state());
+        bench.run_cycle();
+    }
+}
+
+fn random_bench() -> TestBench {
+    let mut bench = TestBench::basic_vm();
+    bench.randomize_exa_order();
+    bench.reseed(7);
+    let _ = bench.exa("noop\n mark a\n rand 0 9 x\n copy x m\n jump a\n");
+    let _ = bench.exa("noop\n mark a\n rand 0 9 x\n copy x m\n jump a\n");
+    let _ = bench.exa("noop\n mark a\n addi m x x\n jump a\n");
+    bench
+}
+
+#[test]
+fn rewind_to_cycle() {
+    let mut bench = random_bench();
+    bench.vm().enable_rewind(5, 100);
+    let mut states = vec![];
+    record(&mut bench, &mut states, 12);
+    let end = bench.save_state();
+
+    bench.vm().rewind_to(3).unwrap();
+    assert_eq!(bench.vm().cycle, 3);
+    assert!(bench.save_state() == states[3], "rewound state differs");
+
+    bench.vm().rewind_to(3).unwrap();
+    assert!(bench.save_state() == states[3], "rewound state differs");
+
+    // Running forward again takes the same path
+    bench.run_cycles(9);
+    assert!(bench.save_state() == end, "replayed state differs");
+}
+
+#[test]
+fn rewind_step_back() {
+    let mut bench = random_bench();
+    bench.vm().enable_rewind(4, 100);
+    let mut states = vec![];
+    record(&mut bench, &mut states, 10);
+
+    for cycle in (0..10).rev() {
+        bench.vm().step_back().unwrap();
+        assert!(bench.save_state() == states[cycle], "cycle {}", cycle);
+    }
+    assert!(bench.vm().step_back().is_err());
+}
+
+#[test]
+fn rewind_mid_cycle() {
+    let mut bench = random_bench();
+    bench.vm().enable_rewind(4, 100);
+    bench.run_cycles(6);
+    let start = bench.save_state();
+
+    bench.vm().step_instruction();
+    bench.vm().step_instruction();
+    bench.vm().step_back().unwrap();
+    assert!(bench.save_state() == start, "rewound state differs");
+}
+
+#[test]
+fn rewind_inputs() {
+    let mut bench = TestBench::redshift_vm();
+    bench.vm().enable_rewind(3, 100);
+    let mut states = vec![];
+    record(&mut bench, &mut states, 2);
+    bench.input_pressed(RedshiftButton::Right);
+    bench.input_pressed(RedshiftButton::X);
+    record(&mut bench, &mut states, 2);
+    bench.vm().reset_inputs();
+    bench.input_pressed(RedshiftButton::Left);
+    record(&mut bench, &mut states, 3);
+    bench.assert_host_register("input", "#padx", -1);
+
+    bench.vm().rewind_to(3).unwrap();
+    assert!(bench.save_state() == states[3], "rewound state differs");
+    bench.assert_host_register("input", "#padx", 1);
+    bench.assert_host_register("input", "#padb", 1);
+
+    // Inputs after the rewind point are forgotten
+    bench.run_cycles(4);
+    bench.vm().rewind_to(5).unwrap();
+    bench.assert_host_register("input", "#padx", 1);
+}
+
+#[test]
+fn rewind_limits() {
+    let mut bench = random_bench();
+    assert!(bench.vm().rewind_to(0).is_err());
+
+    bench.vm().enable_rewind(2, 2);
+    bench.run_cycles(10);
+    assert_eq!(bench.vm().earliest_cycle(), Some(8));
+    assert!(bench.vm().rewind_to(7).is_err());
+    assert!(bench.vm().rewind_to(11).is_err());
+    assert!(bench.vm().rewind_to(8).is_ok());
+}
+
+#[test]
+fn rewind_keeps_breakpoints() {
+    let mut bench = random_bench();
+    bench.vm().enable_rewind(5, 100);
+    bench.vm().add_breakpoint("x2", Location::Label("a".into()));
+    bench.vm().run_until_break(100);
+    bench.run_cycles(3);
+
+    bench.vm().rewind_to(1).unwrap();
+    assert_eq!(bench.vm().breakpoints().len(), 1);
+    assert!(bench.vm().run_until_break(100) != Break::CycleLimit);
+}
+
+#[test]
+fn rewind_custom_register() {
+    let mut bench = random_bench();
+    let clock = Arc::new(Mutex::new(Clock::default()));
+    bench.add_register("start", "#CLCK", clock.clone());
+    let _ = bench.exa("mark a\n addi x #clck x\n jump a\n");
+    bench.vm().enable_rewind(5, 100);
+    let mut states = vec![];
+    record(&mut bench, &mut states, 12);
+    let end = bench.save_state();
+    let ticks = clock.lock().unwrap().ticks;
+
+    bench.vm().rewind_to(3).unwrap();
+    assert!(bench.save_state() == states[3], "rewound state differs");
+    assert_eq!(clock.lock().unwrap().ticks, 2);
+
+    // Still the same register, so replaying ticks it the same way
+    bench.run_cycles(9);
+    assert!(bench.save_state() == end, "replayed state differs");
+    assert_eq!(clock.lock().unwrap().ticks, ticks);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::*;
+use exa::vm::scheduler::{Reverse, Scripted, Shuffle};
+use exa::vm::value::Value;
+
+// Whoever runs last leaves their number in #REG
+fn racing_bench() -> TestBench {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("copy 1 #reg\n copy 1 #reg\n copy 1 #reg\n noop\n");
+    let _ = bench.exa("copy 2 #reg\n copy 2 #reg\n copy 2 #reg\n noop\n");
+    let _ = bench.exa("copy 3 #reg\n copy 3 #reg\n copy 3 #reg\n noop\n");
+    bench
+}
+
+#[test]
+fn scheduler_spawn_order() {
+    let mut bench = racing_bench();
+    bench.run_cycle();
+    bench.assert_host_register("start", "#reg", 3);
+}
+
+#[test]
+fn scheduler_reverse() {
+    let mut bench = racing_bench();
+    bench.vm().scheduler = Box::new(Reverse);
+    bench.run_cycle();
+    bench.assert_host_register("start", "#reg", 1);
+}
+
+#[test]
+fn scheduler_scripted() {
+    let mut bench = racing_bench();
+    bench.vm().scheduler = Box::new(Scripted::new(&["x2", "x0"]).on_cycle(1, &["x2", "x1", "x0"]));
+
+    // Unnamed EXAs run last
+    bench.run_cycle();
+    bench.assert_host_register("start", "#reg", 2);
+    bench.run_cycle();
+    bench.assert_host_register("start", "#reg", 1);
+    bench.run_cycle();
+    bench.assert_host_register("start", "#reg", 2);
+}
+
+#[test]
+fn scheduler_shuffle_seeded() {
+    let mut seen: Vec<Value> = vec![];
+    for seed in 0..20 {
+        let mut b1 = racing_bench();
+        let mut b2 = racing_bench();
+        for bench in [&mut b1, &mut b2].iter_mut() {
+            bench.reseed(seed);
+            bench.vm().scheduler = Box::new(Shuffle);
+            bench.run_cycles(3);
+        }
+        b1.assert_same_state(&b2);
+        seen.push(b1.vm().hosts["start"].registers["#reg"].peek());
+    }
+    assert!(
+        seen.iter().any(|v| *v != seen[0]),
+        "shuffling never changed the order"
+    );
+}
+
+#[test]
+fn scheduler_survives_rewind() {
+    let mut bench = racing_bench();
+    bench.vm().enable_rewind(1, 10);
+    bench.vm().scheduler = Box::new(Scripted::new(&[]).on_cycle(1, &["x2", "x1"]));
+    bench.run_cycles(2);
+    bench.assert_host_register("start", "#reg", 1);
+
+    bench.vm().rewind_to(1).unwrap();
+    bench.run_cycle();
+    bench.assert_host_register("start", "#reg", 1);
+}
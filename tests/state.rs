@@ -0,0 +1,180 @@
+mod common;
+
+use std::error::Error;
+
+use common::*;
+use exa::vm::error::ExaError;
+use exa::vm::exa::Mode;
+use exa::vm::redshift::RedshiftButton;
+use exa::vm::register::HardwareRegister;
+use exa::vm::scheduler::SpawnOrder;
+use exa::vm::value::Value;
+use exa::vm::VM;
+
+/// Run both benches side by side, checking that they never diverge.
+fn run_in_lockstep(left: &mut TestBench, right: &mut TestBench, cycles: usize) {
+    for _ in 0..cycles {
+        left.run_cycle();
+        right.run_cycle();
+        left.assert_same_state(right);
+    }
+}
+
+#[test]
+fn state_round_trip() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("make\n copy 1 f\n copy 2 f\n link 800\n drop\n link -1\n noop\n");
+    let _ = bench.exa("copy 5 m\n copy 6 m\n halt\n");
+    let _ = bench.exa("noop\n copy m x\n copy m t\n addi x t #reg\n");
+    let _ = bench.exa_custom(
+        "noop\n mark a\n repl b\n jump a\n mark b\n noop\n",
+        "end",
+        Mode::Local,
+    );
+
+    bench.run_cycle();
+    bench.run_cycle();
+
+    let mut restored = bench.clone_via_state();
+    bench.assert_same_state(&restored);
+    run_in_lockstep(&mut bench, &mut restored, 10);
+}
+
+#[test]
+fn state_round_trip_frozen_and_blocked() {
+    let mut bench = TestBench::basic_vm();
+    let e1 = bench.exa("copy 1 m\n noop\n");
+    let e2 = bench.exa("noop\n noop\n copy m x\n noop\n");
+    let e3 = bench.exa("grab 999\n");
+
+    bench.run_cycle();
+    bench.assert_freezing_error(&e1);
+    bench.assert_fatal_error(&e3);
+
+    let mut restored = bench.clone_via_state();
+    let r1 = restored.get_exa("x0");
+    let r2 = restored.get_exa("x1");
+    let r3 = restored.get_exa("x2");
+    restored.assert_freezing_error(&r1);
+    restored.assert_fatal_error(&r3);
+
+    run_in_lockstep(&mut bench, &mut restored, 3);
+    bench.assert_exa_register(&e2, "x", 1);
+    restored.assert_exa_register(&r2, "x", 1);
+}
+
+/// Never has anything to read.
+#[derive(Debug)]
+struct Empty;
+
+impl HardwareRegister for Empty {
+    fn on_read(&mut self) -> Result<Value, Box<dyn Error>> {
+        Err(ExaError::Blocking("nothing to read".into()).into())
+    }
+
+    fn on_write(&mut self, _: Value) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn peek(&self) -> Value {
+        Value::Number(0)
+    }
+}
+
+#[test]
+fn state_round_trip_error_message() {
+    let mut bench = TestBench::basic_vm();
+    bench.add_register("start", "#EMPT", Empty);
+    let e1 = bench.exa("copy #empt x\n");
+
+    bench.run_cycle();
+    let error = ExaError::Blocking("nothing to read".into());
+    bench.assert_error(&e1, error.clone());
+
+    let restored = bench.clone_via_state();
+    restored.assert_error(&restored.get_exa("x0"), error);
+}
+
+#[test]
+fn state_round_trip_redshift() {
+    let mut bench = TestBench::redshift_vm();
+    let _ = bench
+        .exa("copy 1 co\n copy 301 gp\n addi gx 50 gx\n mark a\n addi gx 1 gx\n wait\n jump a\n");
+    let _ = bench.exa("copy 2 co\n copy 302 gp\n copy 52 gx\n mark a\n copy ci x\n jump a\n");
+    let _ = bench.exa("link 801\n copy 60 #sqr0\n copy 40 #nse0\n mark a\n noop\n jump a\n");
+
+    for _ in 0..4 {
+        bench.run_cycle();
+    }
+
+    let mut restored = bench.clone_via_state();
+    run_in_lockstep(&mut bench, &mut restored, 10);
+}
+
+#[test]
+fn state_shares_redshift_registers() {
+    let mut bench = TestBench::redshift_vm();
+    let _ = bench.exa("link 800\n mark a\n copy #padx x\n jump a\n");
+
+    bench.run_cycle();
+    let mut restored = bench.clone_via_state();
+    restored.input_pressed(RedshiftButton::Left);
+    restored.run_cycle();
+    restored.run_cycle();
+
+    let e1 = restored.get_exa("x0");
+    restored.assert_position(&e1, "input");
+    restored.assert_exa_register(&e1, "x", -1);
+}
+
+#[test]
+fn state_round_trip_repl_lineage() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("repl a\n repl a\n noop\n mark a\n noop\n noop\n");
+
+    bench.run_cycle();
+    let mut restored = bench.clone_via_state();
+    run_in_lockstep(&mut bench, &mut restored, 1);
+
+    // Spawn counters are shared by the lineage after a restore too
+    let _ = restored.get_exa("x0:1");
+    let _ = restored.get_exa("x0:2");
+}
+
+#[test]
+fn state_round_trip_image() {
+    let mut bench = TestBench::redshift_vm_from_image("./tests/golden.png".to_string());
+    let mut restored = bench.clone_via_state();
+    let r1 = restored.get_exa("AB");
+    let r2 = restored.get_exa("CD");
+    restored.assert_exa_local_mode(&r2);
+    restored.assert_exa_sprite(&r2, vec![0, 1, 8, 1, 80, 1, 8, 1]);
+
+    // Golden image EXAs share the shuffled order, so only compare a
+    // single cycle where order doesn't matter.
+    bench.run_cycle();
+    restored.run_cycle();
+    restored.assert_exa_register(&r1, "x", 1);
+}
+
+#[test]
+fn state_runs_on_another_thread() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("make\n copy 1 f\n link 800\n drop\n link -1\n noop\n");
+    let _ = bench.exa("copy 5 m\n noop\n");
+    let _ = bench.exa("noop\n copy m x\n addi x 1 #reg\n");
+
+    let mut vm = VM::load_state(&bench.save_state()).expect("failed to load state");
+    vm.scheduler = Box::new(SpawnOrder);
+    let moved = std::thread::spawn(move || {
+        for _ in 0..6 {
+            vm.run_cycle();
+        }
+        vm.save_state()
+    })
+    .join()
+    .unwrap();
+
+    bench.run_cycles(6);
+    assert!(bench.save_state() == moved, "save states differ");
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use common::*;
+use exa::vm::error::ExaError;
+use exa::vm::terminated::{Cause, Terminated};
+use exa::vm::value::Value;
+
+fn register(entry: &Terminated, name: &str) -> Value {
+    entry
+        .registers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.clone())
+        .unwrap()
+}
+
+#[test]
+fn terminated_halt() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("noop\n copy 5 x\n halt\n noop\n");
+
+    bench.run_cycles(3);
+    assert!(bench.vm().find_terminated("x0").is_none());
+    bench.run_cycle();
+
+    let vm = bench.vm();
+    let entry = vm.find_terminated("x0").unwrap();
+    assert_eq!(entry.base_name, "x0");
+    assert_eq!(entry.spawn_id, 0);
+    assert_eq!(entry.host, "start");
+    assert_eq!(entry.cycle, 2);
+    assert_eq!(entry.pc, 2);
+    assert_eq!(entry.line, Some(3));
+    assert_eq!(entry.cause, Cause::Halted);
+    assert_eq!(register(entry, "X"), Value::from(5));
+}
+
+#[test]
+fn terminated_out_of_instructions() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("noop\n copy 1 t\n");
+
+    bench.run_cycles(3);
+    let vm = bench.vm();
+    let entry = vm.find_terminated("x0").unwrap();
+    assert_eq!(entry.cause, Cause::OutOfInstructions);
+    assert_eq!(entry.cycle, 1);
+    assert_eq!(register(entry, "T"), Value::from(1));
+}
+
+#[test]
+fn terminated_killed() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa("noop\n noop\n noop\n");
+    let _ = bench.exa("kill\n noop\n noop\n");
+
+    bench.run_cycles(2);
+    let vm = bench.vm();
+    let entry = vm.find_terminated("x0").unwrap();
+    assert_eq!(entry.cause, Cause::Killed { by: "x1".into() });
+    assert_eq!(entry.cycle, 0);
+    assert!(vm.find_terminated("x1").is_none());
+}
+
+#[test]
+fn terminated_fault_lineage() {
+    let mut bench = TestBench::basic_vm();
+    let _ = bench.exa(
+        "noop\n repl child\n copy 3 x\n noop\n noop\n noop\n \
+         mark child\n copy 7 x\n divi x 0 x\n noop\n",
+    );
+
+    bench.run_cycles(5);
+    let vm = bench.vm();
+    let entry = vm.find_terminated("x0:1").unwrap();
+    assert_eq!(entry.base_name, "x0");
+    assert_eq!(entry.spawn_id, 1);
+    assert_eq!(entry.cycle, 3);
+    assert_eq!(entry.cause, Cause::Fault(ExaError::DivideByZero));
+    assert_eq!(register(entry, "X"), Value::from(7));
+    assert_eq!(vm.terminated().count(), 1);
+}
+
+#[test]
+fn terminated_capacity() {
+    let mut bench = TestBench::basic_vm();
+    bench.vm().set_terminated_capacity(2);
+    let _ = bench.exa("halt\n");
+    let _ = bench.exa("noop\n halt\n");
+    let _ = bench.exa("noop\n noop\n halt\n");
+
+    bench.run_cycles(4);
+    let names: Vec<String> = bench.vm().terminated().map(|t| t.name.clone()).collect();
+    assert_eq!(names, vec!["x1", "x2"]);
+
+    bench.vm().set_terminated_capacity(1);
+    let names: Vec<String> = bench.vm().terminated().map(|t| t.name.clone()).collect();
+    assert_eq!(names, vec!["x2"]);
+}
+
+#[test]
+fn terminated_rewind() {
+    let mut bench = TestBench::basic_vm();
+    bench.vm().enable_rewind(2, 100);
+    let _ = bench.exa("noop\n halt\n");
+    let _ = bench.exa("noop\n noop\n noop\n halt\n");
+
+    bench.run_cycles(5);
+    assert_eq!(bench.vm().terminated().count(), 2);
+
+    bench.vm().rewind_to(3).unwrap();
+    let names: Vec<String> = bench.vm().terminated().map(|t| t.name.clone()).collect();
+    assert_eq!(names, vec!["x0"]);
+
+    bench.run_cycles(2);
+    assert_eq!(bench.vm().terminated().count(), 2);
+}
@@ -0,0 +1,250 @@
+mod common;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use exa::vm::exa::Mode;
+use exa::vm::instruction::{Instruction, Target};
+use exa::vm::trace::{write_json_lines, Bus, Event, JsonLines, MemoryTrace, TraceEvent};
+use exa::vm::value::Value;
+
+fn traced(bench: &TestBench) -> MemoryTrace {
+    let trace = MemoryTrace::new();
+    bench.vm().set_trace(Box::new(trace.clone()));
+    trace
+}
+
+/// Everything but the instruction events, which most tests don't care about.
+fn actions(trace: &MemoryTrace) -> Vec<(u32, String, usize, Event)> {
+    trace
+        .events()
+        .iter()
+        .filter(|e| !matches!(e.event, Event::Instruction { .. }))
+        .map(|e| (e.cycle, e.exa.clone(), e.pc, e.event.clone()))
+        .collect()
+}
+
+#[test]
+fn trace_instructions() {
+    let mut bench = TestBench::basic_vm();
+    let trace = traced(&bench);
+    let _ = bench.exa("copy 800 x\n link x\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+
+    let expected = vec![
+        TraceEvent {
+            cycle: 0,
+            exa: "x0".into(),
+            pc: 0,
+            event: Event::Instruction {
+                instruction: Instruction::Copy(Target::Literal(800), Target::Register("x".into())),
+            },
+        },
+        TraceEvent {
+            cycle: 1,
+            exa: "x0".into(),
+            pc: 1,
+            event: Event::Instruction {
+                instruction: Instruction::Link(Target::Register("x".into())),
+            },
+        },
+        TraceEvent {
+            cycle: 1,
+            exa: "x0".into(),
+            pc: 1,
+            event: Event::Link {
+                link: 800,
+                from: "start".into(),
+                to: "end".into(),
+            },
+        },
+    ];
+    assert_eq!(*trace.events(), expected);
+
+    bench.run_cycle();
+    assert_eq!(
+        actions(&trace)[1],
+        (
+            2,
+            "x0".into(),
+            2,
+            Event::Fatal {
+                reason: "out of instructions".into()
+            }
+        )
+    );
+}
+
+#[test]
+fn trace_global_bus() {
+    let mut bench = TestBench::basic_vm();
+    let trace = traced(&bench);
+    let _ = bench.exa("copy 5 m\n noop\n noop\n");
+    let _ = bench.exa("copy m x\n noop\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+
+    let expected = vec![
+        (
+            0,
+            "x0".into(),
+            0,
+            Event::Send {
+                bus: Bus::Global,
+                value: Value::Number(5),
+            },
+        ),
+        (
+            0,
+            "x0".into(),
+            0,
+            Event::Frozen {
+                reason: "bus write successful, freezing until it is read".into(),
+            },
+        ),
+        (
+            0,
+            "x1".into(),
+            0,
+            Event::Blocked {
+                reason: "no messages available to read".into(),
+            },
+        ),
+        (
+            1,
+            "x1".into(),
+            0,
+            Event::Receive {
+                bus: Bus::Global,
+                sender: "x0".into(),
+                value: Value::Number(5),
+            },
+        ),
+    ];
+    assert_eq!(actions(&trace), expected);
+}
+
+#[test]
+fn trace_local_bus() {
+    let mut bench = TestBench::basic_vm();
+    let trace = traced(&bench);
+    let _ = bench.exa_custom("copy 'HI' m\n noop\n noop\n", "start", Mode::Local);
+
+    bench.run_cycle();
+
+    assert_eq!(
+        actions(&trace)[0].3,
+        Event::Send {
+            bus: Bus::Host("start".into()),
+            value: Value::Keyword("HI".into()),
+        }
+    );
+}
+
+#[test]
+fn trace_files() {
+    let mut bench = TestBench::basic_vm();
+    let trace = traced(&bench);
+    let _ = bench.exa("make\n drop\n grab 400\n wipe\n noop\n");
+
+    bench.run_cycles(4);
+
+    let expected = vec![
+        (1, "x0".into(), 1, Event::Drop { file: 400 }),
+        (2, "x0".into(), 2, Event::Grab { file: 400 }),
+        (3, "x0".into(), 3, Event::Wipe { file: 400 }),
+    ];
+    assert_eq!(actions(&trace), expected);
+}
+
+#[test]
+fn trace_repl_and_kill() {
+    let mut bench = TestBench::basic_vm();
+    let trace = traced(&bench);
+    let _ = bench.exa("repl a\n kill\n noop\n noop\n mark a\n noop\n noop\n");
+
+    bench.run_cycle();
+    bench.run_cycle();
+
+    let expected = vec![
+        (
+            0,
+            "x0".into(),
+            0,
+            Event::Repl {
+                child: "x0:1".into(),
+            },
+        ),
+        (
+            1,
+            "x0".into(),
+            1,
+            Event::Kill {
+                target: Some("x0:1".into()),
+            },
+        ),
+    ];
+    assert_eq!(actions(&trace), expected);
+}
+
+#[test]
+fn trace_stop() {
+    let mut bench = TestBench::basic_vm();
+    let trace = traced(&bench);
+    let _ = bench.exa("noop\n noop\n noop\n");
+
+    bench.run_cycle();
+    assert!(bench.vm().take_trace().is_some());
+    bench.run_cycle();
+
+    assert_eq!(trace.events().len(), 1);
+    assert!(!bench.vm().is_tracing());
+}
+
+#[derive(Clone, Debug, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn trace_json_lines() {
+    let mut bench = TestBench::basic_vm();
+    let trace = traced(&bench);
+    let buffer = SharedBuffer::default();
+    let _ = bench.exa("copy 1 m\n noop\n noop\n");
+    let _ = bench.exa("noop\n copy m x\n noop\n");
+
+    // An identical run, streamed straight to JSON Lines
+    let mut both = TestBench::basic_vm();
+    both.vm()
+        .set_trace(Box::new(JsonLines::new(buffer.clone())));
+    let _ = both.exa("copy 1 m\n noop\n noop\n");
+    let _ = both.exa("noop\n copy m x\n noop\n");
+
+    bench.run_cycles(3);
+    both.run_cycles(3);
+
+    let mut written = vec![];
+    write_json_lines(&trace.events(), &mut written).unwrap();
+    assert_eq!(written, *buffer.0.lock().unwrap());
+
+    let text = String::from_utf8(written).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), trace.events().len());
+    assert_eq!(
+        lines[1],
+        r#"{"cycle":0,"exa":"x0","pc":0,"event":"send","bus":"global","value":1}"#
+    );
+}